@@ -0,0 +1,154 @@
+use log::{debug, info};
+use std::path::PathBuf;
+
+use futures::stream::{self, StreamExt};
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use crate::capture::{capture_frontmatter, fetch_capture, Capture};
+use crate::error;
+use crate::{create_markdown_file, Config};
+
+/// The `batch:` config section. Request rates are limited separately by `http.rate_limits`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct BatchConfig {
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+}
+
+fn default_concurrency() -> usize { 4 }
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        BatchConfig { concurrency: default_concurrency() }
+    }
+}
+
+#[derive(Debug)]
+pub struct BatchResult {
+    pub index: usize,
+    pub url: String,
+    pub outcome: Result<PathBuf, eyre::Report>,
+}
+
+/// One URL per line; blank lines, `#` comments and repeated URLs are skipped.
+pub fn parse_url_list(text: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        if !urls.iter().any(|url| url == line) {
+            urls.push(line.to_string());
+        }
+    }
+    urls
+}
+
+pub(crate) async fn process_capture(capture: &Capture, config: &Config, write_lock: &Mutex<()>) -> eyre::Result<PathBuf> {
+    let (note, naming) = fetch_capture(capture, config).await?;
+    // Fetches overlap, but writes are serialized so collision handling sees every earlier note
+    let _guard = write_lock.lock().await;
+    create_markdown_file(&note, &config.vault, &capture_frontmatter(&config.frontmatter, capture), &naming).await
+}
+
+/// Runs [`crate::handle_url`] over `urls` with at most `concurrency` in flight, calling
+/// `progress` as each finishes. Results come back in input order regardless of completion order.
+pub async fn run_batch<F>(urls: &[String], config: &Config, concurrency: usize, progress: F) -> Vec<BatchResult>
+where
+    F: FnMut(usize, usize, &BatchResult),
+{
+    let captures = urls.iter().map(|url| Capture::new(url)).collect::<Vec<_>>();
+    run_captures(&captures, config, concurrency, progress).await
+}
+
+/// Like [`run_batch`], but for captures that carry their own title, tags, folder or date.
+pub async fn run_captures<F>(captures: &[Capture], config: &Config, concurrency: usize, mut progress: F) -> Vec<BatchResult>
+where
+    F: FnMut(usize, usize, &BatchResult),
+{
+    debug!("run_captures: captures.len={} concurrency={}", captures.len(), concurrency);
+    let write_lock = Mutex::new(());
+    let mut results = Vec::with_capacity(captures.len());
+    let mut pending = stream::iter(captures.iter().enumerate())
+        .map(|(index, capture)| {
+            let write_lock = &write_lock;
+            async move {
+                let outcome = process_capture(capture, config, write_lock).await;
+                BatchResult { index, url: capture.url.clone(), outcome }
+            }
+        })
+        .buffer_unordered(concurrency.max(1));
+    while let Some(result) = pending.next().await {
+        progress(results.len() + 1, captures.len(), &result);
+        results.push(result);
+    }
+    results.sort_by_key(|result| result.index);
+    info!("Batch finished: {} of {} succeeded", results.iter().filter(|r| r.outcome.is_ok()).count(), captures.len());
+    results
+}
+
+/// Tab-separated summary in input order: `created <path> <url>` or `failed <kind> <url> <message>`.
+pub fn render_summary(results: &[BatchResult]) -> String {
+    let mut summary = String::new();
+    for result in results {
+        match &result.outcome {
+            Ok(path) => summary += &format!("created\t{}\t{}\n", path.display(), result.url),
+            Err(report) => {
+                let kind = error::classify(report).map_or("unknown", |error| error.kind());
+                summary += &format!("failed\t{}\t{}\t{}\n", kind, result.url, report);
+            }
+        }
+    }
+    let failed = results.iter().filter(|result| result.outcome.is_err()).count();
+    summary += &format!("{} created, {} failed\n", results.len() - failed, failed);
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{fixture, mock_config};
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_parse_url_list() {
+        let text = "# inbox\nhttps://a.example/1\n\n  https://b.example/2  \nhttps://a.example/1\n";
+        assert_eq!(parse_url_list(text), vec!["https://a.example/1", "https://b.example/2"]);
+    }
+
+    #[tokio::test]
+    async fn test_run_batch_keeps_input_order() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/videos"))
+            .and(query_param("id", "missing0000"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(fixture("youtube_empty.json"), "application/json"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/videos"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(fixture("youtube_video.json"), "application/json"))
+            .mount(&server)
+            .await;
+        let vault = tempfile::tempdir().unwrap();
+        let config = mock_config(&server, vault.path());
+        let urls = parse_url_list(
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ\nhttps://www.youtube.com/watch?v=missing0000\nhttps://www.youtube.com/watch?v=9bZkp7q19f0\n"
+        );
+
+        let mut seen = Vec::new();
+        let results = run_batch(&urls, &config, 3, |done, total, result| seen.push((done, total, result.index))).await;
+
+        assert_eq!(results.iter().map(|r| r.index).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(seen.iter().map(|(done, total, _)| (*done, *total)).collect::<Vec<_>>(), vec![(1, 3), (2, 3), (3, 3)]);
+        let paths = results.iter().filter_map(|r| r.outcome.as_ref().ok()).collect::<Vec<_>>();
+        assert_eq!(paths.len(), 2);
+        assert_ne!(paths[0], paths[1], "same-titled videos must not overwrite each other");
+
+        let summary = render_summary(&results);
+        let lines = summary.lines().collect::<Vec<_>>();
+        assert!(lines[0].starts_with("created\t"));
+        assert!(lines[1].starts_with("failed\tmetadata_missing\thttps://www.youtube.com/watch?v=missing0000"));
+        assert!(lines[2].starts_with("created\t"));
+        assert_eq!(lines[3], "2 created, 1 failed");
+    }
+}
@@ -0,0 +1,272 @@
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use eyre::{eyre, Result};
+use rusqlite::{Connection, OpenFlags};
+use serde::Deserialize;
+use tempfile::TempDir;
+
+use crate::import::Bookmark;
+
+/// Seconds between the Windows epoch (1601-01-01) Chromium counts from and the Unix epoch.
+const WINDOWS_EPOCH_OFFSET_SECS: i64 = 11_644_473_600;
+
+const FIREFOX_ROOTS: &[(&str, &str)] = &[
+    ("menu________", "Bookmarks Menu"),
+    ("toolbar_____", "Bookmarks Toolbar"),
+    ("unfiled_____", "Other Bookmarks"),
+    ("mobile______", "Mobile Bookmarks"),
+];
+const FIREFOX_TAGS_ROOT: &str = "tags________";
+const MAX_FOLDER_DEPTH: usize = 64;
+
+/// Which Firefox history entries to import besides bookmarks.
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+    pub min_visits: u32,
+    pub since: Option<DateTime<Utc>>,
+}
+
+/// A copy of a browser database in the temp directory, removed on drop. Browsers keep
+/// their databases locked (and recent writes in the `-wal` file) while running.
+struct DatabaseCopy {
+    // Created 0700, so other users cannot read the copied history
+    _dir: TempDir,
+    path: PathBuf,
+}
+
+impl DatabaseCopy {
+    fn new(source: &Path) -> Result<Self> {
+        debug!("DatabaseCopy::new: source={}", source.display());
+        let file_name = source.file_name().ok_or_else(|| eyre!("Invalid database path: {}", source.display()))?;
+        let dir = tempfile::Builder::new().prefix(env!("CARGO_PKG_NAME")).tempdir()?;
+        let copy = DatabaseCopy { path: dir.path().join(file_name), _dir: dir };
+        fs::copy(source, &copy.path).map_err(|e| eyre!("Failed to copy {}: {}", source.display(), e))?;
+        for suffix in ["-wal", "-shm"] {
+            let sidecar = PathBuf::from(format!("{}{}", source.display(), suffix));
+            if sidecar.exists() {
+                fs::copy(&sidecar, PathBuf::from(format!("{}{}", copy.path.display(), suffix)))?;
+            }
+        }
+        Ok(copy)
+    }
+}
+
+fn from_micros(micros: Option<i64>) -> Option<DateTime<Utc>> {
+    micros.filter(|micros| *micros > 0).and_then(DateTime::from_timestamp_micros)
+}
+
+/// `places.sqlite` itself, or the one inside a Firefox profile directory.
+pub fn firefox_places(path: &Path) -> PathBuf {
+    if path.is_dir() { path.join("places.sqlite") } else { path.to_path_buf() }
+}
+
+/// `Bookmarks` itself, or the one inside a Chromium profile directory.
+pub fn chromium_bookmarks(path: &Path) -> PathBuf {
+    if path.is_dir() { path.join("Bookmarks") } else { path.to_path_buf() }
+}
+
+struct Folder {
+    parent: i64,
+    title: String,
+    guid: String,
+}
+
+// Folder titles from the bookmark up to (not including) a root; None for folders under the tags root
+fn folder_path(folders: &HashMap<i64, Folder>, mut id: i64) -> Option<Vec<String>> {
+    let mut path = Vec::new();
+    while let Some(folder) = folders.get(&id) {
+        // A corrupt database can hold a parent cycle
+        if path.len() >= MAX_FOLDER_DEPTH {
+            warn!("Folder nesting deeper than {} levels, stopping at {}", MAX_FOLDER_DEPTH, folder.title);
+            break;
+        }
+        if folder.guid == FIREFOX_TAGS_ROOT {
+            return None;
+        }
+        if folder.guid == "root________" {
+            break;
+        }
+        let title = FIREFOX_ROOTS.iter().find(|(guid, _)| *guid == folder.guid).map_or(folder.title.as_str(), |(_, title)| title);
+        path.push(title.to_string());
+        id = folder.parent;
+    }
+    path.reverse();
+    Some(path)
+}
+
+/// Reads bookmarks (with Firefox tags) and, if `history` is given, visited pages from a copy
+/// of `places.sqlite`.
+pub fn read_firefox(path: &Path, history: Option<&HistoryFilter>) -> Result<Vec<Bookmark>> {
+    let places = firefox_places(path);
+    debug!("read_firefox: places={} history={:?}", places.display(), history);
+    let copy = DatabaseCopy::new(&places)?;
+    let db = Connection::open_with_flags(&copy.path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| eyre!("Failed to open {}: {}", places.display(), e))?;
+
+    let mut folders = HashMap::new();
+    let mut statement = db.prepare("SELECT id, parent, COALESCE(title, ''), COALESCE(guid, '') FROM moz_bookmarks WHERE type = 2")?;
+    let rows = statement.query_map([], |row| Ok((row.get(0)?, Folder { parent: row.get(1)?, title: row.get(2)?, guid: row.get(3)? })))?;
+    for row in rows {
+        let (id, folder) = row?;
+        folders.insert(id, folder);
+    }
+
+    let mut bookmarks: Vec<Bookmark> = Vec::new();
+    let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+    let mut statement = db.prepare(
+        "SELECT p.url, b.title, b.parent, b.dateAdded FROM moz_bookmarks b JOIN moz_places p ON p.id = b.fk \
+         WHERE b.type = 1 ORDER BY b.parent, b.position",
+    )?;
+    let rows = statement.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?, row.get::<_, i64>(2)?, row.get::<_, Option<i64>>(3)?)))?;
+    for row in rows {
+        let (url, title, parent, added) = row?;
+        match folder_path(&folders, parent) {
+            Some(path) => bookmarks.push(Bookmark { url, title: title.filter(|title| !title.is_empty()), folders: path, added: from_micros(added), ..Bookmark::default() }),
+            // Tagging a URL in Firefox files a bookmark under a folder named after the tag
+            None => tags.entry(url).or_default().extend(folders.get(&parent).map(|folder| folder.title.clone())),
+        }
+    }
+    for bookmark in &mut bookmarks {
+        bookmark.tags = tags.get(&bookmark.url).cloned().unwrap_or_default();
+    }
+
+    if let Some(filter) = history {
+        let since = filter.since.map_or(0, |since| since.timestamp_micros());
+        let mut statement = db.prepare(
+            "SELECT url, title, last_visit_date FROM moz_places \
+             WHERE hidden = 0 AND visit_count >= ?1 AND COALESCE(last_visit_date, 0) >= ?2 ORDER BY last_visit_date DESC",
+        )?;
+        let rows = statement.query_map(rusqlite::params![filter.min_visits.max(1), since], |row| {
+            Ok(Bookmark { url: row.get(0)?, title: row.get(1)?, added: from_micros(row.get(2)?), ..Bookmark::default() })
+        })?;
+        for row in rows {
+            bookmarks.push(row?);
+        }
+    }
+    Ok(bookmarks)
+}
+
+#[derive(Deserialize, Debug)]
+struct ChromiumFile {
+    roots: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChromiumNode {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    url: Option<String>,
+    date_added: Option<String>,
+    #[serde(default)]
+    children: Vec<ChromiumNode>,
+}
+
+fn from_webkit(value: Option<&str>) -> Option<DateTime<Utc>> {
+    let micros = value?.parse::<i64>().ok().filter(|micros| *micros > 0)?;
+    DateTime::from_timestamp_micros(micros - WINDOWS_EPOCH_OFFSET_SECS * 1_000_000)
+}
+
+fn walk_chromium(node: &ChromiumNode, path: &mut Vec<String>, bookmarks: &mut Vec<Bookmark>) {
+    match (node.kind.as_str(), &node.url) {
+        ("url", Some(url)) => bookmarks.push(Bookmark {
+            url: url.clone(),
+            title: Some(node.name.clone()).filter(|name| !name.is_empty()),
+            folders: path.clone(),
+            added: from_webkit(node.date_added.as_deref()),
+            ..Bookmark::default()
+        }),
+        ("folder", _) => {
+            path.push(node.name.clone());
+            for child in &node.children {
+                walk_chromium(child, path, bookmarks);
+            }
+            path.pop();
+        }
+        _ => {}
+    }
+}
+
+/// Parses the contents of Chromium's `Bookmarks` file (also used by Chrome, Edge and Brave).
+pub fn parse_chromium(json: &str) -> Result<Vec<Bookmark>> {
+    let file: ChromiumFile = serde_json::from_str(json).map_err(|e| eyre!("Not a Chromium Bookmarks file: {}", e))?;
+    let mut bookmarks = Vec::new();
+    let mut roots = file.roots.into_iter().collect::<Vec<_>>();
+    roots.sort_by(|(a, _), (b, _)| a.cmp(b));
+    // `roots` also holds bookkeeping entries such as `sync_transaction_version`
+    for node in roots.into_iter().filter_map(|(_, value)| serde_json::from_value::<ChromiumNode>(value).ok()) {
+        walk_chromium(&node, &mut Vec::new(), &mut bookmarks);
+    }
+    Ok(bookmarks)
+}
+
+pub fn read_chromium(path: &Path) -> Result<Vec<Bookmark>> {
+    let file = chromium_bookmarks(path);
+    debug!("read_chromium: file={}", file.display());
+    parse_chromium(&fs::read_to_string(&file).map_err(|e| eyre!("Failed to read {}: {}", file.display(), e))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_folder_path_stops_on_cycles() {
+        let folder = |parent, title: &str| Folder { parent, title: title.to_string(), guid: String::new() };
+        let folders = HashMap::from([(1, folder(2, "a")), (2, folder(1, "b"))]);
+        assert_eq!(folder_path(&folders, 1).unwrap().len(), MAX_FOLDER_DEPTH);
+    }
+
+    #[test]
+    fn test_parse_chromium_folders_and_dates() {
+        let json = r#"{"checksum": "x", "roots": {
+            "bookmark_bar": {"type": "folder", "name": "Bookmarks bar", "children": [
+                {"type": "folder", "name": "Rust", "children": [
+                    {"type": "url", "name": "The Book", "url": "https://doc.rust-lang.org/book/", "date_added": "13244365800000000"}
+                ]}
+            ]},
+            "other": {"type": "folder", "name": "Other bookmarks", "children": [{"type": "url", "name": "", "url": "https://example.com/"}]},
+            "sync_transaction_version": "1"
+        }, "version": 1}"#;
+        let bookmarks = parse_chromium(json).unwrap();
+        assert_eq!(bookmarks.len(), 2);
+        assert_eq!(bookmarks[0].folders, vec!["Bookmarks bar", "Rust"]);
+        assert_eq!(bookmarks[0].added, DateTime::from_timestamp(1_599_892_200, 0));
+        assert_eq!(bookmarks[1].title, None);
+    }
+
+    #[test]
+    fn test_read_firefox_bookmarks_tags_and_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Connection::open(dir.path().join("places.sqlite")).unwrap();
+        db.execute_batch(
+            "CREATE TABLE moz_places (id INTEGER PRIMARY KEY, url TEXT, title TEXT, visit_count INTEGER, hidden INTEGER, last_visit_date INTEGER);
+             CREATE TABLE moz_bookmarks (id INTEGER PRIMARY KEY, type INTEGER, fk INTEGER, parent INTEGER, position INTEGER, title TEXT, dateAdded INTEGER, guid TEXT);
+             INSERT INTO moz_places VALUES (1, 'https://a.example/', 'A page', 1, 0, 1600000000000000),
+                                           (2, 'https://b.example/', 'B page', 5, 0, 1700000000000000);
+             INSERT INTO moz_bookmarks VALUES (1, 2, NULL, 0, 0, '', NULL, 'root________'),
+                                              (2, 2, NULL, 1, 0, 'toolbar', NULL, 'toolbar_____'),
+                                              (3, 2, NULL, 2, 0, 'Reading', NULL, 'folder000001'),
+                                              (4, 1, 1, 3, 0, 'A bookmark', 1500000000000000, 'bookmark0001'),
+                                              (5, 2, NULL, 1, 1, 'tags', NULL, 'tags________'),
+                                              (6, 2, NULL, 5, 0, 'later', NULL, 'tagfolder001'),
+                                              (7, 1, 1, 6, 0, NULL, NULL, 'tagentry0001');",
+        ).unwrap();
+        drop(db);
+
+        let bookmarks = read_firefox(dir.path(), None).unwrap();
+        assert_eq!(bookmarks.len(), 1);
+        assert_eq!(bookmarks[0].folders, vec!["Bookmarks Toolbar", "Reading"]);
+        assert_eq!(bookmarks[0].tags, vec!["later"]);
+        assert_eq!(bookmarks[0].added, DateTime::from_timestamp(1_500_000_000, 0));
+
+        let filter = HistoryFilter { min_visits: 2, since: DateTime::from_timestamp(1_650_000_000, 0) };
+        let with_history = read_firefox(&dir.path().join("places.sqlite"), Some(&filter)).unwrap();
+        assert_eq!(with_history.iter().map(|bookmark| bookmark.url.as_str()).collect::<Vec<_>>(), vec!["https://a.example/", "https://b.example/"]);
+    }
+}
@@ -0,0 +1,251 @@
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use eyre::{eyre, Result};
+use reqwest::Url;
+use serde::{Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::vault;
+
+/// Query parameters that carry credentials; they are left out of cache keys so rotating a key keeps the cache.
+const SECRET_PARAMS: &[&str] = &["key", "api_key", "apikey", "access_token"];
+/// Providers whose URLs carry [`SECRET_PARAMS`] as credentials. Elsewhere they are ordinary parameters.
+const CREDENTIAL_PROVIDERS: &[&str] = &["youtube", "llm"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum CacheMode {
+    #[default]
+    Normal,
+    /// `--no-cache`: neither read nor write entries.
+    Disabled,
+    /// `--refresh`: ignore existing entries but store fresh responses.
+    Refresh,
+}
+
+/// The `cache:` config section. `ttl_hours` is keyed by provider (`youtube`, `llm`,
+/// `wikipedia`, ...); providers without an entry use `default_ttl_hours`, and a TTL of 0
+/// turns caching off for that provider.
+#[derive(Deserialize, Debug, Clone)]
+pub struct CacheConfig {
+    pub dir: Option<PathBuf>,
+    #[serde(default = "default_ttl_hours")]
+    pub default_ttl_hours: u64,
+    #[serde(default = "default_provider_ttls", deserialize_with = "merge_provider_ttls")]
+    pub ttl_hours: HashMap<String, u64>,
+    #[serde(skip)]
+    pub mode: CacheMode,
+}
+
+fn default_ttl_hours() -> u64 { 24 }
+
+fn default_provider_ttls() -> HashMap<String, u64> {
+    HashMap::from([("youtube".to_string(), 24 * 7), ("llm".to_string(), 24 * 30)])
+}
+
+// Entries from the config file override the built-in TTLs instead of replacing them all
+fn merge_provider_ttls<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<String, u64>, D::Error> {
+    let mut ttls = default_provider_ttls();
+    ttls.extend(HashMap::<String, u64>::deserialize(deserializer)?);
+    Ok(ttls)
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        serde_yaml::from_str("{}").expect("defaults deserialize")
+    }
+}
+
+impl CacheConfig {
+    /// `dir` if set, otherwise `$XDG_CACHE_HOME/obsidian-link` (or the platform equivalent).
+    pub fn resolved_dir(&self) -> Option<PathBuf> {
+        match &self.dir {
+            Some(dir) => dir.to_str().and_then(|dir| crate::expanduser(dir).ok()),
+            None => dirs::cache_dir().map(|dir| dir.join(env!("CARGO_PKG_NAME"))),
+        }
+    }
+
+    fn ttl_secs(&self, provider: &str) -> i64 {
+        let hours = self.ttl_hours.get(provider).copied().unwrap_or(self.default_ttl_hours);
+        (hours * 3600) as i64
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CacheEntry {
+    pub provider: String,
+    pub stored_at: i64,
+    pub status: u16,
+    pub content_type: String,
+    pub body: String,
+}
+
+/// Hex SHA-256 of the key parts, used as the entry's file name.
+pub fn cache_key(parts: &[&str]) -> String {
+    let digest = Sha256::digest(parts.join("\0").as_bytes());
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Drops the fragment (and, for API providers, credential parameters) and sorts the query so equivalent URLs share an entry.
+pub fn normalize_url(provider: &str, url: &str) -> String {
+    let Ok(mut parsed) = Url::parse(url) else {
+        return url.trim().to_string();
+    };
+    parsed.set_fragment(None);
+    let strip_secrets = CREDENTIAL_PROVIDERS.contains(&provider);
+    let mut pairs = parsed.query_pairs()
+        .filter(|(name, _)| !(strip_secrets && SECRET_PARAMS.contains(&name.as_ref())))
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect::<Vec<_>>();
+    pairs.sort();
+    if pairs.is_empty() {
+        parsed.set_query(None);
+    } else {
+        parsed.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    parsed.to_string()
+}
+
+#[derive(Debug)]
+pub struct Cache {
+    dir: PathBuf,
+    config: CacheConfig,
+}
+
+impl Cache {
+    /// Returns `None` when caching is disabled or no cache directory can be determined.
+    pub fn open(config: &CacheConfig) -> Option<Cache> {
+        if config.mode == CacheMode::Disabled {
+            return None;
+        }
+        let dir = config.resolved_dir()?;
+        debug!("Cache::open: dir={}", dir.display());
+        Some(Cache { dir, config: config.clone() })
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(&key[..2]).join(format!("{}.json", key))
+    }
+
+    pub fn get(&self, provider: &str, key: &str) -> Option<CacheEntry> {
+        if self.config.mode == CacheMode::Refresh {
+            return None;
+        }
+        let entry: CacheEntry = serde_json::from_str(&fs::read_to_string(self.entry_path(key)).ok()?).ok()?;
+        if Utc::now().timestamp() - entry.stored_at >= self.config.ttl_secs(provider) {
+            debug!("Cache entry {} for {} has expired", key, provider);
+            return None;
+        }
+        debug!("Cache hit {} for {}", key, provider);
+        Some(entry)
+    }
+
+    pub fn put(&self, key: &str, entry: &CacheEntry) {
+        if self.config.ttl_secs(&entry.provider) == 0 {
+            return;
+        }
+        let result = (|| -> Result<()> {
+            fs::create_dir_all(&self.dir)?;
+            let root = self.dir.canonicalize()?;
+            vault::write_atomic(&root, &root.join(&key[..2]).join(format!("{}.json", key)), &serde_json::to_vec(entry)?)
+        })();
+        if let Err(e) = result {
+            warn!("Failed to write cache entry for {}: {}", entry.provider, e);
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct PruneStats {
+    pub removed: usize,
+    pub kept: usize,
+}
+
+/// Deletes expired and unreadable entries from the cache directory.
+pub fn prune(config: &CacheConfig) -> Result<PruneStats> {
+    let dir = config.resolved_dir().ok_or_else(|| eyre!("No cache directory could be determined"))?;
+    debug!("prune: dir={}", dir.display());
+    let mut stats = PruneStats::default();
+    if !dir.exists() {
+        return Ok(stats);
+    }
+    let now = Utc::now().timestamp();
+    for shard in fs::read_dir(&dir)?.filter_map(|entry| entry.ok()).map(|entry| entry.path()).filter(|path| path.is_dir()) {
+        for path in fs::read_dir(&shard)?.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+            let expired = read_entry(&path).is_none_or(|entry| now - entry.stored_at >= config.ttl_secs(&entry.provider));
+            if expired {
+                fs::remove_file(&path)?;
+                stats.removed += 1;
+            } else {
+                stats.kept += 1;
+            }
+        }
+        let _ = fs::remove_dir(&shard);
+    }
+    Ok(stats)
+}
+
+fn read_entry(path: &Path) -> Option<CacheEntry> {
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_cache(dir: &Path, mode: CacheMode) -> Cache {
+        let config = CacheConfig { dir: Some(dir.to_path_buf()), mode, ..CacheConfig::default() };
+        Cache::open(&config).unwrap()
+    }
+
+    fn entry(provider: &str, stored_at: i64) -> CacheEntry {
+        CacheEntry { provider: provider.to_string(), stored_at, status: 200, content_type: "application/json".to_string(), body: "{}".to_string() }
+    }
+
+    #[test]
+    fn test_normalize_url_ignores_fragment_order_and_keys() {
+        assert_eq!(
+            normalize_url("youtube", "https://www.googleapis.com/youtube/v3/videos?part=snippet&id=abc&key=secret#top"),
+            normalize_url("youtube", "https://www.googleapis.com/youtube/v3/videos?id=abc&part=snippet&key=other"),
+        );
+        assert_ne!(normalize_url("oembed", "https://example.com/?id=1"), normalize_url("oembed", "https://example.com/?id=2"));
+        assert_ne!(normalize_url("oembed", "https://example.com/?key=a"), normalize_url("oembed", "https://example.com/?key=b"));
+        assert_eq!(cache_key(&["llm", "model", "prompt"]).len(), 64);
+    }
+
+    #[test]
+    fn test_get_respects_ttl_and_refresh() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = temp_cache(dir.path(), CacheMode::Normal);
+        let now = Utc::now().timestamp();
+        cache.put(&cache_key(&["fresh"]), &entry("youtube", now));
+        cache.put(&cache_key(&["stale"]), &entry("wikipedia", now - 25 * 3600));
+        assert!(cache.get("youtube", &cache_key(&["fresh"])).is_some());
+        assert!(cache.get("wikipedia", &cache_key(&["stale"])).is_none());
+        assert!(temp_cache(dir.path(), CacheMode::Refresh).get("youtube", &cache_key(&["fresh"])).is_none());
+        assert!(Cache::open(&CacheConfig { mode: CacheMode::Disabled, ..CacheConfig::default() }).is_none());
+    }
+
+    #[test]
+    fn test_ttl_hours_merge_over_defaults() {
+        let config: CacheConfig = serde_yaml::from_str("ttl_hours:\n  wikipedia: 1\n  llm: 2\n").unwrap();
+        assert_eq!(config.ttl_secs("wikipedia"), 3600);
+        assert_eq!(config.ttl_secs("llm"), 2 * 3600);
+        assert_eq!(config.ttl_secs("youtube"), 24 * 7 * 3600);
+    }
+
+    #[test]
+    fn test_prune_removes_expired_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = temp_cache(dir.path(), CacheMode::Normal);
+        let now = Utc::now().timestamp();
+        cache.put(&cache_key(&["a"]), &entry("youtube", now - 3600));
+        cache.put(&cache_key(&["b"]), &entry("youtube", now - 8 * 24 * 3600));
+        cache.put(&cache_key(&["c"]), &entry("llm", now - 8 * 24 * 3600));
+        let stats = prune(&CacheConfig { dir: Some(dir.path().to_path_buf()), ..CacheConfig::default() }).unwrap();
+        assert_eq!(stats, PruneStats { removed: 1, kept: 2 });
+    }
+}
@@ -0,0 +1,161 @@
+use log::debug;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::filename::Naming;
+use crate::{create_markdown_file, date_parts, fetch_note, find_rule, Config, Frontmatter, Note};
+
+/// A URL plus whatever the caller already knows about it (a browser's page title and
+/// selection, user-chosen tags or folder, when it was saved, notes written in a read-later
+/// service). Provider metadata wins; these only fill gaps.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Capture {
+    pub url: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub selection: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub folder: Option<String>,
+    #[serde(default)]
+    pub saved: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    /// Extra frontmatter, added after the provider's own fields unless the provider already set the key.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<(String, serde_yaml::Value)>,
+}
+
+impl Capture {
+    pub fn new(url: &str) -> Self {
+        Capture { url: url.to_string(), ..Capture::default() }
+    }
+}
+
+fn is_placeholder_title(note: &Note) -> bool {
+    note.title.trim().is_empty() || note.title.starts_with("No Title") || note.title == note.url
+}
+
+pub fn apply_capture(note: &mut Note, capture: &Capture) {
+    if let Some(title) = capture.title.as_deref().map(str::trim).filter(|title| !title.is_empty()) {
+        if is_placeholder_title(note) {
+            note.title = title.to_string();
+        }
+    }
+    if let Some(selection) = capture.selection.as_deref().map(str::trim).filter(|selection| !selection.is_empty()) {
+        let quote = selection.lines().map(|line| format!("> {}", line)).collect::<Vec<_>>().join("\n");
+        note.annotations = format!("{}\n\n## Highlight\n{}", note.annotations, quote).trim_start().to_string();
+    }
+    if let Some(notes) = capture.notes.as_deref().map(str::trim).filter(|notes| !notes.is_empty()) {
+        note.annotations = format!("{}\n\n## Notes\n{}", note.annotations, notes).trim_start().to_string();
+    }
+    for tag in &capture.tags {
+        if !note.tags.contains(tag) {
+            note.tags.push(tag.clone());
+        }
+    }
+    if let Some(folder) = capture.folder.as_deref().filter(|folder| !folder.trim().is_empty()) {
+        note.folder = folder.trim().to_string();
+    }
+    for (key, value) in &capture.fields {
+        if !note.fields.iter().any(|(existing, _)| existing == key) {
+            note.fields.push((key.clone(), value.clone()));
+        }
+    }
+}
+
+/// The config defaults, with the capture's tags added to the default tags and date, day and
+/// time taken from `capture.saved` when it is known.
+pub fn capture_frontmatter(defaults: &Frontmatter, capture: &Capture) -> Frontmatter {
+    let mut frontmatter = defaults.clone();
+    let mut tags = defaults.tags.clone().unwrap_or_default();
+    for tag in &capture.tags {
+        if !tags.contains(tag) {
+            tags.push(tag.clone());
+        }
+    }
+    frontmatter.tags = Some(tags).filter(|tags| !tags.is_empty());
+    if let Some(saved) = capture.saved {
+        let (date, day, time) = date_parts(saved);
+        frontmatter.date = Some(date);
+        frontmatter.day = Some(day);
+        frontmatter.time = Some(time);
+    }
+    frontmatter
+}
+
+/// Fetches the note for `capture.url` and layers the capture's metadata onto it, without writing.
+pub async fn fetch_capture(capture: &Capture, config: &Config) -> Result<(Note, Naming)> {
+    debug!("fetch_capture: capture={:?}", capture);
+    let naming = Naming::from_link(find_rule(&capture.url, config)?)?;
+    let mut note = fetch_note(&capture.url, config).await?;
+    apply_capture(&mut note, capture);
+    Ok((note, naming))
+}
+
+/// Runs the same pipeline as [`crate::handle_url`] for a [`Capture`].
+pub async fn capture_url(capture: &Capture, config: &Config) -> Result<PathBuf> {
+    let (note, naming) = fetch_capture(capture, config).await?;
+    create_markdown_file(&note, &config.vault, &capture_frontmatter(&config.frontmatter, capture), &naming).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_capture_fills_gaps_only() {
+        let mut note = Note { title: "No Title 2024-01-01".into(), description: "Summary".into(), tags: vec!["rust".into()], folder: "links".into(), ..Note::default() };
+        let capture = Capture {
+            url: "https://example.com".into(),
+            title: Some("Page Title".into()),
+            selection: Some("first line\nsecond line".into()),
+            tags: vec!["rust".into(), "inbox".into()],
+            folder: Some("reading".into()),
+            notes: Some("Read later".into()),
+            fields: vec![("read".into(), serde_yaml::Value::Bool(false))],
+            ..Capture::default()
+        };
+        apply_capture(&mut note, &capture);
+        assert_eq!(note.title, "Page Title");
+        assert_eq!(note.description, "Summary");
+        assert_eq!(note.annotations, "## Highlight\n> first line\n> second line\n\n## Notes\nRead later");
+        assert_eq!(note.fields, vec![("read".to_string(), serde_yaml::Value::Bool(false))]);
+        assert_eq!(note.tags, vec!["rust", "inbox"]);
+        assert_eq!(note.folder, "reading");
+
+        let mut titled = Note { title: "Real Title".into(), ..Note::default() };
+        apply_capture(&mut titled, &capture);
+        assert_eq!(titled.title, "Real Title");
+    }
+
+    #[test]
+    fn test_capture_frontmatter_merges_default_tags() {
+        let mut defaults = crate::testing::test_config().frontmatter;
+        defaults.tags = Some(vec!["clippings".into()]);
+        let capture = Capture { tags: vec!["rust".into(), "clippings".into()], ..Capture::new("https://example.com") };
+
+        let frontmatter = capture_frontmatter(&defaults, &capture);
+        assert_eq!(frontmatter.tags, Some(vec!["clippings".to_string(), "rust".to_string()]));
+        let mut note = Note { url: capture.url.clone(), ..Note::default() };
+        apply_capture(&mut note, &capture);
+        assert!(crate::render_note(&note, &frontmatter).contains("tags:\n  - clippings\n  - rust\nurl:"));
+    }
+
+    #[test]
+    fn test_update_keeps_captured_highlight() {
+        let mut captured = Note { url: "https://example.com".into(), description: "Old".into(), ..Note::default() };
+        apply_capture(&mut captured, &Capture { selection: Some("keep me".into()), ..Capture::new("https://example.com") });
+        let refetched = Note { url: "https://example.com".into(), description: "New".into(), ..Note::default() };
+        let frontmatter = crate::testing::test_config().frontmatter;
+
+        let merged = crate::update::merge_note(&crate::render_note(&captured, &frontmatter), &crate::render_note(&refetched, &frontmatter));
+        assert!(merged.contains("## Description\nNew\n"), "got: {}", merged);
+        assert!(merged.contains("<!-- obsidian-link:end -->\n\n## Highlight\n> keep me\n"), "got: {}", merged);
+    }
+}
@@ -0,0 +1,362 @@
+use log::{debug, info, warn};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use eyre::{eyre, Result};
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::{StatusCode, Url};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::http::{urlencode, HttpClient};
+use crate::ratelimit::{Rate, TokenBucket};
+use crate::update::{set_frontmatter_fields, MANAGED_START};
+use crate::{read_frontmatter, today, vault, Config};
+
+const MAX_BODY_BYTES: usize = 64 * 1024;
+const PARKING_HOSTS: &[&str] = &[
+    "sedoparking.com", "bodis.com", "parkingcrew.net", "above.com", "afternic.com",
+    "dan.com", "hugedomains.com", "undeveloped.com", "parklogic.com", "domainmarket.com",
+];
+
+lazy_static! {
+    static ref YOUTUBE_REGEX: Regex = Regex::new(r"^https?://(?:www\.|m\.)?(?:youtube\.com/(?:watch|shorts/)|youtu\.be/)").expect("valid regex");
+    static ref PARKING_REGEX: Regex = Regex::new(
+        r"(?i)(this domain (?:name )?(?:is|may be) for sale|buy this domain|domain is parked|parked free|sedoparking|parkingcrew|bodis\.com)"
+    ).expect("valid regex");
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LinkStatus {
+    Dead,
+    Parked,
+    Unavailable,
+    Private,
+    Unreachable,
+    Error,
+    Ok,
+}
+
+impl fmt::Display for LinkStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LinkStatus::Ok => "ok",
+            LinkStatus::Dead => "dead",
+            LinkStatus::Parked => "parked",
+            LinkStatus::Unavailable => "unavailable",
+            LinkStatus::Private => "private",
+            LinkStatus::Unreachable => "unreachable",
+            LinkStatus::Error => "error",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckResult {
+    pub path: PathBuf,
+    pub url: String,
+    pub status: LinkStatus,
+    pub detail: String,
+}
+
+#[derive(Debug)]
+pub struct CheckOptions {
+    pub concurrency: usize,
+    /// Shared by every check, on top of the per-host limits in `http.rate_limits`.
+    pub rate: Rate,
+    pub report: String,
+    pub write: bool,
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with('.'))
+}
+
+pub fn collect_notes(root: &Path) -> Vec<(PathBuf, String)> {
+    debug!("collect_notes: root={}", root.display());
+    let mut notes = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(file_type) = entry.file_type() else { continue };
+            if is_hidden(&path) || file_type.is_symlink() {
+                continue;
+            }
+            if file_type.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "md") {
+                if let Some(url) = tool_note_url(&path) {
+                    notes.push((path, url));
+                }
+            }
+        }
+    }
+    notes.sort();
+    notes
+}
+
+// Notes written by this tool either carry the managed-section marker or the frontmatter keys it always emits
+fn tool_note_url(path: &Path) -> Option<String> {
+    let frontmatter = read_frontmatter(path)?;
+    let url = frontmatter.get("url")?.as_str()?.to_string();
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return None;
+    }
+    let has_marker = std::fs::read_to_string(path).is_ok_and(|content| content.contains(MANAGED_START));
+    let has_keys = ["date", "day", "author"].iter().all(|key| frontmatter.contains_key(*key));
+    (has_marker || has_keys).then_some(url)
+}
+
+pub fn classify_status(status: StatusCode) -> Option<LinkStatus> {
+    match status.as_u16() {
+        200..=399 => None,
+        404 | 410 => Some(LinkStatus::Dead),
+        _ => Some(LinkStatus::Error),
+    }
+}
+
+pub fn is_parked(final_url: &Url, body: &str) -> bool {
+    let host = final_url.host_str().unwrap_or_default();
+    PARKING_HOSTS.iter().any(|parking| host == *parking || host.ends_with(&format!(".{}", parking)))
+        || PARKING_REGEX.is_match(body)
+}
+
+async fn check_youtube(client: &HttpClient, url: &str) -> (LinkStatus, String) {
+    let oembed = format!("https://www.youtube.com/oembed?format=json&url={}", urlencode(url));
+    match client.get(&oembed).await {
+        Ok(response) => match response.status().as_u16() {
+            200 => (LinkStatus::Ok, String::new()),
+            401 | 403 => (LinkStatus::Private, "video is private".to_string()),
+            400 | 404 => (LinkStatus::Unavailable, "video unavailable".to_string()),
+            code => (LinkStatus::Error, format!("HTTP {}", code)),
+        },
+        Err(e) => (LinkStatus::Unreachable, e.to_string()),
+    }
+}
+
+async fn read_body_prefix(mut response: reqwest::Response) -> String {
+    let mut body = Vec::new();
+    while let Ok(Some(chunk)) = response.chunk().await {
+        body.extend_from_slice(&chunk);
+        if body.len() >= MAX_BODY_BYTES {
+            break;
+        }
+    }
+    String::from_utf8_lossy(&body).into_owned()
+}
+
+fn is_html(response: &reqwest::Response) -> bool {
+    response.headers().get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_none_or(|content_type| content_type.starts_with("text/html") || content_type.starts_with("application/xhtml"))
+}
+
+pub async fn check_url(client: &HttpClient, url: &str) -> (LinkStatus, String) {
+    debug!("check_url: url={}", url);
+    if YOUTUBE_REGEX.is_match(url) {
+        return check_youtube(client, url).await;
+    }
+
+    if let Ok(response) = client.head(url).await {
+        let status = response.status();
+        if matches!(status.as_u16(), 404 | 410) {
+            return (LinkStatus::Dead, format!("HTTP {}", status.as_u16()));
+        }
+        if status.is_success() && is_parked(response.url(), "") {
+            return (LinkStatus::Parked, response.url().to_string());
+        }
+        if status.is_success() && !is_html(&response) {
+            return (LinkStatus::Ok, String::new());
+        }
+    }
+
+    // Servers that reject or mishandle HEAD get a second chance with GET, and HTML pages
+    // are read so parking pages served from the original domain are recognised
+    match client.get(url).await {
+        Ok(response) => {
+            let status = response.status();
+            if let Some(link_status) = classify_status(status) {
+                return (link_status, format!("HTTP {}", status.as_u16()));
+            }
+            let final_url = response.url().clone();
+            let body = read_body_prefix(response).await;
+            if is_parked(&final_url, &body) {
+                (LinkStatus::Parked, final_url.to_string())
+            } else {
+                (LinkStatus::Ok, String::new())
+            }
+        }
+        Err(e) => (LinkStatus::Unreachable, e.to_string()),
+    }
+}
+
+pub fn render_report(results: &[CheckResult], root: &Path, date: &str) -> String {
+    let mut sorted = results.to_vec();
+    sorted.sort_by(|a, b| a.status.cmp(&b.status).then_with(|| a.path.cmp(&b.path)));
+
+    let mut counts: Vec<(LinkStatus, usize)> = Vec::new();
+    for result in &sorted {
+        match counts.last_mut() {
+            Some((status, count)) if *status == result.status => *count += 1,
+            _ => counts.push((result.status, 1)),
+        }
+    }
+
+    let mut report = format!("---\ndate: {}\ntype: report\n---\n\n# Link Check Report\n\nChecked {} links.\n\n", date, results.len());
+    for (status, count) in &counts {
+        report += &format!("- {}: {}\n", status, count);
+    }
+    let broken = sorted.iter().filter(|result| result.status != LinkStatus::Ok).collect::<Vec<_>>();
+    if !broken.is_empty() {
+        report += "\n## Broken Links\n\n| Status | Note | URL | Detail |\n| --- | --- | --- | --- |\n";
+        for result in broken {
+            let note = result.path.strip_prefix(root).unwrap_or(&result.path).with_extension("");
+            report += &format!(
+                "| {} | [[{}]] | <{}> | {} |\n",
+                result.status, note.display(), result.url, result.detail.replace('|', "\\|")
+            );
+        }
+    }
+    report
+}
+
+fn write_status(root: &Path, result: &CheckResult, checked_at: &str) -> Result<()> {
+    let content = std::fs::read_to_string(&result.path)?;
+    let updated = set_frontmatter_fields(&content, &[
+        ("link_status", result.status.to_string()),
+        ("checked_at", checked_at.to_string()),
+    ]);
+    vault::write_atomic(root, &result.path, updated.as_bytes())
+}
+
+pub async fn check_vault(config: &Config, options: &CheckOptions) -> Result<Vec<CheckResult>> {
+    debug!("check_vault: options={:?}", options);
+    let root = vault::vault_root(&config.vault)?;
+    let notes = collect_notes(&root);
+    info!("Checking {} links", notes.len());
+
+    let client = config.http()?.clone();
+    let semaphore = Arc::new(Semaphore::new(options.concurrency.max(1)));
+    let limiter = Arc::new(TokenBucket::new(options.rate));
+    let mut tasks = JoinSet::new();
+    for (path, url) in notes {
+        let (client, semaphore, limiter) = (client.clone(), semaphore.clone(), limiter.clone());
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await.expect("semaphore is never closed");
+            limiter.acquire(1.0).await;
+            let (status, detail) = check_url(&client, &url).await;
+            CheckResult { path, url, status, detail }
+        });
+    }
+
+    let mut results = Vec::new();
+    while let Some(result) = tasks.join_next().await {
+        results.push(result.map_err(|e| eyre!("Link check task failed: {}", e))?);
+    }
+    results.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let (date, _, time) = today();
+    if options.write {
+        let checked_at = format!("{} {}", date, time);
+        for result in &results {
+            if let Err(e) = write_status(&root, result, &checked_at) {
+                warn!("Failed to record link status in {}: {}", result.path.display(), e);
+            }
+        }
+        let report_path = vault::safe_join(&root, &options.report)?;
+        vault::write_atomic(&root, &report_path, render_report(&results, &root, &date).as_bytes())?;
+        info!("Wrote report to {}", report_path.display());
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::test_config;
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const CHECKED: &str = "---\nurl: https://example.com/a\nlink_status: dead\nchecked_at: 2024-01-01 10:00\n---\n\nbody\n";
+
+    #[test]
+    fn test_classify_status_and_parking() {
+        assert_eq!(classify_status(StatusCode::NOT_FOUND), Some(LinkStatus::Dead));
+        assert_eq!(classify_status(StatusCode::GONE), Some(LinkStatus::Dead));
+        assert_eq!(classify_status(StatusCode::OK), None);
+        assert_eq!(classify_status(StatusCode::INTERNAL_SERVER_ERROR), Some(LinkStatus::Error));
+
+        let url = Url::parse("https://example.com/").unwrap();
+        assert!(is_parked(&url, "<h1>This domain is for sale!</h1>"));
+        assert!(is_parked(&Url::parse("https://ww1.sedoparking.com/x").unwrap(), ""));
+        assert!(!is_parked(&url, "<h1>Welcome</h1>"));
+    }
+
+    #[test]
+    fn test_collect_notes_only_includes_tool_notes() {
+        let vault = tempfile::tempdir().unwrap();
+        let root = vault.path();
+        std::fs::create_dir_all(root.join("youtube")).unwrap();
+        std::fs::create_dir_all(root.join(".obsidian")).unwrap();
+        std::fs::write(root.join("youtube/a.md"), "---\ndate: 2024-01-01\nday: Mon\nurl: https://example.com/a\nauthor: x\n---\n").unwrap();
+        std::fs::write(root.join("b.md"), "---\nurl: https://example.com/b\n---\n<!-- obsidian-link:start -->\n").unwrap();
+        std::fs::write(root.join("mine.md"), "---\nurl: https://example.com/c\n---\nmy own note\n").unwrap();
+        std::fs::write(root.join(".obsidian/c.md"), "---\ndate: 1\nday: 1\nurl: https://example.com/d\nauthor: x\n---\n").unwrap();
+
+        let urls = collect_notes(root).into_iter().map(|(_, url)| url).collect::<Vec<_>>();
+        assert_eq!(urls, vec!["https://example.com/b".to_string(), "https://example.com/a".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_check_url_reads_html_after_head() {
+        let server = MockServer::start().await;
+        Mock::given(method("HEAD"))
+            .respond_with(ResponseTemplate::new(200).insert_header("content-type", "text/html; charset=utf-8"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw("<h1>This domain is for sale!</h1>", "text/html"))
+            .mount(&server)
+            .await;
+        let config = test_config();
+        let (status, _) = check_url(config.http().unwrap(), &format!("{}/home", server.uri())).await;
+        assert_eq!(status, LinkStatus::Parked);
+    }
+
+    #[test]
+    fn test_write_status_refreshes_checked_at() {
+        let vault = tempfile::tempdir().unwrap();
+        let root = vault.path().canonicalize().unwrap();
+        let path = root.join("a.md");
+        std::fs::write(&path, CHECKED).unwrap();
+        let result = CheckResult { path: path.clone(), url: "https://example.com/a".into(), status: LinkStatus::Dead, detail: String::new() };
+        write_status(&root, &result, "2024-01-02 10:00").unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.contains("link_status: dead\n"));
+        assert!(content.contains("checked_at: 2024-01-02 10:00\n"), "got: {}", content);
+        assert!(!content.contains("2024-01-01"));
+    }
+
+    #[test]
+    fn test_render_report_orders_by_status_then_path() {
+        let root = Path::new("/vault");
+        let result = |path: &str, status| CheckResult { path: root.join(path), url: format!("https://{}", path), status, detail: String::new() };
+        let report = render_report(&[
+            result("b.md", LinkStatus::Ok),
+            result("z.md", LinkStatus::Dead),
+            result("a.md", LinkStatus::Dead),
+            result("y.md", LinkStatus::Parked),
+        ], root, "2024-01-01");
+        assert!(report.contains("Checked 4 links.\n\n- dead: 2\n- parked: 1\n- ok: 1\n"));
+        let a = report.find("[[a]]").unwrap();
+        let z = report.find("[[z]]").unwrap();
+        let y = report.find("[[y]]").unwrap();
+        assert!(a < z && z < y);
+        assert!(!report.contains("[[b]]"));
+    }
+}
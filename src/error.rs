@@ -0,0 +1,193 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use serde_json::{json, Value};
+
+use crate::vault::PathEscapeError;
+
+/// Exit code used for every transient failure, so automation can retry on a single code.
+pub const EXIT_TRANSIENT: i32 = 75;
+/// Exit code for failures that do not map to a more specific [`Error`] kind.
+pub const EXIT_UNKNOWN: i32 = 1;
+
+/// Failures callers may want to tell apart. They travel inside `eyre::Report`,
+/// so use [`classify`] to recover them from a returned error.
+///
+/// | kind              | exit code |
+/// | ----------------- | --------- |
+/// | `config_invalid`  | 3         |
+/// | `no_matching_rule`| 4         |
+/// | `fetch_failed`    | 5         |
+/// | `metadata_missing`| 6         |
+/// | `llm_failed`      | 7         |
+/// | `write_failed`    | 8         |
+/// | `duplicate`       | 9         |
+/// | `response_too_large` | 11     |
+/// | `quota_exceeded`  | 75        |
+///
+/// Transient errors (network failures, timeouts, HTTP 429 and 5xx, exhausted quotas)
+/// exit with [`EXIT_TRANSIENT`] instead; anything unclassified exits with [`EXIT_UNKNOWN`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    ConfigInvalid(String),
+    NoMatchingRule(String),
+    FetchFailed { url: String, status: Option<u16>, message: String },
+    MetadataMissing(String),
+    LlmFailed { status: Option<u16>, message: String },
+    WriteFailed(String),
+    Duplicate(PathBuf),
+    QuotaExceeded(String),
+    ResponseTooLarge { url: String, limit: u64 },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ConfigInvalid(message) => write!(f, "Invalid config: {}", message),
+            Error::NoMatchingRule(url) => write!(f, "No link rule matches {}", url),
+            Error::FetchFailed { url, status: Some(status), .. } => write!(f, "Request to {} failed with status {}", url, status),
+            Error::FetchFailed { url, message, .. } => write!(f, "Request to {} failed: {}", url, message),
+            Error::MetadataMissing(message) => write!(f, "Metadata missing: {}", message),
+            Error::LlmFailed { message, .. } => write!(f, "Summarization failed: {}", message),
+            Error::WriteFailed(message) => write!(f, "Write failed: {}", message),
+            Error::Duplicate(path) => write!(f, "Note already exists: {}", path.display()),
+            Error::QuotaExceeded(message) => write!(f, "Quota exceeded: {}", message),
+            Error::ResponseTooLarge { url, limit } => write!(f, "Response from {} exceeds {} bytes", url, limit),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn is_transient_status(status: Option<u16>) -> bool {
+    status.is_some_and(|status| status == 429 || status >= 500)
+}
+
+impl Error {
+    pub fn http(url: &str, status: reqwest::StatusCode) -> Self {
+        Error::FetchFailed { url: url.to_string(), status: Some(status.as_u16()), message: status.to_string() }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::ConfigInvalid(_) => "config_invalid",
+            Error::NoMatchingRule(_) => "no_matching_rule",
+            Error::FetchFailed { .. } => "fetch_failed",
+            Error::MetadataMissing(_) => "metadata_missing",
+            Error::LlmFailed { .. } => "llm_failed",
+            Error::WriteFailed(_) => "write_failed",
+            Error::Duplicate(_) => "duplicate",
+            Error::QuotaExceeded(_) => "quota_exceeded",
+            Error::ResponseTooLarge { .. } => "response_too_large",
+        }
+    }
+
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::FetchFailed { status: None, .. } => true,
+            Error::FetchFailed { status, .. } | Error::LlmFailed { status, .. } => is_transient_status(*status),
+            Error::QuotaExceeded(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::QuotaExceeded(_) => EXIT_TRANSIENT,
+            error if error.is_transient() => EXIT_TRANSIENT,
+            Error::ConfigInvalid(_) => 3,
+            Error::NoMatchingRule(_) => 4,
+            Error::FetchFailed { .. } => 5,
+            Error::MetadataMissing(_) => 6,
+            Error::LlmFailed { .. } => 7,
+            Error::WriteFailed(_) => 8,
+            Error::Duplicate(_) => 9,
+            Error::ResponseTooLarge { .. } => 11,
+        }
+    }
+
+    fn status(&self) -> Option<u16> {
+        match self {
+            Error::FetchFailed { status, .. } | Error::LlmFailed { status, .. } => *status,
+            _ => None,
+        }
+    }
+}
+
+/// Finds the most specific [`Error`] in `report`'s chain, mapping path-escape and
+/// `reqwest` errors onto their kinds. Returns `None` for unclassified failures.
+pub fn classify(report: &eyre::Report) -> Option<Error> {
+    for cause in report.chain() {
+        if let Some(error) = cause.downcast_ref::<Error>() {
+            return Some(error.clone());
+        }
+        if let Some(error) = cause.downcast_ref::<PathEscapeError>() {
+            return Some(Error::WriteFailed(error.to_string()));
+        }
+        if let Some(error) = cause.downcast_ref::<reqwest::Error>() {
+            return Some(Error::FetchFailed {
+                url: error.url().map(|url| url.to_string()).unwrap_or_default(),
+                status: error.status().map(|status| status.as_u16()),
+                message: error.to_string(),
+            });
+        }
+    }
+    None
+}
+
+pub fn exit_code(report: &eyre::Report) -> i32 {
+    classify(report).map_or(EXIT_UNKNOWN, |error| error.exit_code())
+}
+
+/// The `--json` error document: `{"error": {"kind", "message", "exit_code", "transient", "status"}}`.
+pub fn to_json(report: &eyre::Report) -> Value {
+    let error = classify(report);
+    json!({
+        "error": {
+            "kind": error.as_ref().map_or("unknown", |e| e.kind()),
+            "message": format!("{:#}", report),
+            "exit_code": error.as_ref().map_or(EXIT_UNKNOWN, |e| e.exit_code()),
+            "transient": error.as_ref().is_some_and(|e| e.is_transient()),
+            "status": error.as_ref().and_then(|e| e.status()),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eyre::WrapErr;
+
+    #[test]
+    fn test_exit_codes_and_transience() {
+        assert_eq!(Error::NoMatchingRule("x".into()).exit_code(), 4);
+        assert_eq!(Error::Duplicate(PathBuf::from("a.md")).exit_code(), 9);
+        let not_found = Error::FetchFailed { url: "u".into(), status: Some(404), message: String::new() };
+        assert_eq!(not_found.exit_code(), 5);
+        let unavailable = Error::FetchFailed { url: "u".into(), status: Some(503), message: String::new() };
+        assert!(unavailable.is_transient());
+        assert_eq!(unavailable.exit_code(), EXIT_TRANSIENT);
+        assert_eq!(Error::QuotaExceeded("youtube".into()).exit_code(), EXIT_TRANSIENT);
+    }
+
+    #[test]
+    fn test_classify_through_context() {
+        let report = Err::<(), _>(Error::ConfigInvalid("bad yaml".into()))
+            .wrap_err("while starting")
+            .unwrap_err();
+        assert_eq!(exit_code(&report), 3);
+        let escape = eyre::Report::new(PathEscapeError { path: "/etc/x".into(), root: "/vault".into() });
+        assert_eq!(classify(&escape).map(|e| e.kind()), Some("write_failed"));
+        assert_eq!(exit_code(&eyre::eyre!("something else")), EXIT_UNKNOWN);
+    }
+
+    #[test]
+    fn test_to_json() {
+        let report = eyre::Report::new(Error::LlmFailed { status: Some(429), message: "rate limited".into() });
+        let value = to_json(&report);
+        assert_eq!(value["error"]["kind"], "llm_failed");
+        assert_eq!(value["error"]["transient"], true);
+        assert_eq!(value["error"]["exit_code"], EXIT_TRANSIENT);
+        assert_eq!(value["error"]["status"], 429);
+    }
+}
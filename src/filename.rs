@@ -0,0 +1,270 @@
+use log::debug;
+use std::path::{Path, PathBuf};
+
+use eyre::Result;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::error::Error;
+use crate::{read_frontmatter, today, Link, Note};
+
+const DEFAULT_TEMPLATE: &str = "{title}";
+const DEFAULT_MAX_LENGTH: usize = 100;
+// eCryptfs (Android, encrypted home dirs) caps names at 143 bytes; leave room for suffixes and ".md"
+const MAX_STEM_BYTES: usize = 120;
+const FORBIDDEN_CHARS: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*', '#', '^', '[', ']'];
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Collision {
+    Suffix,
+    AppendId,
+    Fail,
+    Overwrite,
+}
+
+impl Collision {
+    pub fn from_config(value: Option<&str>) -> Result<Self> {
+        match value.map(|v| v.to_lowercase()).as_deref() {
+            None | Some("suffix") => Ok(Collision::Suffix),
+            Some("append-id") | Some("id") => Ok(Collision::AppendId),
+            Some("fail") => Ok(Collision::Fail),
+            Some("overwrite") => Ok(Collision::Overwrite),
+            Some(other) => Err(Error::ConfigInvalid(format!("unknown on_collision strategy '{}', expected suffix, append-id, fail or overwrite", other)).into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Naming {
+    pub template: String,
+    pub max_length: usize,
+    pub collision: Collision,
+}
+
+impl Default for Naming {
+    fn default() -> Self {
+        Naming {
+            template: DEFAULT_TEMPLATE.to_string(),
+            max_length: DEFAULT_MAX_LENGTH,
+            collision: Collision::Suffix,
+        }
+    }
+}
+
+impl Naming {
+    pub fn from_link(link: &Link) -> Result<Self> {
+        Ok(Naming {
+            template: link.filename.clone().unwrap_or_else(|| DEFAULT_TEMPLATE.to_string()),
+            max_length: link.max_length.unwrap_or(DEFAULT_MAX_LENGTH),
+            collision: Collision::from_config(link.on_collision.as_deref())?,
+        })
+    }
+
+    pub fn file_stem(&self, note: &Note) -> String {
+        let (date, _, _) = today();
+        let stem = safe_file_stem(&render_template(&self.template, note, &date), self.max_length);
+        if !stem.is_empty() {
+            return stem;
+        }
+        let fallback = safe_file_stem(&note_id(note), self.max_length);
+        if fallback.is_empty() { "Untitled".to_string() } else { fallback }
+    }
+
+    pub fn resolve(&self, dir: &Path, note: &Note) -> Result<PathBuf> {
+        debug!("Naming::resolve: dir={} note.title={}", dir.display(), note.title);
+        let stem = self.file_stem(note);
+        let path = dir.join(format!("{}.md", stem));
+        if !path.exists() || self.collision == Collision::Overwrite {
+            return Ok(path);
+        }
+        match self.collision {
+            Collision::Fail => Err(Error::Duplicate(path).into()),
+            // The existing file holds this URL, so it is the same note and gets merged into
+            _ if holds_url(&path, note) => Ok(path),
+            Collision::AppendId => {
+                let id = safe_file_stem(&note_id(note), self.max_length);
+                let stem = with_suffix(&stem, &format!(" - {}", id));
+                let path = dir.join(format!("{}.md", stem));
+                if is_free(&path, note) { Ok(path) } else { Ok(with_counter(dir, &stem, note)) }
+            }
+            _ => Ok(with_counter(dir, &stem, note)),
+        }
+    }
+}
+
+// Shortens `stem` so that it still fits MAX_STEM_BYTES once `suffix` is appended
+fn with_suffix(stem: &str, suffix: &str) -> String {
+    let budget = MAX_STEM_BYTES.saturating_sub(suffix.len());
+    let mut head = String::new();
+    for grapheme in stem.graphemes(true) {
+        if head.len() + grapheme.len() > budget {
+            break;
+        }
+        head.push_str(grapheme);
+    }
+    format!("{}{}", head.trim_end(), suffix).trim().to_string()
+}
+
+fn with_counter(dir: &Path, stem: &str, note: &Note) -> PathBuf {
+    (2..)
+        .map(|n| dir.join(format!("{} {}.md", stem, n)))
+        .find(|path| is_free(path, note))
+        .expect("an unused counter exists")
+}
+
+fn is_free(path: &Path, note: &Note) -> bool {
+    !path.exists() || holds_url(path, note)
+}
+
+fn holds_url(path: &Path, note: &Note) -> bool {
+    read_frontmatter(path)
+        .and_then(|fm| fm.get("url").and_then(|url| url.as_str().map(String::from)))
+        .is_some_and(|url| !note.url.is_empty() && url == note.url)
+}
+
+pub fn note_id(note: &Note) -> String {
+    if !note.id.is_empty() {
+        return note.id.clone();
+    }
+    // FNV-1a keeps the fallback ID stable across runs without pulling in a hashing crate
+    let hash = note.url.bytes().fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+    format!("{:08x}", hash as u32)
+}
+
+pub fn render_template(template: &str, note: &Note, date: &str) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}') else {
+            out.push_str(&rest[start..]);
+            return out;
+        };
+        let key = &rest[start + 1..start + end];
+        out.push_str(&sanitize_filename(&placeholder(key, note, date)));
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn placeholder(key: &str, note: &Note, date: &str) -> String {
+    match key {
+        "title" => note.title.clone(),
+        "date" => date.to_string(),
+        "channel" | "author" => note.author.clone(),
+        "id" => note_id(note),
+        "type" => note.note_type.clone().unwrap_or_default(),
+        _ => note.fields.iter()
+            .find(|(name, _)| name == key)
+            .and_then(|(_, value)| match value {
+                serde_yaml::Value::String(s) => Some(s.clone()),
+                serde_yaml::Value::Number(n) => Some(n.to_string()),
+                serde_yaml::Value::Bool(b) => Some(b.to_string()),
+                _ => None,
+            })
+            .unwrap_or_default(),
+    }
+}
+
+pub fn sanitize_filename(name: &str) -> String {
+    let replaced = name.chars()
+        .map(|c| if c.is_control() || FORBIDDEN_CHARS.contains(&c) { ' ' } else { c })
+        .collect::<String>();
+    replaced.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+pub fn safe_file_stem(name: &str, max_length: usize) -> String {
+    let name = sanitize_filename(name);
+    let mut stem = String::new();
+    for grapheme in name.graphemes(true).take(max_length) {
+        if stem.len() + grapheme.len() > MAX_STEM_BYTES {
+            break;
+        }
+        stem.push_str(grapheme);
+    }
+    // Windows drops trailing dots and spaces; leading dots hide files on Unix
+    let stem = stem.trim_matches(|c: char| c == '.' || c.is_whitespace()).to_string();
+    let base = stem.split('.').next().unwrap_or_default().trim_end();
+    if RESERVED_NAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(base)) {
+        format!("{}_{}", base, &stem[base.len()..])
+    } else {
+        stem
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error;
+
+    fn note(title: &str) -> Note {
+        Note {
+            title: title.to_string(),
+            url: format!("https://example.com/{}", title.len()),
+            author: "Channel".to_string(),
+            id: "abc123".to_string(),
+            ..Note::default()
+        }
+    }
+
+    #[test]
+    fn test_render_template() {
+        let note = note("What/is: this?");
+        assert_eq!(render_template("{date} {title}", &note, "2024-01-01"), "2024-01-01 What is this");
+        assert_eq!(render_template("{channel} - {title}", &note, "2024-01-01"), "Channel - What is this");
+        assert_eq!(render_template("{id}", &note, "2024-01-01"), "abc123");
+    }
+
+    #[test]
+    fn test_safe_file_stem_reserved_and_truncation() {
+        assert_eq!(safe_file_stem("CON", 100), "CON_");
+        assert_eq!(safe_file_stem("nul.txt", 100), "nul_.txt");
+        assert_eq!(safe_file_stem("  ..hidden name.  ", 100), "hidden name");
+        assert_eq!(safe_file_stem("e\u{301}e\u{301}e\u{301}", 2), "e\u{301}e\u{301}");
+        assert!(safe_file_stem(&"日本語".repeat(100), 1000).len() <= MAX_STEM_BYTES);
+    }
+
+    #[test]
+    fn test_empty_title_falls_back_to_id() {
+        let naming = Naming::default();
+        assert_eq!(naming.file_stem(&note("???")), "abc123");
+    }
+
+    #[test]
+    fn test_resolve_collision_strategies() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(dir.join("Same.md"), "---\nurl: https://other.example\n---\n").unwrap();
+        let note = note("Same");
+
+        let suffix = Naming::default();
+        assert_eq!(suffix.resolve(dir, &note).unwrap(), dir.join("Same 2.md"));
+        let append_id = Naming { collision: Collision::AppendId, ..Naming::default() };
+        assert_eq!(append_id.resolve(dir, &note).unwrap(), dir.join("Same - abc123.md"));
+        let fail = Naming { collision: Collision::Fail, ..Naming::default() };
+        assert!(fail.resolve(dir, &note).is_err());
+
+        std::fs::write(dir.join("Same.md"), format!("---\nurl: {}\n---\n", note.url)).unwrap();
+        assert!(matches!(error::classify(&fail.resolve(dir, &note).unwrap_err()), Some(Error::Duplicate(_))));
+        assert_eq!(suffix.resolve(dir, &note).unwrap(), dir.join("Same.md"));
+    }
+
+    #[test]
+    fn test_append_id_stays_within_stem_limit() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let note = note(&"a".repeat(200));
+        let append_id = Naming { collision: Collision::AppendId, max_length: 200, ..Naming::default() };
+        let stem = append_id.file_stem(&note);
+        std::fs::write(dir.join(format!("{}.md", stem)), "").unwrap();
+        let path = append_id.resolve(dir, &note).unwrap();
+        let name = path.file_stem().unwrap().to_str().unwrap();
+        assert!(name.ends_with(" - abc123"));
+        assert!(name.len() <= MAX_STEM_BYTES);
+    }
+}
@@ -0,0 +1,216 @@
+use log::debug;
+use scraper::{ElementRef, Html, Node};
+
+pub fn html_to_markdown(html: &str) -> String {
+    debug!("html_to_markdown: html.len={}", html.len());
+    let fragment = Html::parse_fragment(html);
+    let mut out = String::new();
+    walk(fragment.root_element(), &mut out, 0);
+    collapse_blank_lines(&out)
+}
+
+pub fn html_to_text(html: &str) -> String {
+    debug!("html_to_text: html.len={}", html.len());
+    let fragment = Html::parse_fragment(html);
+    let text = fragment.root_element().text().collect::<Vec<_>>().join(" ");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn walk(element: ElementRef, out: &mut String, depth: usize) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => {
+                let text = collapse_whitespace(text);
+                if !text.trim().is_empty() || (!out.ends_with(' ') && !out.ends_with('\n') && !text.is_empty()) {
+                    out.push_str(&text);
+                }
+            }
+            Node::Element(_) => {
+                if let Some(child) = ElementRef::wrap(child) {
+                    walk_element(child, out, depth);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn walk_element(element: ElementRef, out: &mut String, depth: usize) {
+    let name = element.value().name();
+    match name {
+        "script" | "style" | "noscript" => {}
+        "br" => out.push('\n'),
+        "hr" => out.push_str("\n\n---\n\n"),
+        "p" | "div" | "section" | "article" | "figure" => {
+            out.push_str("\n\n");
+            walk(element, out, depth);
+            out.push_str("\n\n");
+        }
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            let level = name[1..].parse::<usize>().unwrap_or(1);
+            out.push_str(&format!("\n\n{} ", "#".repeat(level)));
+            out.push_str(inline_text(element).trim());
+            out.push_str("\n\n");
+        }
+        "strong" | "b" => wrap_inline(element, out, depth, "**"),
+        "em" | "i" => wrap_inline(element, out, depth, "*"),
+        "del" | "s" => wrap_inline(element, out, depth, "~~"),
+        "code" => {
+            out.push('`');
+            out.push_str(&element.text().collect::<String>());
+            out.push('`');
+        }
+        "pre" => {
+            let code = element.text().collect::<String>();
+            let lang = element
+                .select(&scraper::Selector::parse("code").expect("valid selector"))
+                .next()
+                .and_then(|code| code.value().attr("class"))
+                .and_then(|class| class.split_whitespace().find_map(|c| c.strip_prefix("language-").or_else(|| c.strip_prefix("lang-"))))
+                .unwrap_or_default();
+            out.push_str(&format!("\n\n```{}\n{}\n```\n\n", lang, code.trim_end_matches('\n')));
+        }
+        "a" => {
+            let href = element.value().attr("href").unwrap_or_default();
+            let text = inline_text(element);
+            let text = text.trim();
+            if href.is_empty() || href.starts_with('#') {
+                out.push_str(text);
+            } else if text.is_empty() || text == href {
+                out.push_str(&format!("<{}>", href));
+            } else {
+                out.push_str(&format!("[{}]({})", text, href));
+            }
+        }
+        "img" => {
+            if let Some(src) = element.value().attr("src") {
+                let alt = element.value().attr("alt").unwrap_or_default();
+                out.push_str(&format!("![{}]({})", alt, src));
+            }
+        }
+        "ul" | "ol" => {
+            out.push_str("\n\n");
+            let ordered = name == "ol";
+            let mut index = 1;
+            for item in element.children().filter_map(ElementRef::wrap) {
+                if item.value().name() != "li" {
+                    continue;
+                }
+                let marker = if ordered { format!("{}.", index) } else { "-".to_string() };
+                let mut body = String::new();
+                walk(item, &mut body, depth + 1);
+                let body = collapse_blank_lines(&body);
+                let indent = "  ".repeat(depth);
+                let mut lines = body.lines();
+                if let Some(first) = lines.next() {
+                    out.push_str(&format!("{}{} {}\n", indent, marker, first));
+                }
+                for line in lines.filter(|line| !line.trim().is_empty()) {
+                    out.push_str(&format!("{}  {}\n", indent, line));
+                }
+                index += 1;
+            }
+            out.push('\n');
+        }
+        "blockquote" => {
+            let mut body = String::new();
+            walk(element, &mut body, depth);
+            out.push_str("\n\n");
+            for line in collapse_blank_lines(&body).lines() {
+                out.push_str(&format!("> {}\n", line).replace("> \n", ">\n"));
+            }
+            out.push('\n');
+        }
+        _ => walk(element, out, depth),
+    }
+}
+
+fn wrap_inline(element: ElementRef, out: &mut String, depth: usize, marker: &str) {
+    let mut body = String::new();
+    walk(element, &mut body, depth);
+    let body = body.trim();
+    if !body.is_empty() {
+        out.push_str(&format!("{}{}{}", marker, body, marker));
+    }
+}
+
+fn inline_text(element: ElementRef) -> String {
+    collapse_whitespace(&element.text().collect::<String>())
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut last_was_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !last_was_space {
+                result.push(' ');
+            }
+            last_was_space = true;
+        } else {
+            result.push(c);
+            last_was_space = false;
+        }
+    }
+    result
+}
+
+fn collapse_blank_lines(text: &str) -> String {
+    let mut result = String::new();
+    let mut blank = 0;
+    let mut in_fence = false;
+    for line in text.lines() {
+        if in_fence {
+            result.push('\n');
+            result.push_str(line);
+            in_fence = !line.starts_with("```");
+            continue;
+        }
+        let line = line.trim_end();
+        if line.trim().is_empty() {
+            blank += 1;
+            continue;
+        }
+        if !result.is_empty() {
+            result.push_str(if blank > 0 { "\n\n" } else { "\n" });
+        }
+        let line = line.strip_prefix(' ').unwrap_or(line);
+        result.push_str(line);
+        in_fence = line.starts_with("```");
+        blank = 0;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_to_markdown_paragraphs_and_links() {
+        let html = r#"<p>Hello <a href="https://example.com">world</a></p><p>Second<br>line</p>"#;
+        assert_eq!(html_to_markdown(html), "Hello [world](https://example.com)\n\nSecond\nline");
+    }
+
+    #[test]
+    fn test_html_to_markdown_preserves_code_blocks() {
+        let html = "<p>Try:</p><pre><code class=\"lang-rust\">fn main() {\n    println!(\"hi\");\n}\n</code></pre>";
+        let markdown = html_to_markdown(html);
+        assert!(markdown.contains("```rust\nfn main() {\n    println!(\"hi\");\n}\n```"), "got: {}", markdown);
+    }
+
+    #[test]
+    fn test_html_to_markdown_lists() {
+        let html = "<ul><li>one</li><li><b>two</b></li></ul><ol><li>first</li></ol>";
+        assert_eq!(html_to_markdown(html), "- one\n- **two**\n\n1. first");
+    }
+
+    #[test]
+    fn test_html_to_text() {
+        assert_eq!(html_to_text("<p>Hello   <b>there</b></p>\n<p>friend</p>"), "Hello there friend");
+    }
+}
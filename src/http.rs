@@ -0,0 +1,388 @@
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use eyre::Result;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, RETRY_AFTER};
+use reqwest::{Method, RequestBuilder, Response, ResponseBuilderExt, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::cache::{cache_key, normalize_url, Cache, CacheEntry};
+use crate::error::Error;
+use crate::ratelimit::{Quota, RateLimitConfig, RateLimits};
+
+const DEFAULT_USER_AGENT: &str = concat!("Mozilla/5.0 (compatible; ", env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"), ")");
+
+/// The `http:` config section shared by every provider.
+#[derive(Deserialize, Debug, Clone)]
+pub struct HttpConfig {
+    #[serde(default = "default_connect_timeout")]
+    pub connect_timeout_secs: u64,
+    #[serde(default = "default_read_timeout")]
+    pub read_timeout_secs: u64,
+    #[serde(default = "default_timeout")]
+    pub timeout_secs: u64,
+    pub proxy: Option<String>,
+    #[serde(default = "default_user_agent")]
+    pub user_agent: String,
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    #[serde(default = "default_backoff_ms")]
+    pub backoff_ms: u64,
+    #[serde(default = "default_max_backoff")]
+    pub max_backoff_secs: u64,
+    #[serde(default = "default_max_response_mb")]
+    pub max_response_mb: u64,
+    #[serde(default)]
+    pub domains: HashMap<String, DomainConfig>,
+    #[serde(default)]
+    pub rate_limits: RateLimitConfig,
+}
+
+/// Extra headers and cookies sent to a domain and its subdomains.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct DomainConfig {
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub cookies: HashMap<String, String>,
+}
+
+fn default_connect_timeout() -> u64 { 10 }
+fn default_read_timeout() -> u64 { 30 }
+fn default_timeout() -> u64 { 120 }
+fn default_user_agent() -> String { DEFAULT_USER_AGENT.to_string() }
+fn default_max_retries() -> u32 { 3 }
+fn default_backoff_ms() -> u64 { 500 }
+fn default_max_backoff() -> u64 { 60 }
+fn default_max_response_mb() -> u64 { 20 }
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        serde_yaml::from_str("{}").expect("defaults deserialize")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    config: HttpConfig,
+    limits: Arc<RateLimits>,
+    cache: Option<Arc<Cache>>,
+}
+
+impl HttpClient {
+    pub fn new(config: &HttpConfig) -> Result<Self> {
+        debug!("HttpClient::new: config={:?}", config);
+        let mut builder = reqwest::Client::builder()
+            .user_agent(&config.user_agent)
+            .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+            .timeout(Duration::from_secs(config.timeout_secs));
+        if let Some(proxy) = &config.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)
+                .map_err(|e| Error::ConfigInvalid(format!("invalid http.proxy '{}': {}", proxy, e)))?);
+        }
+        Ok(HttpClient { client: builder.build()?, config: config.clone(), limits: Arc::new(RateLimits::new(&config.rate_limits)), cache: None })
+    }
+
+    pub fn with_cache(self, cache: Option<Cache>) -> Self {
+        HttpClient { cache: cache.map(Arc::new), ..self }
+    }
+
+    pub fn cache(&self) -> Option<&Cache> {
+        self.cache.as_deref()
+    }
+
+    /// Waits until `cost` units of an API's quota are available.
+    pub async fn acquire_quota(&self, quota: Quota, cost: f64) {
+        self.limits.acquire_quota(quota, cost).await;
+    }
+
+    pub fn max_response_bytes(&self) -> u64 {
+        self.config.max_response_mb * 1024 * 1024
+    }
+
+    fn domain_headers(&self, url: &str) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        let host = Url::parse(url).ok().and_then(|url| url.host_str().map(String::from)).unwrap_or_default();
+        let mut domains = self.config.domains.iter()
+            .filter(|(domain, _)| host == **domain || host.ends_with(&format!(".{}", domain)))
+            .collect::<Vec<_>>();
+        // More specific domains are applied last so they win
+        domains.sort_by_key(|(domain, _)| domain.len());
+        for (domain, domain_config) in domains {
+            let invalid = |e: &dyn std::fmt::Display| Error::ConfigInvalid(format!("invalid header for {}: {}", domain, e));
+            for (name, value) in &domain_config.headers {
+                headers.insert(
+                    HeaderName::from_bytes(name.as_bytes()).map_err(|e| invalid(&e))?,
+                    HeaderValue::from_str(value).map_err(|e| invalid(&e))?,
+                );
+            }
+            if !domain_config.cookies.is_empty() {
+                let mut cookies = domain_config.cookies.iter().map(|(name, value)| format!("{}={}", name, value)).collect::<Vec<_>>();
+                cookies.sort();
+                headers.insert(COOKIE, HeaderValue::from_str(&cookies.join("; ")).map_err(|e| invalid(&e))?);
+            }
+        }
+        Ok(headers)
+    }
+
+    pub fn request(&self, method: Method, url: &str) -> Result<RequestBuilder> {
+        Ok(self.client.request(method, url).headers(self.domain_headers(url)?))
+    }
+
+    pub async fn get(&self, url: &str) -> Result<Response> {
+        self.send(self.request(Method::GET, url)?).await
+    }
+
+    /// Like [`HttpClient::get`], but successful text responses are served from and stored in
+    /// the on-disk cache under `provider`'s TTL.
+    pub async fn get_cached(&self, provider: &str, url: &str) -> Result<Response> {
+        self.get_cached_with_quota(provider, url, None).await
+    }
+
+    /// Like [`HttpClient::get_cached`], but spends `cost` units of `quota` when the
+    /// request actually goes out; cache hits are free.
+    pub async fn get_metered(&self, provider: &str, url: &str, quota: Quota, cost: f64) -> Result<Response> {
+        self.get_cached_with_quota(provider, url, Some((quota, cost))).await
+    }
+
+    async fn get_cached_with_quota(&self, provider: &str, url: &str, quota: Option<(Quota, f64)>) -> Result<Response> {
+        let cache = self.cache.as_ref();
+        let key = cache_key(&[provider, &normalize_url(provider, url)]);
+        if let Some(entry) = cache.and_then(|cache| cache.get(provider, &key)) {
+            return cached_response(url, entry.status, &entry.content_type, entry.body.into_bytes());
+        }
+        if let Some((quota, cost)) = quota {
+            self.acquire_quota(quota, cost).await;
+        }
+        let response = self.get(url).await?;
+        let Some(cache) = cache else {
+            return Ok(response);
+        };
+        if !response.status().is_success() {
+            return Ok(response);
+        }
+        let status = response.status().as_u16();
+        let content_type = response.headers().get(CONTENT_TYPE).and_then(|value| value.to_str().ok()).unwrap_or_default().to_string();
+        let body = self.bytes(response).await?;
+        if let Ok(text) = std::str::from_utf8(&body) {
+            let entry = CacheEntry { provider: provider.to_string(), stored_at: Utc::now().timestamp(), status, content_type: content_type.clone(), body: text.to_string() };
+            cache.put(&key, &entry);
+        }
+        cached_response(url, status, &content_type, body)
+    }
+
+    pub async fn head(&self, url: &str) -> Result<Response> {
+        self.send(self.request(Method::HEAD, url)?).await
+    }
+
+    /// Sends `request`, retrying connection failures, timeouts, 429 and 5xx responses
+    /// with exponential backoff (or the server's `Retry-After`). The last response is
+    /// returned as-is, so callers still decide what a non-success status means.
+    pub async fn send(&self, mut request: RequestBuilder) -> Result<Response> {
+        let read_timeout = Duration::from_secs(self.config.read_timeout_secs);
+        let url = request.try_clone().and_then(|r| r.build().ok()).map(|r| r.url().clone());
+        let host = url.as_ref().and_then(|url| url.host_str()).unwrap_or_default().to_string();
+        let url = url.map(String::from).unwrap_or_default();
+        let mut attempt = 0;
+        loop {
+            self.limits.acquire_host(&host).await;
+            // Requests with streaming bodies cannot be cloned, so they are only sent once
+            let next = request.try_clone().filter(|_| attempt < self.config.max_retries);
+            let result = tokio::time::timeout(read_timeout, request.send()).await;
+
+            // Some(delay) means the attempt should be retried, after the server's delay if it gave one
+            let retry = match &result {
+                Ok(Ok(response)) if is_retryable_status(response.status()) => Some(retry_after(response)),
+                Ok(Ok(_)) => None,
+                Ok(Err(e)) if e.is_timeout() || e.is_connect() => Some(None),
+                Ok(Err(_)) => None,
+                Err(_) => Some(None),
+            };
+            match (retry, next) {
+                (Some(retry_after), Some(next)) => {
+                    let delay = self.backoff(attempt, retry_after);
+                    warn!("Retrying {} in {:?} (attempt {} of {})", url, delay, attempt + 1, self.config.max_retries);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                    request = next;
+                }
+                _ => {
+                    return match result {
+                        Ok(response) => Ok(response?),
+                        Err(_) => Err(Error::FetchFailed { url, status: None, message: "read timed out".to_string() }.into()),
+                    };
+                }
+            }
+        }
+    }
+
+    fn backoff(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        let max = Duration::from_secs(self.config.max_backoff_secs);
+        let exponential = Duration::from_millis(self.config.backoff_ms.saturating_mul(1 << attempt.min(16)));
+        retry_after.unwrap_or(exponential).min(max)
+    }
+
+    /// Reads the body, failing once it grows past `max_response_mb` or stalls longer than the read timeout.
+    pub async fn bytes(&self, response: Response) -> Result<Vec<u8>> {
+        self.bytes_limited(response, self.max_response_bytes()).await
+    }
+
+    pub async fn bytes_limited(&self, mut response: Response, limit: u64) -> Result<Vec<u8>> {
+        let url = response.url().to_string();
+        let too_large = || Error::ResponseTooLarge { url: url.clone(), limit };
+        if response.headers().get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok()?.parse::<u64>().ok())
+            .is_some_and(|length| length > limit)
+        {
+            return Err(too_large().into());
+        }
+        let read_timeout = Duration::from_secs(self.config.read_timeout_secs);
+        let mut body = Vec::new();
+        loop {
+            let chunk = tokio::time::timeout(read_timeout, response.chunk()).await
+                .map_err(|_| Error::FetchFailed { url: url.clone(), status: None, message: "read timed out".to_string() })??;
+            let Some(chunk) = chunk else { break };
+            if body.len() as u64 + chunk.len() as u64 > limit {
+                return Err(too_large().into());
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+
+    pub async fn text(&self, response: Response) -> Result<String> {
+        Ok(String::from_utf8_lossy(&self.bytes(response).await?).into_owned())
+    }
+
+    pub async fn json<T: DeserializeOwned>(&self, response: Response) -> Result<T> {
+        Ok(serde_json::from_slice(&self.bytes(response).await?)?)
+    }
+}
+
+// Rebuilds a response whose body has already been read, so callers can treat hits and misses alike
+fn cached_response(url: &str, status: u16, content_type: &str, body: Vec<u8>) -> Result<Response> {
+    let response = http::Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, content_type)
+        .url(Url::parse(url)?)
+        .body(body)?;
+    Ok(Response::from(response))
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+fn retry_after(response: &Response) -> Option<Duration> {
+    response.headers().get(RETRY_AFTER).and_then(|value| value.to_str().ok()).and_then(parse_retry_after)
+}
+
+/// Percent-encodes `value` for use in a query string.
+pub fn urlencode(value: &str) -> String {
+    Url::parse_with_params("http://x/", &[("u", value)])
+        .map(|url| url.query().unwrap_or_default().trim_start_matches("u=").to_string())
+        .unwrap_or_default()
+}
+
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::CacheConfig;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn test_client(yaml: &str) -> HttpClient {
+        let config: HttpConfig = serde_yaml::from_str(yaml).unwrap();
+        HttpClient::new(&config).unwrap()
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[tokio::test]
+    async fn test_retries_on_503_honoring_retry_after() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/flaky"))
+            .respond_with(ResponseTemplate::new(503).insert_header("retry-after", "0"))
+            .up_to_n_times(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/flaky"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .mount(&server)
+            .await;
+        let client = test_client("max_retries: 3\nbackoff_ms: 1");
+        let response = client.get(&format!("{}/flaky", server.uri())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(client.text(response).await.unwrap(), "ok");
+    }
+
+    #[tokio::test]
+    async fn test_domain_headers_cookies_and_user_agent() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header("x-api-key", "secret"))
+            .and(header("cookie", "a=1; b=2"))
+            .and(header("user-agent", "test-agent"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+        let client = test_client(
+            "user_agent: test-agent\ndomains:\n  127.0.0.1:\n    headers:\n      x-api-key: secret\n    cookies:\n      b: \"2\"\n      a: \"1\"\n"
+        );
+        let response = client.get(&server.uri()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_get_cached_serves_repeat_requests_from_disk() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(r#"{"title":"cached"}"#, "application/json"))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let dir = tempfile::tempdir().unwrap();
+        let cache_config = CacheConfig { dir: Some(dir.path().to_path_buf()), ..CacheConfig::default() };
+        let client = test_client("{}").with_cache(Cache::open(&cache_config));
+        for _ in 0..2 {
+            let response = client.get_cached("wikipedia", &format!("{}/api?key=secret", server.uri())).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body: serde_json::Value = client.json(response).await.unwrap();
+            assert_eq!(body["title"], "cached");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_response_size() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("x".repeat(2048)))
+            .mount(&server)
+            .await;
+        let client = test_client("{}");
+        let response = client.get(&server.uri()).await.unwrap();
+        let err = client.bytes_limited(response, 1024).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<Error>(), Some(Error::ResponseTooLarge { .. })));
+    }
+}
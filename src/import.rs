@@ -0,0 +1,405 @@
+use log::{debug, info};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use eyre::{eyre, Result};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::batch::{run_captures, BatchResult};
+use crate::browser::{self, HistoryFilter};
+use crate::capture::Capture;
+use crate::check::collect_notes;
+use crate::filename::sanitize_filename;
+use crate::html::html_to_text;
+use crate::{readlater, vault, Config};
+
+lazy_static! {
+    static ref TAG_REGEX: Regex = Regex::new(r"(?is)<(/?)([a-z0-9]+)([^>]*)>").expect("valid regex");
+    static ref ATTR_REGEX: Regex = Regex::new(r#"(?is)([a-z_:-]+)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#).expect("valid regex");
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FolderMapping {
+    /// Each folder in a bookmark's path becomes a tag; notes go to the matching rule's folder.
+    #[default]
+    Tags,
+    /// Notes go to `import.folder` followed by the bookmark's folder path.
+    Subfolders,
+    /// Folder paths are dropped.
+    Ignore,
+}
+
+impl std::str::FromStr for FolderMapping {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "tags" => Ok(FolderMapping::Tags),
+            "subfolders" => Ok(FolderMapping::Subfolders),
+            "ignore" => Ok(FolderMapping::Ignore),
+            other => Err(format!("unknown folder mapping '{}', expected tags, subfolders or ignore", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ImportFormat {
+    #[default]
+    Auto,
+    /// The HTML bookmarks export every browser can write.
+    Netscape,
+    /// A Firefox profile directory or its `places.sqlite`.
+    Firefox,
+    /// A Chromium (Chrome, Edge, Brave) profile directory or its `Bookmarks` file.
+    Chromium,
+    /// Pocket's HTML or CSV export.
+    Pocket,
+    /// Raindrop.io's CSV export.
+    Raindrop,
+    /// Pinboard's JSON export.
+    Pinboard,
+    /// Instapaper's CSV export.
+    Instapaper,
+}
+
+impl std::str::FromStr for ImportFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "auto" => Ok(ImportFormat::Auto),
+            "netscape" | "html" => Ok(ImportFormat::Netscape),
+            "firefox" => Ok(ImportFormat::Firefox),
+            "chromium" | "chrome" => Ok(ImportFormat::Chromium),
+            "pocket" => Ok(ImportFormat::Pocket),
+            "raindrop" => Ok(ImportFormat::Raindrop),
+            "pinboard" => Ok(ImportFormat::Pinboard),
+            "instapaper" => Ok(ImportFormat::Instapaper),
+            other => Err(format!(
+                "unknown import format '{}', expected auto, netscape, firefox, chromium, pocket, raindrop, pinboard or instapaper", other
+            )),
+        }
+    }
+}
+
+/// Guesses the format from a profile directory's contents or the file's first bytes.
+pub fn detect_format(path: &Path) -> Result<ImportFormat> {
+    if path.is_dir() {
+        return if path.join("places.sqlite").exists() {
+            Ok(ImportFormat::Firefox)
+        } else if path.join("Bookmarks").exists() {
+            Ok(ImportFormat::Chromium)
+        } else {
+            Err(eyre!("{} is not a Firefox or Chromium profile directory", path.display()))
+        };
+    }
+    let mut head = Vec::new();
+    std::fs::File::open(path)
+        .and_then(|file| file.take(512).read_to_end(&mut head))
+        .map_err(|e| eyre!("Failed to read {}: {}", path.display(), e))?;
+    if head.starts_with(b"SQLite format 3") {
+        return Ok(ImportFormat::Firefox);
+    }
+    let head = String::from_utf8_lossy(&head).trim_start_matches('\u{feff}').trim_start().to_lowercase();
+    let header = head.lines().next().unwrap_or_default();
+    if head.starts_with('{') {
+        Ok(ImportFormat::Chromium)
+    } else if head.starts_with('[') {
+        Ok(ImportFormat::Pinboard)
+    } else if head.contains("time_added") || head.contains("<title>pocket export</title>") {
+        Ok(ImportFormat::Pocket)
+    } else if head.starts_with('<') {
+        Ok(ImportFormat::Netscape)
+    } else if header.starts_with("url,title,selection") {
+        Ok(ImportFormat::Instapaper)
+    } else if header.contains("excerpt") && header.contains("highlights") {
+        Ok(ImportFormat::Raindrop)
+    } else {
+        Err(eyre!("Could not tell what kind of export {} is; pass --format", path.display()))
+    }
+}
+
+/// Reads the bookmarks at `path`. `history` only applies to Firefox profiles.
+pub fn read_bookmarks(path: &Path, format: ImportFormat, history: Option<&HistoryFilter>) -> Result<Vec<Bookmark>> {
+    debug!("read_bookmarks: path={} format={:?} history={:?}", path.display(), format, history);
+    let format = match format {
+        ImportFormat::Auto => detect_format(path)?,
+        format => format,
+    };
+    if history.is_some() && format != ImportFormat::Firefox {
+        return Err(eyre!("History can only be imported from a Firefox profile"));
+    }
+    let read = || std::fs::read_to_string(path).map_err(|e| eyre!("Failed to read {}: {}", path.display(), e));
+    match format {
+        ImportFormat::Firefox => browser::read_firefox(path, history),
+        ImportFormat::Chromium => browser::read_chromium(path),
+        ImportFormat::Pocket => readlater::parse_pocket(&read()?),
+        ImportFormat::Raindrop => readlater::parse_raindrop(&read()?),
+        ImportFormat::Pinboard => readlater::parse_pinboard(&read()?),
+        ImportFormat::Instapaper => readlater::parse_instapaper(&read()?),
+        ImportFormat::Netscape | ImportFormat::Auto => Ok(parse_netscape(&read()?)),
+    }
+}
+
+/// The `import:` config section.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ImportConfig {
+    #[serde(default)]
+    pub folders: FolderMapping,
+    #[serde(default = "default_folder")]
+    pub folder: String,
+    #[serde(default = "default_skip_existing")]
+    pub skip_existing: bool,
+}
+
+fn default_folder() -> String { "Bookmarks".to_string() }
+fn default_skip_existing() -> bool { true }
+
+impl Default for ImportConfig {
+    fn default() -> Self {
+        serde_yaml::from_str("{}").expect("defaults deserialize")
+    }
+}
+
+/// A bookmark read from an export, before it is turned into a [`Capture`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Bookmark {
+    pub url: String,
+    pub title: Option<String>,
+    pub folders: Vec<String>,
+    pub added: Option<DateTime<Utc>>,
+    pub tags: Vec<String>,
+    pub notes: Option<String>,
+    pub highlights: Vec<String>,
+    /// Read-later services track whether an item was read; browsers don't.
+    pub read: Option<bool>,
+}
+
+/// Reads a Unix timestamp in seconds, milliseconds or microseconds, as browsers disagree.
+pub fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim().parse::<i64>().ok().filter(|value| *value > 0)?;
+    match value {
+        value if value > 100_000_000_000_000 => DateTime::from_timestamp_micros(value),
+        value if value > 100_000_000_000 => DateTime::from_timestamp_millis(value),
+        value => DateTime::from_timestamp(value, 0),
+    }
+}
+
+pub(crate) fn attributes(raw: &str) -> HashMap<String, String> {
+    ATTR_REGEX.captures_iter(raw)
+        .map(|caps| {
+            let value = caps.get(2).or(caps.get(3)).or(caps.get(4)).map_or("", |m| m.as_str());
+            (caps[1].to_lowercase(), html_to_text(value))
+        })
+        .collect()
+}
+
+pub(crate) fn split_tags(value: &str) -> Vec<String> {
+    value.split(',').map(str::trim).filter(|tag| !tag.is_empty()).map(String::from).collect()
+}
+
+/// Parses the Netscape bookmark file format that browsers export. `<H3>` headings name
+/// the folder whose `<DL>` list follows; `TAGS` attributes (Firefox, Pinboard) and `<DD>`
+/// descriptions are kept.
+pub fn parse_netscape(html: &str) -> Vec<Bookmark> {
+    debug!("parse_netscape: html.len={}", html.len());
+    let mut bookmarks: Vec<Bookmark> = Vec::new();
+    // One entry per open <DL>, holding the folder name it belongs to (if any)
+    let mut lists: Vec<Option<String>> = Vec::new();
+    let mut heading: Option<String> = None;
+    let mut link: Option<Bookmark> = None;
+    let mut description = false;
+    let mut position = 0;
+    for caps in TAG_REGEX.captures_iter(html) {
+        let whole = caps.get(0).expect("match");
+        let text = || html_to_text(&html[position..whole.start()]);
+        let closing = !caps[1].is_empty();
+        let name = caps[2].to_lowercase();
+        // A <DD> runs until the next tag and describes the bookmark just before it
+        if description && name != "dd" {
+            description = false;
+            if let Some(bookmark) = bookmarks.last_mut() {
+                bookmark.notes = Some(text()).filter(|notes| !notes.is_empty());
+            }
+        }
+        match (name.as_str(), closing) {
+            ("h3", false) => heading = Some(String::new()),
+            ("h3", true) => {
+                if let Some(name) = heading.as_mut() {
+                    name.push_str(&text());
+                }
+            }
+            ("dl", false) => lists.push(heading.take().map(|name| name.trim().to_string())),
+            ("dl", true) => {
+                lists.pop();
+            }
+            ("a", false) => {
+                let attrs = attributes(&caps[3]);
+                link = attrs.get("href").map(|href| Bookmark {
+                    url: href.trim().to_string(),
+                    folders: lists.iter().flatten().filter(|name| !name.is_empty()).cloned().collect(),
+                    added: attrs.get("add_date").and_then(|value| parse_timestamp(value)),
+                    tags: attrs.get("tags").map(|tags| split_tags(tags)).unwrap_or_default(),
+                    ..Bookmark::default()
+                });
+            }
+            ("dd", false) => description = true,
+            ("a", true) => {
+                if let Some(mut bookmark) = link.take() {
+                    bookmark.title = Some(text().trim().to_string()).filter(|title| !title.is_empty());
+                    bookmarks.push(bookmark);
+                }
+            }
+            _ => {}
+        }
+        position = whole.end();
+    }
+    bookmarks
+}
+
+/// Turns a bookmark into a capture, mapping its folder path according to `config`.
+pub fn to_capture(bookmark: &Bookmark, config: &ImportConfig) -> Capture {
+    let mut capture = Capture {
+        url: bookmark.url.clone(),
+        title: bookmark.title.clone(),
+        selection: Some(bookmark.highlights.join("\n\n")).filter(|highlights| !highlights.is_empty()),
+        tags: bookmark.tags.clone(),
+        saved: bookmark.added,
+        notes: bookmark.notes.clone(),
+        fields: bookmark.read.map(|read| ("read".to_string(), serde_yaml::Value::Bool(read))).into_iter().collect(),
+        ..Capture::default()
+    };
+    match config.folders {
+        FolderMapping::Tags => capture.tags.extend(bookmark.folders.iter().cloned()),
+        FolderMapping::Subfolders => {
+            let folder = std::iter::once(config.folder.as_str())
+                .chain(bookmark.folders.iter().map(String::as_str))
+                .map(sanitize_filename)
+                .filter(|segment| !segment.is_empty() && segment != "." && segment != "..")
+                .collect::<Vec<_>>()
+                .join("/");
+            capture.folder = Some(folder).filter(|folder| !folder.is_empty());
+        }
+        FolderMapping::Ignore => {}
+    }
+    capture
+}
+
+fn dedup_key(url: &str) -> String {
+    url.trim().trim_end_matches('/').to_string()
+}
+
+/// Drops non-web URLs and repeats, and (with `import.skip_existing`) URLs already saved in the vault.
+/// Returns the bookmarks to import and how many were skipped.
+pub fn filter_bookmarks(bookmarks: Vec<Bookmark>, config: &Config) -> (Vec<Bookmark>, usize) {
+    let mut seen = HashSet::new();
+    if config.import.skip_existing {
+        if let Ok(root) = vault::vault_root(&config.vault) {
+            seen.extend(collect_notes(&root).into_iter().map(|(_, url)| dedup_key(&url)));
+        }
+    }
+    let total = bookmarks.len();
+    let kept = bookmarks.into_iter()
+        .filter(|bookmark| bookmark.url.starts_with("http://") || bookmark.url.starts_with("https://"))
+        .filter(|bookmark| seen.insert(dedup_key(&bookmark.url)))
+        .collect::<Vec<_>>();
+    let skipped = total - kept.len();
+    (kept, skipped)
+}
+
+/// Runs the bookmarks through the capture pipeline. Returns the results and the number skipped.
+pub async fn import_bookmarks<F>(bookmarks: Vec<Bookmark>, config: &Config, concurrency: usize, progress: F) -> (Vec<BatchResult>, usize)
+where
+    F: FnMut(usize, usize, &BatchResult),
+{
+    debug!("import_bookmarks: bookmarks.len={} concurrency={}", bookmarks.len(), concurrency);
+    let (captures, skipped) = import_captures(bookmarks, config);
+    (run_captures(&captures, config, concurrency, progress).await, skipped)
+}
+
+/// The captures an import would run, plus how many bookmarks were skipped.
+pub fn import_captures(bookmarks: Vec<Bookmark>, config: &Config) -> (Vec<Capture>, usize) {
+    let (bookmarks, skipped) = filter_bookmarks(bookmarks, config);
+    info!("Importing {} bookmarks, skipping {} duplicates or unsupported URLs", bookmarks.len(), skipped);
+    (bookmarks.iter().map(|bookmark| to_capture(bookmark, &config.import)).collect(), skipped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{fixture, mock_config};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const EXPORT: &str = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<META HTTP-EQUIV="Content-Type" CONTENT="text/html; charset=UTF-8">
+<TITLE>Bookmarks</TITLE>
+<H1>Bookmarks</H1>
+<DL><p>
+    <DT><H3 ADD_DATE="1600000000" PERSONAL_TOOLBAR_FOLDER="true">Bookmarks bar</H3>
+    <DL><p>
+        <DT><H3>Dev &amp; Tools</H3>
+        <DL><p>
+            <DT><A HREF="https://www.youtube.com/watch?v=dQw4w9WgXcQ" ADD_DATE="1500000000" TAGS="music,classic">Rick &amp; Roll</A>
+        </DL><p>
+        <DT><A HREF="https://example.com/a" ADD_DATE="1500000000000">A</A>
+        <DD>Read this first
+    </DL><p>
+    <DT><A HREF="javascript:void(0)">Bookmarklet</A>
+    <DT><A HREF="https://www.youtube.com/watch?v=dQw4w9WgXcQ/">Again</A>
+</DL><p>
+"#;
+
+    #[test]
+    fn test_parse_netscape_folders_dates_and_tags() {
+        let bookmarks = parse_netscape(EXPORT);
+        assert_eq!(bookmarks.len(), 4);
+        assert_eq!(bookmarks[0].title.as_deref(), Some("Rick & Roll"));
+        assert_eq!(bookmarks[0].folders, vec!["Bookmarks bar", "Dev & Tools"]);
+        assert_eq!(bookmarks[0].tags, vec!["music", "classic"]);
+        assert_eq!(bookmarks[0].added, DateTime::from_timestamp(1_500_000_000, 0));
+        assert_eq!(bookmarks[1].folders, vec!["Bookmarks bar"]);
+        assert_eq!(bookmarks[1].added, bookmarks[0].added, "millisecond timestamps are recognized");
+        assert_eq!((bookmarks[0].notes.as_deref(), bookmarks[1].notes.as_deref()), (None, Some("Read this first")));
+        assert!(bookmarks[2].folders.is_empty());
+    }
+
+    #[test]
+    fn test_to_capture_folder_mappings() {
+        let bookmark = Bookmark { url: "https://a".into(), folders: vec!["Bar".into(), "Dev/Ops".into()], ..Bookmark::default() };
+        let tags = to_capture(&bookmark, &ImportConfig::default());
+        assert_eq!(tags.tags, vec!["Bar", "Dev/Ops"]);
+        assert_eq!(tags.folder, None);
+        let config = ImportConfig { folders: FolderMapping::Subfolders, ..ImportConfig::default() };
+        assert_eq!(to_capture(&bookmark, &config).folder.as_deref(), Some("Bookmarks/Bar/Dev Ops"));
+    }
+
+    #[tokio::test]
+    async fn test_import_bookmarks_dedups_and_dates_notes() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/videos"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(fixture("youtube_video.json"), "application/json"))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let vault = tempfile::tempdir().unwrap();
+        let config = mock_config(&server, vault.path());
+        let bookmarks = parse_netscape(EXPORT).into_iter().filter(|bookmark| bookmark.url.contains("youtube")).collect();
+
+        let (results, skipped) = import_bookmarks(bookmarks, &config, 2, |_, _, _| {}).await;
+        assert_eq!((results.len(), skipped), (1, 1));
+        let content = std::fs::read_to_string(results[0].outcome.as_ref().unwrap()).unwrap();
+        assert!(content.contains("date: 2017-07-13\n"), "got: {}", content);
+        assert!(content.contains("  - dev---tools\n"), "got: {}", content);
+
+        let again = parse_netscape(EXPORT).into_iter().filter(|bookmark| bookmark.url.contains("youtube")).collect();
+        let (results, skipped) = import_bookmarks(again, &config, 2, |_, _, _| {}).await;
+        assert_eq!((results.len(), skipped), (0, 2), "notes already in the vault are skipped");
+    }
+}
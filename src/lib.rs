@@ -0,0 +1,1087 @@
+//! Create Obsidian notes from URLs.
+//!
+//! A URL is matched against the `links:` rules of a [`Config`], routed to a provider
+//! (YouTube, social posts, PDFs, podcasts, Wikipedia, packages, Stack Exchange, oEmbed,
+//! products or a generic web page) and turned into a [`Note`]. The note can then be
+//! rendered with [`render_note`] or written into the vault with [`create_markdown_file`].
+//!
+//! ```no_run
+//! # async fn run() -> eyre::Result<()> {
+//! let config = obsidian_link::load_config("~/.config/obsidian-link/obsidian-link.yml".into())?;
+//! let note = obsidian_link::fetch_note("https://www.youtube.com/watch?v=dQw4w9WgXcQ", &config).await?;
+//! println!("{}", obsidian_link::render_note(&note, &config.frontmatter));
+//! # Ok(())
+//! # }
+//! ```
+
+use log::{debug, error, warn};
+use std::path::{Path, PathBuf};
+use std::env;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use chrono::prelude::*;
+use chrono_tz::Tz;
+use chrono::format::StrftimeItems;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use eyre::{eyre, Result};
+use lazy_static::lazy_static;
+
+pub mod batch;
+pub mod browser;
+pub mod cache;
+pub mod capture;
+pub mod check;
+pub mod error;
+pub mod filename;
+pub mod html;
+pub mod http;
+pub mod import;
+pub mod oembed;
+pub mod package;
+pub mod pdf;
+pub mod podcast;
+pub mod product;
+pub mod queue;
+pub mod serve;
+pub mod ratelimit;
+pub mod readlater;
+pub mod social;
+pub mod stackexchange;
+pub mod update;
+pub mod vault;
+pub mod watch;
+pub mod wikipedia;
+
+use error::Error;
+use filename::Naming;
+use http::{HttpClient, HttpConfig};
+use pdf::PdfOptions;
+use ratelimit::Quota;
+use social::EmbedStyle;
+
+const TIMEZONE: &str = "America/Los_Angeles";
+
+lazy_static! {
+    static ref RESOLUTIONS: HashMap<&'static str, (usize, usize)> = {
+        let mut m = HashMap::new();
+        m.insert("nHD", (640, 360));
+        m.insert("FWVGA", (854, 480));
+        m.insert("qHD", (960, 540));
+        m.insert("SD", (1280, 720));
+        m.insert("WXGA", (1366, 768));
+        m.insert("HD+", (1600, 900));
+        m.insert("FHD", (1920, 1080));
+        m.insert("WQHD", (2560, 1440));
+        m.insert("QHD+", (3200, 1800));
+        m.insert("4K", (3840, 2160));
+        m.insert("5K", (5120, 2880));
+        m.insert("8K", (7680, 4320));
+        m.insert("16K", (15360, 8640));
+        m
+    };
+    static ref SHORTS_RESOLUTIONS: HashMap<&'static str, (usize, usize)> = {
+        let mut m = HashMap::new();
+        m.insert("480p", (480, 854));
+        m.insert("720p", (720, 1280));
+        m.insert("1080p", (1080, 1920));
+        m.insert("1440p", (1440, 2560));
+        m.insert("2160p", (2160, 3840));
+        m
+    };
+}
+
+/// Top-level configuration, usually loaded from `obsidian-link.yml` with [`load_config`].
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    pub vault: PathBuf,
+    pub frontmatter: Frontmatter,
+    pub links: Vec<Link>,
+    #[serde(default)]
+    pub api: Api,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub batch: batch::BatchConfig,
+    #[serde(default)]
+    pub cache: cache::CacheConfig,
+    #[serde(default)]
+    pub queue: queue::QueueConfig,
+    #[serde(default)]
+    pub serve: serve::ServeConfig,
+    #[serde(default)]
+    pub watch: watch::WatchConfig,
+    #[serde(default)]
+    pub import: import::ImportConfig,
+    #[serde(skip)]
+    client: OnceLock<HttpClient>,
+}
+
+impl Config {
+    /// The HTTP client shared by every request made with this config, built on first use.
+    pub fn http(&self) -> Result<&HttpClient> {
+        if let Some(client) = self.client.get() {
+            return Ok(client);
+        }
+        let client = HttpClient::new(&self.http)?.with_cache(cache::Cache::open(&self.cache));
+        Ok(self.client.get_or_init(|| client))
+    }
+}
+
+/// Base URLs and keys for the YouTube Data and OpenAI APIs. Keys fall back to the
+/// `YOUTUBE_API_KEY` and `CHATGPT_API_KEY` environment variables; overriding the
+/// base URLs points the tool (or its tests) at another server.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Api {
+    #[serde(default = "default_youtube_base")]
+    pub youtube_base: String,
+    #[serde(default = "default_openai_base")]
+    pub openai_base: String,
+    pub youtube_api_key: Option<String>,
+    pub openai_api_key: Option<String>,
+}
+
+fn default_youtube_base() -> String {
+    "https://www.googleapis.com/youtube/v3".to_string()
+}
+
+fn default_openai_base() -> String {
+    "https://api.openai.com/v1".to_string()
+}
+
+impl Default for Api {
+    fn default() -> Self {
+        Api {
+            youtube_base: default_youtube_base(),
+            openai_base: default_openai_base(),
+            youtube_api_key: None,
+            openai_api_key: None,
+        }
+    }
+}
+
+impl Api {
+    pub fn youtube_api_key(&self) -> Result<String> {
+        api_key(self.youtube_api_key.as_ref(), "YOUTUBE_API_KEY")
+    }
+
+    pub fn openai_api_key(&self) -> Result<String> {
+        api_key(self.openai_api_key.as_ref(), "CHATGPT_API_KEY")
+    }
+}
+
+fn api_key(configured: Option<&String>, env_var: &str) -> Result<String> {
+    match configured {
+        Some(key) => Ok(key.clone()),
+        None => env::var(env_var)
+            .map_err(|_| Error::ConfigInvalid(format!("{} is not set in the config or environment", env_var)).into()),
+    }
+}
+
+/// Default frontmatter values; `None` fields are filled in at render time.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Frontmatter {
+    pub date: Option<String>,
+    pub day: Option<String>,
+    pub time: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub url: Option<String>,
+    pub author: Option<String>,
+    #[serde(rename = "type")]
+    pub note_type: Option<String>,
+}
+
+/// A URL rule from the `links:` section; the first matching rule (with `default` last) decides how a URL is handled.
+#[derive(Deserialize, Debug)]
+pub struct Link {
+    pub name: String,
+    pub regex: String,
+    pub resolution: String,
+    pub folder: String,
+    #[serde(default)]
+    pub unroll: bool,
+    pub embed: Option<String>,
+    pub max_size_mb: Option<u64>,
+    pub pages: Option<u32>,
+    pub attachments: Option<String>,
+    #[serde(default)]
+    pub feeds: HashMap<String, String>,
+    #[serde(default)]
+    pub outline: bool,
+    pub filename: Option<String>,
+    pub max_length: Option<usize>,
+    pub on_collision: Option<String>,
+}
+
+/// Snippet metadata returned by the YouTube Data API.
+#[derive(Debug)]
+pub struct VideoMetadata {
+    pub id: String,
+    pub title: String,
+    pub description: String,
+    pub channel: String,
+    pub published_at: String,
+    pub tags: Vec<String>,
+}
+
+/// Everything extracted for a URL, ready to be rendered with [`render_note`] or written with [`create_markdown_file`].
+#[derive(Debug, Default)]
+pub struct Note {
+    pub id: String,
+    pub title: String,
+    pub description: String,
+    pub embed_code: String,
+    pub url: String,
+    pub folder: String,
+    pub author: String,
+    pub tags: Vec<String>,
+    pub note_type: Option<String>,
+    pub fields: Vec<(String, serde_yaml::Value)>,
+    pub attachments: Vec<Attachment>,
+    /// Markdown placed after the managed block, so `update` never rewrites it.
+    pub annotations: String,
+}
+
+/// A binary file (e.g. a downloaded PDF) written next to the note, relative to the vault root.
+pub struct Attachment {
+    pub folder: String,
+    pub file_name: String,
+    pub bytes: Vec<u8>,
+}
+
+impl std::fmt::Debug for Attachment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Attachment")
+            .field("folder", &self.folder)
+            .field("file_name", &self.file_name)
+            .field("bytes", &self.bytes.len())
+            .finish()
+    }
+}
+
+/// The provider a URL is routed to, with the options taken from its matching rule.
+pub enum LinkType {
+    Shorts(String, String, usize, usize),
+    YouTube(String, String, usize, usize),
+    WebLink(String, String, usize, usize),
+    Social(String, String, bool, EmbedStyle),
+    Pdf(String, String, PdfOptions),
+    Podcast(String, String, HashMap<String, String>),
+    Wikipedia(String, String, bool),
+    Package(String, String),
+    StackExchange(String, String),
+    OEmbed(String, String, usize, usize),
+    Product(String, String, usize, usize),
+}
+
+impl LinkType {
+    /// Classifies `url` using the first matching rule in `config`.
+    pub fn from_url(url: &str, config: &Config) -> Result<LinkType> {
+        debug!("LinkType::from_url: url={} config={:?}", url, config);
+        let link = find_rule(url, config)?;
+        let (width, height) = get_resolution(&link.name, config)?;
+        Ok(match link.name.as_str() {
+            "shorts" => LinkType::Shorts(url.to_string(), link.folder.clone(), width, height),
+            "youtube" => LinkType::YouTube(url.to_string(), link.folder.clone(), width, height),
+            "social" => LinkType::Social(url.to_string(), link.folder.clone(), link.unroll, EmbedStyle::from_config(link.embed.as_deref())?),
+            "pdf" => LinkType::Pdf(url.to_string(), link.folder.clone(), PdfOptions::from_link(link)),
+            "podcast" => LinkType::Podcast(url.to_string(), link.folder.clone(), link.feeds.clone()),
+            "wikipedia" => LinkType::Wikipedia(url.to_string(), link.folder.clone(), link.outline),
+            "package" => LinkType::Package(url.to_string(), link.folder.clone()),
+            "stackexchange" => LinkType::StackExchange(url.to_string(), link.folder.clone()),
+            "oembed" => LinkType::OEmbed(url.to_string(), link.folder.clone(), width, height),
+            "product" => LinkType::Product(url.to_string(), link.folder.clone(), width, height),
+            _ => LinkType::WebLink(url.to_string(), link.folder.clone(), width, height),
+        })
+    }
+}
+
+/// Returns the rule for `url`, falling back to the rule named `default`.
+pub fn find_rule<'a>(url: &str, config: &'a Config) -> Result<&'a Link> {
+    debug!("find_rule: url={}", url);
+    let mut default_link = None;
+
+    for link in &config.links {
+        let regex = Regex::new(&link.regex)
+            .map_err(|e| Error::ConfigInvalid(format!("rule '{}' has an invalid regex: {}", link.name, e)))?;
+        // Shows mapped in `feeds` are matched by host as well, so the regex only needs the directories
+        if regex.is_match(url) || podcast::has_mapped_feed(url, &link.feeds) {
+            if link.name == "default" {
+                default_link = Some(link);
+                continue;
+            }
+            return Ok(link);
+        }
+    }
+
+    default_link.ok_or_else(|| Error::NoMatchingRule(url.to_string()).into())
+}
+
+fn expanduser<T: AsRef<str>>(path: T) -> Result<PathBuf> {
+    let expanded_path_str = shellexpand::tilde(path.as_ref());
+    Ok(PathBuf::from(expanded_path_str.into_owned()))
+}
+
+fn today() -> (String, String, String) {
+    debug!("today");
+    date_parts(Utc::now())
+}
+
+/// Formats `timestamp` as the `(date, day, time)` frontmatter values in the configured timezone.
+pub fn date_parts(timestamp: DateTime<Utc>) -> (String, String, String) {
+    let tz: Tz = TIMEZONE.parse().expect("Invalid timezone");
+    let now = timestamp.with_timezone(&tz);
+
+    let date_format = StrftimeItems::new("%Y-%m-%d");
+    let day_format = StrftimeItems::new("%a");
+    let time_format = StrftimeItems::new("%H:%M");
+
+    let formatted_date = now.format_with_items(date_format).to_string();
+    let formatted_day = now.format_with_items(day_format).to_string();
+    let formatted_time = now.format_with_items(time_format).to_string();
+
+    (formatted_date, formatted_day, formatted_time)
+}
+
+fn get_resolution(link_name: &str, config: &Config) -> Result<(usize, usize)> {
+    debug!("get_resolution: link_name={} config={:?}", link_name, config);
+    let resolution_key = config.links.iter().find(|link| link.name == link_name)
+        .ok_or_else(|| Error::ConfigInvalid(format!("link type '{}' not found", link_name)))?.resolution.as_str(); // Convert to &str
+
+    match link_name {
+        "shorts" => SHORTS_RESOLUTIONS.get(resolution_key)
+            .copied()
+            .ok_or_else(|| Error::ConfigInvalid(format!("resolution '{}' not found for shorts", resolution_key)).into()),
+        _ => RESOLUTIONS.get(resolution_key)
+            .copied()
+            .ok_or_else(|| Error::ConfigInvalid(format!("resolution '{}' not found for {}", resolution_key, link_name)).into()),
+    }
+}
+
+/// Loads and parses the YAML config at `config_path` (`~` is expanded).
+pub fn load_config(config_path: PathBuf) -> Result<Config> {
+    debug!("load_config: config_path={}", config_path.display());
+    let config_path_str = config_path.to_str()
+        .ok_or_else(|| eyre!("Failed to convert config path to string"))?;
+    let config_path_expanded = expanduser(config_path_str)?;
+    let config_str = std::fs::read_to_string(config_path_expanded)
+        .map_err(|e| Error::ConfigInvalid(format!("failed to read config file: {}", e)))?;
+    parse_config(&config_str)
+}
+
+/// Parses a config from YAML text, e.g. one built in memory.
+pub fn parse_config(yaml: &str) -> Result<Config> {
+    debug!("parse_config: yaml.len={}", yaml.len());
+    let config: Config = serde_yaml::from_str(yaml)
+        .map_err(|e| Error::ConfigInvalid(format!("failed to parse config file: {}", e)))?;
+    Ok(config)
+}
+
+/// Extracts the video ID from any YouTube watch, short, embed or youtu.be URL.
+pub fn extract_video_id(url: &str) -> Result<String> {
+    debug!("extract_video_id: url={}", url);
+    let pattern = Regex::new(r#"(youtu\.be/|youtube\.com/(watch\?(.*&)?v=|(embed|v|shorts)/))([^?&">]+)"#)
+        .map_err(|e| eyre!("Failed to compile regex: {}", e))?;
+
+    pattern.captures(url)
+        .and_then(|caps| caps.get(5))
+        .map(|m| m.as_str().to_string())
+        .ok_or_else(|| eyre!("Failed to extract video ID from URL"))
+}
+
+/// Resolves where `note` would be written inside the vault, applying the filename template and collision strategy.
+pub fn note_path(note: &Note, vault_path: &Path, naming: &Naming) -> Result<PathBuf> {
+    debug!("note_path: note.title={} vault_path={} naming={:?}", note.title, vault_path.display(), naming);
+    let root = vault::vault_root(vault_path)?;
+    naming.resolve(&vault::safe_join(&root, &note.folder)?, note)
+}
+
+/// Reads the YAML frontmatter of an existing note, if it has any.
+pub fn read_frontmatter(path: &Path) -> Option<serde_yaml::Mapping> {
+    debug!("read_frontmatter: path={}", path.display());
+    let content = std::fs::read_to_string(path).ok()?;
+    let rest = content.strip_prefix("---\n")?;
+    let end = rest.find("\n---")?;
+    serde_yaml::from_str(&rest[..end]).ok()
+}
+
+/// Renders `note` as Markdown, including frontmatter and the tool-managed section.
+pub fn render_note(note: &Note, frontmatter: &Frontmatter) -> String {
+    debug!("render_note: note.title={}", note.title);
+    let frontmatter_str = format_frontmatter(frontmatter, note);
+    let mut content = format!(
+        "{}{}\n{}\n\n## Description\n{}\n{}\n",
+        frontmatter_str, update::MANAGED_START, note.embed_code, note.description, update::MANAGED_END
+    );
+    if !note.annotations.is_empty() {
+        content += &format!("\n{}\n", note.annotations);
+    }
+    content
+}
+
+/// Writes `note` and its attachments into the vault and returns the note's path.
+pub async fn create_markdown_file(note: &Note, vault_path: &Path, frontmatter: &Frontmatter, naming: &Naming) -> Result<PathBuf> {
+    debug!("create_markdown_file: note={:?} vault_path={} frontmatter={:?} naming={:?}", note, vault_path.display(), frontmatter, naming);
+    let root = vault::vault_root(vault_path)?;
+    let file_path = note_path(note, &root, naming)?;
+
+    for attachment in &note.attachments {
+        write_attachment(attachment, &root)?;
+    }
+
+    // Unless overwriting, `resolve` only returns an existing file when it already holds this URL,
+    // so refresh its managed block and keep everything the user added around it
+    let rendered = render_note(note, frontmatter);
+    let content = match std::fs::read_to_string(&file_path) {
+        Ok(existing) if naming.collision != filename::Collision::Overwrite => update::merge_note(&existing, &rendered),
+        _ => rendered,
+    };
+    vault::write_atomic(&root, &file_path, content.as_bytes())?;
+    Ok(file_path)
+}
+
+fn write_attachment(attachment: &Attachment, root: &Path) -> Result<PathBuf> {
+    debug!("write_attachment: attachment={:?} root={}", attachment, root.display());
+    let file_path = vault::safe_join(root, Path::new(&attachment.folder).join(&attachment.file_name))?;
+    // Another note may already embed a file by this name; only identical content may reuse it
+    match std::fs::read(&file_path) {
+        Ok(existing) if existing == attachment.bytes => return Ok(file_path),
+        Ok(_) => return Err(Error::Duplicate(file_path).into()),
+        Err(_) => {}
+    }
+    vault::write_atomic(root, &file_path, &attachment.bytes)?;
+    Ok(file_path)
+}
+
+/// Serializes `note` together with the rule name and target path, as printed by `--json`.
+pub fn note_to_json(note: &Note, rule: &str, path: &Path) -> Value {
+    let fields: serde_json::Map<String, Value> = note.fields.iter()
+        .map(|(key, value)| (key.clone(), serde_json::to_value(value).unwrap_or(Value::Null)))
+        .collect();
+    json!({
+        "rule": rule,
+        "path": path,
+        "title": note.title,
+        "url": note.url,
+        "folder": note.folder,
+        "author": note.author,
+        "tags": note.tags,
+        "type": note.note_type,
+        "fields": fields,
+        "description": note.description,
+        "embed_code": note.embed_code,
+        "attachments": note.attachments.iter().map(|a| json!({"folder": a.folder, "file_name": a.file_name, "size": a.bytes.len()})).collect::<Vec<_>>(),
+    })
+}
+
+/// Renders the frontmatter block for `note`, including its provider-specific fields.
+pub fn format_frontmatter(frontmatter: &Frontmatter, note: &Note) -> String {
+    debug!("format_frontmatter: frontmatter={:?} note.url={} note.author={} note.tags={:?}", frontmatter, note.url, note.author, note.tags);
+    let mut frontmatter_str = String::from("---\n");
+
+    let (current_date, current_day, current_time) = today();
+    frontmatter_str += &format!("date: {}\n", frontmatter.date.as_ref().unwrap_or(&current_date));
+    frontmatter_str += &format!("day: {}\n", frontmatter.day.as_ref().unwrap_or(&current_day));
+    frontmatter_str += &format!("time: {}\n", frontmatter.time.as_ref().unwrap_or(&current_time));
+
+    if let Some(note_type) = note.note_type.as_ref().or(frontmatter.note_type.as_ref()) {
+        frontmatter_str += &format!("type: {}\n", note_type);
+    }
+
+    let mut tags = frontmatter.tags.clone().unwrap_or_default();
+    for tag in &note.tags {
+        if !tags.contains(tag) {
+            tags.push(tag.clone());
+        }
+    }
+    if !tags.is_empty() {
+        frontmatter_str += "tags:\n";
+        for tag in &tags {
+            frontmatter_str += &format!("  - {}\n", sanitize_tag(tag));
+        }
+    }
+
+    frontmatter_str += &format!("url: {}\n", note.url);
+    frontmatter_str += &format!("author: {}\n", note.author);
+
+    for (key, value) in &note.fields {
+        let mut field = serde_yaml::Mapping::new();
+        field.insert(serde_yaml::Value::String(key.clone()), value.clone());
+        frontmatter_str += &serde_yaml::to_string(&field).unwrap_or_default();
+    }
+
+    frontmatter_str += "---\n\n";
+    frontmatter_str
+}
+
+fn generate_embed_code(video_id: &str, width: usize, height: usize) -> String {
+    debug!("generate_embed_code: video_id={} width={} height={}", video_id, width, height);
+    format!(
+        "<iframe width=\"{}\" height=\"{}\" src=\"https://www.youtube.com/embed/{}\" frameborder=\"0\" allowfullscreen></iframe>",
+        width, height, video_id
+    )
+}
+
+fn sanitize_tag(tag: &str) -> String {
+    debug!("sanitize_tag: tag={}", tag);
+    tag.replace("'", "")
+       .chars()
+       .map(|c| if c.is_alphanumeric() || c.is_whitespace() { c } else { '-' })
+       .collect::<String>()
+       .replace(' ', "-")
+       .to_lowercase()
+}
+
+/// Fetches title, description, channel and tags for a YouTube video.
+pub async fn fetch_video_metadata(config: &Config, video_id: &str) -> Result<VideoMetadata> {
+    debug!("fetch_video_metadata: video_id={}", video_id);
+    let (api, client) = (&config.api, config.http()?);
+    let videos_url = format!("{}/videos", api.youtube_base.trim_end_matches('/'));
+    let url = format!("{}?id={}&part=snippet&key={}", videos_url, video_id, api.youtube_api_key()?);
+
+    // videos.list costs one quota unit
+    let response = client.get_metered("youtube", &url, Quota::YouTube, 1.0).await?;
+    let status = response.status();
+    let response = client.json::<serde_json::Value>(response).await?;
+    if !status.is_success() {
+        let reason = response["error"]["errors"][0]["reason"].as_str().unwrap_or_default();
+        if matches!(reason, "quotaExceeded" | "dailyLimitExceeded" | "rateLimitExceeded") {
+            return Err(Error::QuotaExceeded(format!("YouTube Data API: {}", reason)).into());
+        }
+        return Err(Error::http(&videos_url, status).into());
+    }
+
+    if response["items"].as_array().unwrap_or(&Vec::new()).is_empty() {
+        return Err(Error::MetadataMissing(format!("video metadata not found for video_id={}", video_id)).into());
+    }
+
+    let snippet = &response["items"][0]["snippet"];
+    Ok(VideoMetadata {
+        id: video_id.to_string(),
+        title: snippet["title"].as_str().unwrap_or_default().to_string(),
+        description: snippet["description"].as_str().unwrap_or_default().to_string(),
+        channel: snippet["channelTitle"].as_str().unwrap_or_default().to_string(),
+        published_at: snippet["publishedAt"].as_str().unwrap_or_default().to_string(),
+        tags: snippet["tags"].as_array()
+            .unwrap_or(&Vec::new())
+            .iter()
+            .filter_map(|tag| tag.as_str())
+            .map(String::from)
+            .collect(),
+    })
+}
+
+async fn handle_shorts_url(url: &str, folder: &str, width: usize, height: usize, config: &Config) -> Result<Note> {
+    debug!("handle_shorts_url: url={} folder={} width={} height={} config={:?}", url, folder, width, height, config);
+    let video_id = extract_video_id(url)?;
+    let metadata = fetch_video_metadata(config, &video_id).await?;
+    let embed_code = generate_embed_code(&video_id, width, height);
+    Ok(Note {
+        id: video_id,
+        title: metadata.title,
+        description: metadata.description,
+        embed_code,
+        url: url.to_string(),
+        folder: folder.to_string(),
+        author: metadata.channel,
+        tags: metadata.tags,
+        ..Note::default()
+    })
+}
+
+async fn handle_youtube_url(url: &str, folder: &str, width: usize, height: usize, config: &Config) -> Result<Note> {
+    debug!("handle_youtube_url: url={} folder={} width={} height={} config={:?}", url, folder, width, height, config);
+    let video_id = extract_video_id(url)?;
+    let metadata = fetch_video_metadata(config, &video_id).await?;
+    let embed_code = generate_embed_code(&video_id, width, height);
+    Ok(Note {
+        id: video_id,
+        title: metadata.title,
+        description: metadata.description,
+        embed_code,
+        url: url.to_string(),
+        folder: folder.to_string(),
+        author: metadata.channel,
+        tags: metadata.tags,
+        ..Note::default()
+    })
+}
+
+async fn fetch_and_summarize_url_with_chatgpt(config: &Config, url: &str) -> Result<(String, String, String, String, Vec<String>)> {
+    let prompt = format!(
+        "Please visit the URL '{}' and provide a JSON object with the article's title, summary, author, main image URL, and tags.",
+        url
+    );
+    request_chatgpt_summary(config, &prompt).await
+}
+
+async fn summarize_text_with_chatgpt(config: &Config, url: &str, text: &str) -> Result<(String, String, String, String, Vec<String>)> {
+    let prompt = format!(
+        "The following text was extracted from the document at '{}'. Please provide a JSON object with the document's title, summary, author, main image URL, and tags.\n\n{}",
+        url, text
+    );
+    request_chatgpt_summary(config, &prompt).await
+}
+
+const CHATGPT_MODEL: &str = "gpt-3.5-turbo";
+
+async fn request_chat_completion(config: &Config, request_body: &Value) -> Result<Value> {
+    let (api, client) = (&config.api, config.http()?);
+    let request = client.request(reqwest::Method::POST, &format!("{}/chat/completions", api.openai_base.trim_end_matches('/')))?
+        .header("Authorization", format!("Bearer {}", api.openai_api_key()?))
+        .header("Content-Type", "application/json")
+        .json(request_body);
+    client.acquire_quota(Quota::Llm, 1.0).await;
+    let response = client.send(request).await?;
+
+    if response.status() == 200 {
+        client.json::<Value>(response).await
+    } else {
+        let status = response.status().as_u16();
+        Err(Error::LlmFailed { status: Some(status), message: client.text(response).await? }.into())
+    }
+}
+
+async fn request_chatgpt_summary(config: &Config, prompt: &str) -> Result<(String, String, String, String, Vec<String>)> {
+    let client = config.http()?;
+    let (today, _, _) = today();
+
+    let request_body = json!({
+        "model": CHATGPT_MODEL,
+        "messages": [
+            {"role": "system", "content": "You are a helpful assistant."},
+            {"role": "user", "content": prompt}
+        ]
+    });
+
+    let key = cache::cache_key(&["llm", CHATGPT_MODEL, prompt]);
+    let cached = client.cache().and_then(|cache| cache.get("llm", &key));
+    let response_body = match &cached {
+        Some(entry) => serde_json::from_str::<Value>(&entry.body)?,
+        None => request_chat_completion(config, &request_body).await?,
+    };
+    let assistant_reply = &response_body["choices"][0]["message"]["content"];
+
+    // Parsing the JSON response
+    if let Some(parsed) = assistant_reply.as_str().and_then(|s| serde_json::from_str::<Value>(s).ok()) {
+        if let (None, Some(cache)) = (&cached, client.cache()) {
+            cache.put(&key, &cache::CacheEntry {
+                provider: "llm".to_string(),
+                stored_at: Utc::now().timestamp(),
+                status: 200,
+                content_type: "application/json".to_string(),
+                body: response_body.to_string(),
+            });
+        }
+        let title = parsed["title"].as_str().unwrap_or(&format!("No Title {}", today)).to_string();
+        let summary = parsed["summary"].as_str().unwrap_or_default().to_string();
+        let author = parsed["author"].as_str().unwrap_or_default().to_string();
+        let image = parsed["image"].as_str().unwrap_or_default().to_string();
+        let tags = parsed["tags"].as_array().map_or_else(Vec::new, |arr| {
+            arr.iter().filter_map(|tag| tag.as_str().map(String::from)).collect()
+        });
+
+        Ok((title, summary, author, image, tags))
+    } else {
+        error!("Failed to parse ChatGPT response: {:?}", response_body);
+        Err(Error::LlmFailed { status: None, message: "failed to parse ChatGPT response".to_string() }.into())
+    }
+}
+
+
+fn generate_image_embed_code(img_url: &str, width: usize, height: usize) -> String {
+    format!(
+        "<img src=\"{}\" width=\"{}\" height=\"{}\" alt=\"Image\" />",
+        img_url, width, height
+    )
+}
+
+async fn handle_weblink_url(url: &str, folder: &str, width: usize, height: usize, config: &Config) -> Result<Note> {
+    debug!("handle_weblink_url: url={} folder={} config={:?}", url, folder, config);
+    if pdf::is_pdf_path(url) || pdf::is_pdf_content_type(config.http()?, url).await {
+        let options = config.links.iter()
+            .find(|link| link.name == "pdf")
+            .map(PdfOptions::from_link)
+            .unwrap_or_default();
+        return pdf::handle_pdf_url(url, folder, &options, config).await;
+    }
+    if oembed::find_provider(url).is_some() {
+        return oembed::handle_oembed_url(url, folder, width, height, config).await;
+    }
+    // Pages outside the bundled provider list may still advertise their own oEmbed endpoint
+    match oembed::discover_oembed(config.http()?, url, width, height).await {
+        Ok(Some((oembed, page_title))) => return Ok(oembed::oembed_note(url, folder, width, height, &oembed, &page_title)),
+        Ok(None) => {}
+        Err(e) => warn!("oEmbed discovery failed for {}, falling back to the LLM: {}", url, e),
+    }
+    let (title, summary, author, image, tags) = fetch_and_summarize_url_with_chatgpt(config, url).await?;
+    let embed_code = if !image.is_empty() {
+        generate_image_embed_code(&image, width, height)
+    } else {
+        String::new()
+    };
+
+    Ok(Note {
+        title,
+        description: summary,
+        embed_code,
+        url: url.to_string(),
+        folder: folder.to_string(),
+        author,
+        tags,
+        ..Note::default()
+    })
+}
+
+/// Classifies `url` and fetches its metadata from the matching provider without touching the vault.
+pub async fn fetch_note(url: &str, config: &Config) -> Result<Note> {
+    debug!("fetch_note: url={} config={:?}", url, config);
+    match LinkType::from_url(url, config)? {
+        LinkType::Shorts(url, folder, width, height) => handle_shorts_url(&url, &folder, width, height, config).await,
+        LinkType::YouTube(url, folder, width, height) => handle_youtube_url(&url, &folder, width, height, config).await,
+        LinkType::WebLink(url, folder, width, height) => handle_weblink_url(&url, &folder, width, height, config).await,
+        LinkType::Social(url, folder, unroll, embed) => social::handle_social_url(&url, &folder, unroll, embed, config).await,
+        LinkType::Pdf(url, folder, options) => pdf::handle_pdf_url(&url, &folder, &options, config).await,
+        LinkType::Podcast(url, folder, feeds) => podcast::handle_podcast_url(&url, &folder, &feeds, config).await,
+        LinkType::Wikipedia(url, folder, outline) => wikipedia::handle_wikipedia_url(&url, &folder, outline, config).await,
+        LinkType::Package(url, folder) => package::handle_package_url(&url, &folder, config).await,
+        LinkType::StackExchange(url, folder) => stackexchange::handle_stackexchange_url(&url, &folder, config).await,
+        LinkType::OEmbed(url, folder, width, height) => oembed::handle_oembed_url(&url, &folder, width, height, config).await,
+        LinkType::Product(url, folder, width, height) => product::handle_product_url(&url, &folder, width, height, config).await,
+    }
+}
+
+/// Fetches `url` and writes the resulting note into the vault, returning its path.
+pub async fn handle_url(url: &str, config: &Config) -> Result<PathBuf> {
+    debug!("handle_url: url={} config={:?}", url, config);
+    let naming = Naming::from_link(find_rule(url, config)?)?;
+    let note = fetch_note(url, config).await?;
+    create_markdown_file(&note, &config.vault, &config.frontmatter, &naming).await
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::path::Path;
+
+    use wiremock::MockServer;
+
+    use crate::cache::CacheMode;
+    use crate::{parse_config, Api, Config};
+
+    pub fn fixture(name: &str) -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name);
+        std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("missing fixture {}: {}", path.display(), e))
+    }
+
+    pub fn test_config() -> Config {
+        let mut config = parse_config(include_str!("../obsidian-link.yml")).expect("bundled config parses");
+        config.cache.mode = CacheMode::Disabled;
+        config
+    }
+
+    pub fn mock_config(server: &MockServer, vault: &Path) -> Config {
+        let mut config = test_config();
+        config.vault = vault.to_path_buf();
+        config.api = Api {
+            youtube_base: server.uri(),
+            openai_base: server.uri(),
+            youtube_api_key: Some("test-key".to_string()),
+            openai_api_key: Some("test-key".to_string()),
+        };
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{fixture, mock_config, test_config};
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    async fn mock_json(server: &MockServer, http_method: &str, route: &str, status: u16, body: &str) {
+        Mock::given(method(http_method))
+            .and(path(route))
+            .respond_with(ResponseTemplate::new(status).set_body_raw(body.to_string(), "application/json"))
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_youtube_shorts_identification() {
+        let config = test_config();
+        let shorts_urls = vec![
+            "https://www.youtube.com/shorts/gGrqPbb6fuM",
+            "https://www.youtube.com/shorts/FjkS5rjNq-A",
+        ];
+        for url in shorts_urls {
+            let link_type = LinkType::from_url(url, &config).expect("Failed to identify link type");
+            assert!(matches!(link_type, LinkType::Shorts(..))); // Updated to expect Shorts
+        }
+    }
+
+    #[tokio::test]
+    async fn test_youtube_url_identification() {
+        let config = test_config();
+
+        let urls = vec![
+            "https://www.youtube.com/watch?v=y4evLICF8kk",
+            "https://www.youtube.com/watch?v=U3HndX2QnSo",
+            "https://youtu.be/EkDxsQRbIwoA",
+            "https://youtu.be/m7lnIdudEy8?si=VE-14Y1Sk93RdA5u",
+        ];
+
+        for url in urls {
+            let link_type = LinkType::from_url(url, &config).expect("Failed to identify link type");
+            assert!(matches!(link_type, LinkType::YouTube(..)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_weblink_identification() {
+        let config = test_config();
+
+        let weblink_urls = vec![
+            "https://parrot.ai/",
+            "https://pdfgpt.io/",
+        ];
+
+        for url in weblink_urls {
+            let link_type = LinkType::from_url(url, &config).expect("Failed to identify link type");
+            assert!(matches!(link_type, LinkType::WebLink(..)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_shorts_url_format() {
+        let config = test_config();
+        let invalid_shorts_url = "https://www.youtube.com/notshorts/gGrqPbb6fuM";
+        let link_type = LinkType::from_url(invalid_shorts_url, &config).expect("Failed to identify link type");
+        assert!(matches!(link_type, LinkType::WebLink(..)), "Expected a WebLink for invalid Shorts URL format");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_youtube_url_format() {
+        let config = test_config();
+        let invalid_youtube_url = "https://www.notyoutube.com/watch?v=y4evLICF8kk";
+        let link_type = LinkType::from_url(invalid_youtube_url, &config).expect("Failed to identify link type");
+        assert!(matches!(link_type, LinkType::WebLink(..)), "Expected a WebLink for invalid YouTube URL format");
+    }
+
+    #[tokio::test]
+    async fn test_fetch_metadata_nonexistent_video() {
+        let server = MockServer::start().await;
+        mock_json(&server, "GET", "/videos", 200, &fixture("youtube_empty.json")).await;
+        let config = mock_config(&server, Path::new("/unused"));
+        let non_existent_video_id = "thisdoesnotexist12345";
+        let result = fetch_video_metadata(&config, non_existent_video_id).await;
+        assert!(result.is_err(), "Expected an error for non-existent video metadata fetch");
+        assert_eq!(error::classify(&result.unwrap_err()).map(|e| e.kind()), Some("metadata_missing"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_metadata_quota_exceeded_is_transient() {
+        let server = MockServer::start().await;
+        mock_json(&server, "GET", "/videos", 403, &fixture("youtube_quota.json")).await;
+        let config = mock_config(&server, Path::new("/unused"));
+        let report = fetch_video_metadata(&config, "dQw4w9WgXcQ").await.unwrap_err();
+        assert_eq!(error::exit_code(&report), error::EXIT_TRANSIENT);
+    }
+
+    #[tokio::test]
+    async fn test_cached_video_metadata_spends_no_quota() {
+        let server = MockServer::start().await;
+        mock_json(&server, "GET", "/videos", 200, &fixture("youtube_video.json")).await;
+        let cache_dir = tempfile::tempdir().unwrap();
+        let mut config = mock_config(&server, Path::new("/unused"));
+        config.cache.dir = Some(cache_dir.path().to_path_buf());
+        config.cache.mode = cache::CacheMode::Normal;
+        // One unit per hour: a second request that spent quota would have to wait
+        config.http.rate_limits.youtube = Some(ratelimit::Rate { per_minute: 1.0 / 60.0, burst: 1.0 });
+
+        fetch_video_metadata(&config, "dQw4w9WgXcQ").await.unwrap();
+        let cached = tokio::time::timeout(std::time::Duration::from_secs(5), fetch_video_metadata(&config, "dQw4w9WgXcQ")).await;
+        assert_eq!(cached.expect("cache hits skip the quota").unwrap().id, "dQw4w9WgXcQ");
+    }
+
+    #[tokio::test]
+    async fn test_handle_youtube_url_writes_into_vault() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/videos"))
+            .and(query_param("id", "dQw4w9WgXcQ"))
+            .and(query_param("key", "test-key"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(fixture("youtube_video.json"), "application/json"))
+            .mount(&server)
+            .await;
+        let vault = tempfile::tempdir().unwrap();
+        let config = mock_config(&server, vault.path());
+
+        let path = handle_url("https://www.youtube.com/watch?v=dQw4w9WgXcQ", &config).await.expect("note is written");
+        assert_eq!(path, vault.path().canonicalize().unwrap().join("youtube/Rick Astley - Never Gonna Give You Up (Official Music Video).md"));
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.contains("author: Rick Astley\n"));
+        assert!(content.contains("  - 80s-music\n"));
+        assert!(content.contains("https://www.youtube.com/embed/dQw4w9WgXcQ"));
+    }
+
+    #[test]
+    fn test_podcast_rule_matches_directories_and_mapped_feeds() {
+        let config = test_config();
+        for url in ["https://podcasts.apple.com/us/podcast/x/id1?i=2", "https://overcast.fm/+abc", "https://rustacean-station.org/episode/rust-1.80/"] {
+            assert_eq!(find_rule(url, &config).unwrap().name, "podcast", "{}", url);
+        }
+        for url in ["https://blog.example.com/why-i-started-a-podcast", "https://last.fm/music/x"] {
+            assert_ne!(find_rule(url, &config).unwrap().name, "podcast", "{}", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_weblink_is_summarized_by_llm() {
+        let server = MockServer::start().await;
+        Mock::given(method("HEAD"))
+            .and(path("/article"))
+            .respond_with(ResponseTemplate::new(200).insert_header("content-type", "text/html"))
+            .mount(&server)
+            .await;
+        mock_json(&server, "POST", "/chat/completions", 200, &fixture("openai_chat.json")).await;
+        let config = mock_config(&server, Path::new("/unused"));
+
+        let note = fetch_note(&format!("{}/article", server.uri()), &config).await.expect("note is fetched");
+        assert_eq!(note.title, "Understanding Ownership in Rust");
+        assert_eq!(note.author, "Jane Doe");
+        assert_eq!(note.tags, vec!["rust".to_string(), "memory safety".to_string()]);
+        assert!(note.embed_code.contains("https://example.com/ownership.png"));
+    }
+
+    #[tokio::test]
+    async fn test_weblink_uses_discovered_oembed_before_llm() {
+        let server = MockServer::start().await;
+        let page = format!(
+            r#"<html><head><title>Page</title><link rel="alternate" type="application/json+oembed" href="{}/oembed"></head></html>"#,
+            server.uri()
+        );
+        Mock::given(method("GET"))
+            .and(path("/talk"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(page, "text/html"))
+            .mount(&server)
+            .await;
+        mock_json(&server, "GET", "/oembed", 200, r#"{"type": "video", "title": "A Talk", "author_name": "Speaker", "html": "<iframe></iframe>"}"#).await;
+        let config = mock_config(&server, Path::new("/unused"));
+
+        let note = fetch_note(&format!("{}/talk", server.uri()), &config).await.expect("note is fetched");
+        assert_eq!(note.title, "A Talk");
+        assert_eq!(note.author, "Speaker");
+        assert_eq!(note.embed_code, "<iframe></iframe>");
+    }
+
+    #[test]
+    fn test_generate_embed_code_non_integer() {
+        let video_id = "y4evLICF8kk";
+        let embed_code = generate_embed_code(video_id, 0, 0);
+        assert!(embed_code.contains("width=\"0\""), "Embed code should contain width=\"0\"");
+        assert!(embed_code.contains("height=\"0\""), "Embed code should contain height=\"0\"");
+    }
+
+    #[tokio::test]
+    async fn test_create_markdown_special_characters() {
+        let title = "Test: Special/Characters?*";
+        let description = "A test video.";
+        let embed_code = "<iframe...></iframe>"; // Example embed code
+        let url = "https://www.example.com";
+        let author = "Test Channel";
+        let tags = vec![String::from("test")];
+        let vault = tempfile::tempdir().unwrap();
+        let mut config = test_config();
+        config.vault = vault.path().to_path_buf();
+
+        let result = create_markdown_file(
+            &Note {
+                title: title.to_string(),
+                description: description.to_string(),
+                embed_code: embed_code.to_string(),
+                url: url.to_string(),
+                folder: "test_folder".to_string(),
+                author: author.to_string(),
+                tags,
+                ..Note::default()
+            },
+            &config.vault,
+            &config.frontmatter,
+            &Naming { collision: filename::Collision::Overwrite, ..Naming::default() }
+        ).await;
+
+        assert!(result.is_ok(), "Failed to create markdown file with special characters in title");
+        assert_eq!(result.unwrap().file_name().unwrap(), "Test Special Characters.md");
+    }
+
+    #[tokio::test]
+    async fn test_create_markdown_same_url_keeps_user_text() {
+        let vault = tempfile::tempdir().unwrap();
+        let config = test_config();
+        let note = |description: &str| Note { title: "Page".into(), description: description.into(), url: "https://example.com".into(), folder: "links".into(), ..Note::default() };
+
+        let path = create_markdown_file(&note("first"), vault.path(), &config.frontmatter, &Naming::default()).await.unwrap();
+        let edited = std::fs::read_to_string(&path).unwrap() + "\nMy thoughts\n";
+        std::fs::write(&path, edited).unwrap();
+
+        let again = create_markdown_file(&note("second"), vault.path(), &config.frontmatter, &Naming::default()).await.unwrap();
+        assert_eq!(again, path);
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.contains("## Description\nsecond\n"), "got: {}", content);
+        assert!(content.ends_with(&format!("{}\n\nMy thoughts\n", update::MANAGED_END)), "got: {}", content);
+    }
+
+    #[test]
+    fn test_format_frontmatter_note_type_and_fields() {
+        let frontmatter = Frontmatter {
+            date: Some("2024-01-01".to_string()),
+            day: Some("Mon".to_string()),
+            time: Some("12:00".to_string()),
+            tags: None,
+            url: None,
+            author: None,
+            note_type: Some("link".to_string()),
+        };
+        let note = Note {
+            url: "https://crates.io/crates/regex".to_string(),
+            note_type: Some("library".to_string()),
+            fields: vec![("version".to_string(), serde_yaml::Value::from("1.10.2"))],
+            ..Note::default()
+        };
+        let frontmatter_str = format_frontmatter(&frontmatter, &note);
+        assert!(frontmatter_str.contains("type: library\n"));
+        assert!(frontmatter_str.contains("version: 1.10.2\n"));
+        assert!(frontmatter_str.ends_with("---\n\n"));
+    }
+
+    #[test]
+    fn test_write_attachment_never_replaces_different_content() {
+        let vault = tempfile::tempdir().unwrap();
+        let root = vault.path().canonicalize().unwrap();
+        let attachment = |bytes: &[u8]| Attachment { folder: "attachments".to_string(), file_name: "a.pdf".to_string(), bytes: bytes.to_vec() };
+        let path = write_attachment(&attachment(b"first"), &root).unwrap();
+        assert_eq!(write_attachment(&attachment(b"first"), &root).unwrap(), path);
+        assert!(write_attachment(&attachment(b"second"), &root).is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"first");
+    }
+
+    #[test]
+    fn test_note_to_json_includes_rule_path_and_fields() {
+        let note = Note {
+            title: "regex".to_string(),
+            url: "https://crates.io/crates/regex".to_string(),
+            folder: "Libraries".to_string(),
+            fields: vec![("version".to_string(), serde_yaml::Value::from("1.10.2"))],
+            attachments: vec![Attachment { folder: "attachments".to_string(), file_name: "a.pdf".to_string(), bytes: vec![0; 3] }],
+            ..Note::default()
+        };
+        let value = note_to_json(&note, "package", Path::new("/vault/Libraries/regex.md"));
+        assert_eq!(value["rule"], "package");
+        assert_eq!(value["path"], "/vault/Libraries/regex.md");
+        assert_eq!(value["fields"]["version"], "1.10.2");
+        assert_eq!(value["attachments"][0]["size"], 3);
+    }
+}
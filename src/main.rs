@@ -1,540 +1,325 @@
-#![cfg_attr(debug_assertions, allow(unused_imports, unused_variables, unused_mut, dead_code))]
-
-use log::{debug, info, warn, error};
+use log::{debug, info, warn};
 use std::path::{Path, PathBuf};
-use std::io::Write;
-use std::env;
-use std::collections::HashMap;
 
-use reqwest;
-use tokio;
-use shellexpand;
-use chrono::prelude::*;
-use chrono_tz::Tz;
-use chrono::format::StrftimeItems;
-use regex::Regex;
-use clap::{Parser, Args};
-use serde::Deserialize;
-use serde_json::{json, Value};
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand};
 use eyre::{eyre, Result};
-use scraper::{Html, Selector};
 use lazy_static::lazy_static;
 
-const TIMEZONE: &str = "America/Los_Angeles";
+use obsidian_link::filename::Naming;
+use obsidian_link::capture::Capture;
+use obsidian_link::cache::{self, CacheMode};
+use obsidian_link::ratelimit::Rate;
+use obsidian_link::{batch, browser, check, error, import, queue, serve, update, vault, watch};
+use obsidian_link::{fetch_note, find_rule, handle_url, load_config, note_path, note_to_json, render_note, Config};
 
 lazy_static! {
     static ref LOG_LEVEL: String = std::env::var("LOG_LEVEL").unwrap_or("INFO".to_string());
-    static ref YOUTUBE_API_KEY: String = env::var("YOUTUBE_API_KEY").expect("YOUTUBE_API_KEY not set in environment");
-    static ref CHATGPT_API_KEY: String = env::var("CHATGPT_API_KEY").expect("CHATGPT_API_KEY not set in environment");
-    static ref RESOLUTIONS: HashMap<&'static str, (usize, usize)> = {
-        let mut m = HashMap::new();
-        m.insert("nHD", (640, 360));
-        m.insert("FWVGA", (854, 480));
-        m.insert("qHD", (960, 540));
-        m.insert("SD", (1280, 720));
-        m.insert("WXGA", (1366, 768));
-        m.insert("HD+", (1600, 900));
-        m.insert("FHD", (1920, 1080));
-        m.insert("WQHD", (2560, 1440));
-        m.insert("QHD+", (3200, 1800));
-        m.insert("4K", (3840, 2160));
-        m.insert("5K", (5120, 2880));
-        m.insert("8K", (7680, 4320));
-        m.insert("16K", (15360, 8640));
-        m
-    };
-    static ref SHORTS_RESOLUTIONS: HashMap<&'static str, (usize, usize)> = {
-        let mut m = HashMap::new();
-        m.insert("480p", (480, 854));
-        m.insert("720p", (720, 1280));
-        m.insert("1080p", (1080, 1920));
-        m.insert("1440p", (1440, 2560));
-        m.insert("2160p", (2160, 3840));
-        m
-    };
 }
 
+const EXIT_CODES: &str = "Exit codes: 0 ok, 1 unknown, 2 usage, 3 config invalid, 4 no matching rule, 5 fetch failed, \
+6 metadata missing, 7 LLM failed, 8 write failed, 9 duplicate, 11 response too large, 75 transient (safe to retry)";
+
 #[derive(Parser)]
-#[clap(author, version, about, long_about = None)]
+#[clap(author, version, about, long_about = None, after_help = EXIT_CODES)]
 struct Cli {
     #[clap(short, long, value_parser, default_value = "~/.config/obsidian-link/obsidian-link.yml")]
     config: PathBuf,
 
     #[clap(short, long)]
     url: Option<String>,
-}
-
-#[derive(Deserialize, Debug)]
-struct Config {
-    vault: PathBuf,
-    frontmatter: Frontmatter,
-    links: Vec<Link>,
-}
-
-#[derive(Deserialize, Debug)]
-struct Frontmatter {
-    date: Option<String>,
-    day: Option<String>,
-    time: Option<String>,
-    tags: Option<Vec<String>>,
-    url: Option<String>,
-    author: Option<String>,
-}
-
-#[derive(Deserialize, Debug)]
-struct Link {
-    name: String,
-    regex: String,
-    resolution: String,
-    folder: String,
-}
-
-#[derive(Debug)]
-struct VideoMetadata {
-    id: String,
-    title: String,
-    description: String,
-    channel: String,
-    published_at: String,
-    tags: Vec<String>,
-}
 
-enum LinkType {
-    Shorts(String, String, usize, usize),
-    YouTube(String, String, usize, usize),
-    WebLink(String, String, usize, usize),
-}
+    #[clap(long, help = "Print the resolved rule, target path and rendered note without writing to the vault")]
+    dry_run: bool,
 
-impl LinkType {
-    fn from_url(url: &str, config: &Config) -> Result<LinkType> {
-        debug!("LinkType::from_url: url={} config={:?}", url, config);
-        let mut default_link = None;
+    #[clap(long, help = "Print the rendered note to stdout instead of writing it to the vault")]
+    stdout: bool,
 
-        for link in &config.links {
-            let regex = Regex::new(&link.regex)?;
-            if regex.is_match(url) {
-                let (width, height) = get_resolution(&link.name, config)?;
-                if link.name == "default" {
-                    default_link = Some(LinkType::WebLink(url.to_string(), link.folder.clone(), width, height));
-                    continue;
-                }
-                return Ok(match link.name.as_str() {
-                    "shorts" => LinkType::Shorts(url.to_string(), link.folder.clone(), width, height),
-                    "youtube" => LinkType::YouTube(url.to_string(), link.folder.clone(), width, height),
-                    _ => LinkType::WebLink(url.to_string(), link.folder.clone(), width, height),
-                });
-            }
-        }
+    #[clap(long, help = "Print the resolved rule, target path and extracted metadata as JSON without writing")]
+    json: bool,
 
-        if let Some(default_link) = default_link {
-            return Ok(default_link);
-        }
+    #[clap(long, global = true, help = "Neither read nor write the on-disk response cache")]
+    no_cache: bool,
 
-        Err(eyre!("Invalid URL format"))
-    }
-}
+    #[clap(long, global = true, conflicts_with = "no_cache", help = "Ignore cached responses but store fresh ones")]
+    refresh: bool,
 
-fn expanduser<T: AsRef<str>>(path: T) -> Result<PathBuf> {
-    let expanded_path_str = shellexpand::tilde(path.as_ref());
-    Ok(PathBuf::from(expanded_path_str.into_owned()))
+    #[clap(subcommand)]
+    command: Option<Command>,
 }
 
-fn today() -> (String, String, String) {
-    debug!("today");
-    let tz: Tz = TIMEZONE.parse().expect("Invalid timezone");
-    let now = Utc::now().with_timezone(&tz);
-
-    let date_format = StrftimeItems::new("%Y-%m-%d");
-    let day_format = StrftimeItems::new("%a");
-    let time_format = StrftimeItems::new("%H:%M");
-
-    let formatted_date = now.format_with_items(date_format).to_string();
-    let formatted_day = now.format_with_items(day_format).to_string();
-    let formatted_time = now.format_with_items(time_format).to_string();
-
-    (formatted_date, formatted_day, formatted_time)
+#[derive(Subcommand)]
+enum Command {
+    #[clap(about = "Re-fetch metadata for an existing note, keeping user-added frontmatter and sections")]
+    Update {
+        #[clap(help = "Path to the note (absolute or relative to the vault) or the URL it was created from")]
+        target: String,
+    },
+    #[clap(about = "Check every saved link in the vault for link rot and write a summary report")]
+    Check {
+        #[clap(long, default_value = "8", help = "Number of links checked at the same time")]
+        concurrency: usize,
+        #[clap(long, default_value = "5", help = "Maximum requests started per second")]
+        rate: f64,
+        #[clap(long, default_value = "Link Check Report.md", help = "Report note path relative to the vault")]
+        report: String,
+    },
+    #[clap(about = "Create notes for every URL in a file (one per line, '-' for stdin) concurrently")]
+    Batch {
+        #[clap(help = "File with one URL per line; blank lines and # comments are skipped")]
+        file: PathBuf,
+        #[clap(long, help = "Number of URLs processed at the same time (defaults to batch.concurrency)")]
+        concurrency: Option<usize>,
+    },
+    #[clap(about = "Import bookmarks from a browser or read-later export or profile, creating a note per URL")]
+    Import {
+        #[clap(help = "Bookmarks export (Netscape HTML, Pocket, Raindrop.io, Pinboard, Instapaper), Firefox places.sqlite, \
+Chromium Bookmarks file, or a browser profile directory")]
+        path: PathBuf,
+        #[clap(long, default_value = "auto", help = "auto, netscape, firefox, chromium, pocket, raindrop, pinboard or instapaper")]
+        format: import::ImportFormat,
+        #[clap(long, help = "Also import Firefox history, not just bookmarks")]
+        history: bool,
+        #[clap(long, default_value = "1", requires = "history", help = "Only import history entries visited at least this often")]
+        min_visits: u32,
+        #[clap(long, requires = "history", help = "Only import history entries visited since this date (YYYY-MM-DD)")]
+        since: Option<NaiveDate>,
+        #[clap(long, help = "Map bookmark folders to tags, subfolders or ignore them (defaults to import.folders)")]
+        folders: Option<import::FolderMapping>,
+        #[clap(long, help = "Number of URLs processed at the same time (defaults to batch.concurrency)")]
+        concurrency: Option<usize>,
+    },
+    #[clap(about = "Run a localhost HTTP server that captures URLs posted by browsers and bookmarklets")]
+    Serve {
+        #[clap(long, help = "Address to listen on (defaults to serve.bind)")]
+        bind: Option<String>,
+        #[clap(long, help = "Bearer token clients must send (defaults to serve.token, or a generated one kept in serve.token_file)")]
+        token: Option<String>,
+    },
+    #[clap(about = "Watch an inbox file or note and turn newly added URLs into notes")]
+    Watch {
+        #[clap(long, help = "Inbox file, absolute or relative to the vault (defaults to watch.inbox)")]
+        inbox: Option<PathBuf>,
+        #[clap(long, help = "Process the inbox once and exit")]
+        once: bool,
+    },
+    #[clap(about = "Inspect or retry URLs that failed with a retriable error")]
+    Queue {
+        #[clap(subcommand)]
+        command: QueueCommand,
+    },
+    #[clap(about = "Manage the on-disk response cache")]
+    Cache {
+        #[clap(subcommand)]
+        command: CacheCommand,
+    },
 }
 
-fn get_resolution(link_name: &str, config: &Config) -> Result<(usize, usize)> {
-    debug!("get_resolution: link_name={} config={:?}", link_name, config);
-    let resolution_key = config.links.iter().find(|link| link.name == link_name)
-        .ok_or_else(|| eyre!("Link type '{}' not found in config", link_name))?.resolution.as_str(); // Convert to &str
-
-    match link_name {
-        "shorts" => SHORTS_RESOLUTIONS.get(resolution_key)
-            .copied()
-            .ok_or_else(|| eyre!("Resolution not found for shorts")),
-        "youtube" | _ => RESOLUTIONS.get(resolution_key)
-            .copied()
-            .ok_or_else(|| eyre!("Resolution not found for {}", link_name)),
-    }
+#[derive(Subcommand)]
+enum QueueCommand {
+    #[clap(about = "List URLs waiting to be retried with their last error and attempt count")]
+    List,
+    #[clap(about = "Retry every queued URL now, including ones past queue.max_attempts")]
+    Retry,
 }
 
-fn load_config(config_path: PathBuf) -> Result<Config> {
-    debug!("load_config: config_path={}", config_path.display());
-    let config_path_str = config_path.to_str()
-        .ok_or_else(|| eyre!("Failed to convert config path to string"))?;
-    let config_path_expanded = expanduser(config_path_str)?;
-    let config_str = std::fs::read_to_string(config_path_expanded)
-        .map_err(|e| eyre!("Failed to read config file: {}", e))?;
-    let config: Config = serde_yaml::from_str(&config_str)
-        .map_err(|e| eyre!("Failed to parse config file: {}", e))?;
-    Ok(config)
+#[derive(Subcommand)]
+enum CacheCommand {
+    #[clap(about = "Delete expired and unreadable cache entries")]
+    Prune,
 }
 
-fn extract_video_id(url: &str) -> Result<String> {
-    debug!("extract_video_id: url={}", url);
-    let pattern = Regex::new(r#"(youtu\.be/|youtube\.com/(watch\?(.*&)?v=|(embed|v|shorts)/))([^?&">]+)"#)
-        .map_err(|e| eyre!("Failed to compile regex: {}", e))?;
-
-    pattern.captures(url)
-        .and_then(|caps| caps.get(5))
-        .map(|m| m.as_str().to_string())
-        .ok_or_else(|| eyre!("Failed to extract video ID from URL"))
-}
-
-async fn create_markdown_file(title: &str, description: &str, embed_code: &str, url: &str, author: &str, tags: &[String], vault_path: &PathBuf, folder: &str, frontmatter: &Frontmatter) -> Result<()> {
-    debug!("create_markdown_file: title={} description={} embed_code={} url={} author={} tags={:?} vault_path={} folder={} frontmatter={:?}", title, description, embed_code, url, author, tags, vault_path.display(), folder, frontmatter);
-    let vault_path_str = vault_path.to_str().ok_or_else(|| eyre!("Failed to convert vault path to string"))?;
-    let vault_path_expanded = expanduser(vault_path_str)?;
-    let full_path = vault_path_expanded.join(folder);
-
-    std::fs::create_dir_all(&full_path).map_err(|e| eyre!("Failed to create directory: {:?} with error {}", full_path, e))?;
-
-    let file_name = sanitize_filename(title);
-    let file_path = full_path.join(file_name + ".md");
-
-    let mut file = std::fs::File::create(&file_path)
-        .map_err(|e| eyre!("Failed to create markdown file: {:?} with error {}", file_path, e))?;
-
-    let frontmatter_str = format_frontmatter(frontmatter, url, author, tags);
-    write!(file, "{}\n{}\n\n## Description\n{}", frontmatter_str, embed_code, description)
-        .map_err(|e| eyre!("Failed to write to markdown file: {}", e))
-}
-
-fn format_frontmatter(frontmatter: &Frontmatter, url: &str, author: &str, tags: &[String]) -> String {
-    debug!("format_frontmatter: frontmatter={:?} url={} author={} tags={:?}", frontmatter, url, author, tags);
-    let mut frontmatter_str = String::from("---\n");
-
-    let (current_date, current_day, current_time) = today();
-    frontmatter_str += &format!("date: {}\n", frontmatter.date.as_ref().unwrap_or(&current_date));
-    frontmatter_str += &format!("day: {}\n", frontmatter.day.as_ref().unwrap_or(&current_day));
-    frontmatter_str += &format!("time: {}\n", frontmatter.time.as_ref().unwrap_or(&current_time));
-
-    if !tags.is_empty() {
-        frontmatter_str += "tags:\n";
-        for tag in tags {
-            frontmatter_str += &format!("  - {}\n", sanitize_tag(tag));
+async fn preview_url(url: &str, config: &Config, args: &Cli) -> Result<()> {
+    debug!("preview_url: url={} dry_run={} stdout={} json={}", url, args.dry_run, args.stdout, args.json);
+    let rule = find_rule(url, config)?;
+    let naming = Naming::from_link(rule)?;
+    let note = fetch_note(url, config).await?;
+    let path = note_path(&note, &config.vault, &naming)?;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&note_to_json(&note, &rule.name, &path))?);
+    } else if args.dry_run {
+        println!("rule: {}", rule.name);
+        println!("path: {}", path.display());
+        for attachment in &note.attachments {
+            println!("attachment: {} ({} bytes)", Path::new(&attachment.folder).join(&attachment.file_name).display(), attachment.bytes.len());
         }
+        println!();
+        println!("{}", render_note(&note, &config.frontmatter));
+    } else {
+        print!("{}", render_note(&note, &config.frontmatter));
     }
-
-    frontmatter_str += &format!("url: {}\n", url);
-    frontmatter_str += &format!("author: {}\n", author);
-
-    frontmatter_str += "---\n\n";
-    frontmatter_str
+    Ok(())
 }
 
-fn generate_embed_code(video_id: &str, width: usize, height: usize) -> String {
-    debug!("generate_embed_code: video_id={} width={} height={}", video_id, width, height);
-    format!(
-        "<iframe width=\"{}\" height=\"{}\" src=\"https://www.youtube.com/embed/{}\" frameborder=\"0\" allowfullscreen></iframe>",
-        width, height, video_id
-    )
-}
-
-fn sanitize_tag(tag: &str) -> String {
-    debug!("sanitize_tag: tag={}", tag);
-    tag.replace("'", "")
-       .chars()
-       .map(|c| if c.is_alphanumeric() || c.is_whitespace() { c } else { '-' })
-       .collect::<String>()
-       .replace(' ', "-")
-       .to_lowercase()
-}
-
-fn sanitize_filename(title: &str) -> String {
-    debug!("sanitize_filename: title={}", title);
-    title.chars()
-         .filter(|c| c.is_alphanumeric() || c.is_whitespace() || *c == '-')
-         .collect::<String>()
-}
-
-async fn fetch_video_metadata(api_key: &str, video_id: &str) -> Result<VideoMetadata> {
-    debug!("fetch_video_metadata: api_key={} video_id={}", api_key, video_id);
-    let url = format!(
-        "https://www.googleapis.com/youtube/v3/videos?id={}&part=snippet&key={}",
-        video_id, api_key
-    );
-
-    let response = reqwest::get(&url).await?
-        .json::<serde_json::Value>().await?;
-
-    if response["items"].as_array().unwrap_or(&Vec::new()).is_empty() {
-        return Err(eyre!("Video metadata not found for video_id={}", video_id));
+async fn update_existing(target: &str, config: &Config, args: &Cli) -> Result<()> {
+    debug!("update_existing: target={} dry_run={} stdout={}", target, args.dry_run, args.stdout);
+    if args.dry_run || args.stdout {
+        let (path, _, merged) = update::prepare_update(target, config).await?;
+        if args.dry_run {
+            println!("path: {}", path.display());
+            println!();
+        }
+        print!("{}", merged);
+        return Ok(());
     }
-
-    let snippet = &response["items"][0]["snippet"];
-    Ok(VideoMetadata {
-        id: video_id.to_string(),
-        title: snippet["title"].as_str().unwrap_or_default().to_string(),
-        description: snippet["description"].as_str().unwrap_or_default().to_string(),
-        channel: snippet["channelTitle"].as_str().unwrap_or_default().to_string(),
-        published_at: snippet["publishedAt"].as_str().unwrap_or_default().to_string(),
-        tags: snippet["tags"].as_array()
-            .unwrap_or(&Vec::new())
-            .iter()
-            .filter_map(|tag| tag.as_str())
-            .map(String::from)
-            .collect(),
-    })
+    let path = update::update_note(target, config).await?;
+    info!("Updated {}", path.display());
+    Ok(())
 }
 
-async fn handle_shorts_url(url: &str, folder: &str, width: usize, height: usize, config: &Config) -> Result<()> {
-    debug!("handle_shorts_url: url={} folder={} width={} height={} config={:?}", url, folder, width, height, config);
-    let video_id = extract_video_id(url)?;
-    let metadata = fetch_video_metadata(&YOUTUBE_API_KEY, &video_id).await?;
-    let embed_code = generate_embed_code(&video_id, width, height);
-    create_markdown_file(
-        &metadata.title,
-        &metadata.description,
-        &embed_code,
-        url,
-        &metadata.channel,
-        &metadata.tags,
-        &config.vault,
-        folder,
-        &config.frontmatter
-    ).await
+fn print_progress(done: usize, total: usize, result: &batch::BatchResult) {
+    let status = if result.outcome.is_ok() { "created" } else { "failed" };
+    eprintln!("[{}/{}] {} {}", done, total, status, result.url);
 }
 
-async fn handle_youtube_url(url: &str, folder: &str, width: usize, height: usize, config: &Config) -> Result<()> {
-    debug!("handle_youtube_url: url={} folder={} width={} height={} config={:?}", url, folder, width, height, config);
-    let video_id = extract_video_id(url)?;
-    let metadata = fetch_video_metadata(&YOUTUBE_API_KEY, &video_id).await?;
-    let embed_code = generate_embed_code(&video_id, width, height);
-    create_markdown_file(
-        &metadata.title,
-        &metadata.description,
-        &embed_code,
-        url,
-        &metadata.channel,
-        &metadata.tags,
-        &config.vault,
-        folder,
-        &config.frontmatter
-    ).await
+async fn retry_queue(config: &Config, all: bool) -> Result<Vec<batch::BatchResult>> {
+    debug!("retry_queue: all={}", all);
+    let results = queue::retry_queued(config, all, print_progress).await?;
+    if !results.is_empty() {
+        let succeeded = results.iter().filter(|result| result.outcome.is_ok()).count();
+        info!("Retried {} queued URLs, {} succeeded", results.len(), succeeded);
+    }
+    Ok(results)
 }
 
-async fn fetch_and_summarize_url_with_chatgpt(url: &str) -> Result<(String, String, String, String, Vec<String>)> {
-    let client = reqwest::Client::new();
-    let (today, _, _) = today();
-    let prompt = format!(
-        "Please visit the URL '{}' and provide a JSON object with the article's title, summary, author, main image URL, and tags.",
-        url
-    );
-
-    let request_body = json!({
-        "model": "gpt-3.5-turbo",
-        "messages": [
-            {"role": "system", "content": "You are a helpful assistant."},
-            {"role": "user", "content": prompt}
-        ]
-    });
-
-    let response = client.post("https://api.openai.com/v1/chat/completions")
-        .header("Authorization", format!("Bearer {}", CHATGPT_API_KEY.as_str()))
-        .header("Content-Type", "application/json")
-        .json(&request_body)
-        .send()
-        .await?;
-
-    if response.status() == 200 {
-        let response_body = response.json::<Value>().await?;
-        let assistant_reply = &response_body["choices"][0]["message"]["content"];
-
-        // Parsing the JSON response
-        if let Some(parsed) = assistant_reply.as_str().and_then(|s| serde_json::from_str::<Value>(s).ok()) {
-            let title = parsed["title"].as_str().unwrap_or(&format!("No Title {}", today)).to_string();
-            let summary = parsed["summary"].as_str().unwrap_or_default().to_string();
-            let author = parsed["author"].as_str().unwrap_or_default().to_string();
-            let image = parsed["image"].as_str().unwrap_or_default().to_string();
-            let tags = parsed["tags"].as_array().map_or_else(Vec::new, |arr| {
-                arr.iter().filter_map(|tag| tag.as_str().map(String::from)).collect()
-            });
-
-            Ok((title, summary, author, image, tags))
-        } else {
-            error!("Failed to parse ChatGPT response: {:?}", response_body);
-            Err(eyre!("Failed to parse ChatGPT response"))
+// Queued URLs get another chance whenever a capture run starts; a broken queue must not block the new URL
+async fn retry_queue_on_start(config: &Config) {
+    if config.queue.auto_retry {
+        if let Err(e) = retry_queue(config, false).await {
+            warn!("Failed to retry queued URLs: {}", e);
         }
-    } else {
-        Err(eyre!("Error: {}", response.text().await?))
     }
 }
 
-
-fn generate_image_embed_code(img_url: &str, width: usize, height: usize) -> String {
-    format!(
-        "<img src=\"{}\" width=\"{}\" height=\"{}\" alt=\"Image\" />",
-        img_url, width, height
-    )
-}
-
-async fn handle_weblink_url(url: &str, folder: &str, width: usize, height: usize, config: &Config) -> Result<()> {
-    debug!("handle_weblink_url: url={} folder={} config={:?}", url, folder, config);
-    let (title, summary, author, image, tags) = fetch_and_summarize_url_with_chatgpt(url).await?;
-    let embed_code = if !image.is_empty() {
-        generate_image_embed_code(&image, width, height)
+async fn run_batch(file: &Path, concurrency: Option<usize>, config: &Config) -> Result<()> {
+    debug!("run_batch: file={} concurrency={:?}", file.display(), concurrency);
+    let text = if file == Path::new("-") {
+        std::io::read_to_string(std::io::stdin())?
     } else {
-        String::new()
+        std::fs::read_to_string(file).map_err(|e| eyre!("Failed to read {}: {}", file.display(), e))?
     };
-
-    create_markdown_file(
-        &title,
-        &summary,
-        &embed_code,
-        url,
-        &author,
-        &tags,
-        &config.vault,
-        folder,
-        &config.frontmatter
-    ).await
+    let urls = batch::parse_url_list(&text);
+    let concurrency = concurrency.unwrap_or(config.batch.concurrency);
+    let captures = urls.iter().map(|url| Capture::new(url)).collect::<Vec<_>>();
+    let results = batch::run_captures(&captures, config, concurrency, print_progress).await;
+    queue::record_results(config, &captures, &results);
+    print!("{}", batch::render_summary(&results));
+
+    let failed = results.iter().filter(|result| result.outcome.is_err()).count();
+    if failed > 0 {
+        return Err(eyre!("{} of {} URLs failed", failed, results.len()));
+    }
+    Ok(())
 }
 
-async fn handle_url(url: &str, config: &Config) -> Result<()> {
-    debug!("handle_url: url={} config={:?}", url, config);
-    match LinkType::from_url(url, config)? {
-        LinkType::Shorts(url, folder, width, height) => handle_shorts_url(&url, &folder, width, height, config).await,
-        LinkType::YouTube(url, folder, width, height) => handle_youtube_url(&url, &folder, width, height, config).await,
-        LinkType::WebLink(url, folder, width, height) => handle_weblink_url(&url, &folder, width, height, config).await,
+async fn run_import(path: &Path, bookmarks: Vec<import::Bookmark>, concurrency: Option<usize>, config: &Config) -> Result<()> {
+    debug!("run_import: path={} bookmarks.len={} concurrency={:?}", path.display(), bookmarks.len(), concurrency);
+    let concurrency = concurrency.unwrap_or(config.batch.concurrency);
+    let (captures, skipped) = import::import_captures(bookmarks, config);
+    let results = batch::run_captures(&captures, config, concurrency, print_progress).await;
+    queue::record_results(config, &captures, &results);
+    print!("{}", batch::render_summary(&results));
+    println!("{} skipped", skipped);
+
+    let failed = results.iter().filter(|result| result.outcome.is_err()).count();
+    if failed > 0 {
+        return Err(eyre!("{} of {} bookmarks failed", failed, results.len()));
     }
+    Ok(())
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(LOG_LEVEL.as_str())).init();
     info!("obsidian-link");
     let args = Cli::parse();
-    let config = load_config(args.config)?;
-
-    match args.url {
-        Some(url) => handle_url(&url, &config).await,
-        None => Err(eyre!("No URL provided")),
+    if let Err(report) = run(&args).await {
+        if args.json {
+            println!("{}", error::to_json(&report));
+        } else {
+            eprintln!("Error: {:?}", report);
+        }
+        std::process::exit(error::exit_code(&report));
     }
 }
 
+async fn run(args: &Cli) -> Result<()> {
+    let mut config = load_config(args.config.clone())?;
+    config.cache.mode = if args.no_cache {
+        CacheMode::Disabled
+    } else if args.refresh {
+        CacheMode::Refresh
+    } else {
+        CacheMode::Normal
+    };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    fn load_test_config() -> Config {
-        let config_path = shellexpand::tilde("~/.config/obsidian-link/obsidian-link.yml");
-        let config_path = Path::new(config_path.as_ref());
-
-        load_config(config_path.to_path_buf()).expect("Failed to load config")
-    }
-
-    #[tokio::test]
-    async fn test_youtube_shorts_identification() {
-        let config = load_test_config();
-        let shorts_urls = vec![
-            "https://www.youtube.com/shorts/gGrqPbb6fuM",
-            "https://www.youtube.com/shorts/FjkS5rjNq-A",
-        ];
-        for url in shorts_urls {
-            let link_type = LinkType::from_url(url, &config).expect("Failed to identify link type");
-            assert!(matches!(link_type, LinkType::Shorts(..))); // Updated to expect Shorts
+    match &args.command {
+        Some(Command::Update { target }) => return update_existing(target, &config, args).await,
+        Some(Command::Check { concurrency, rate, report }) => {
+            let rate = Rate { per_minute: rate * 60.0, burst: 1.0 };
+            let options = check::CheckOptions { concurrency: *concurrency, rate, report: report.clone(), write: !args.dry_run };
+            let results = check::check_vault(&config, &options).await?;
+            for result in results.iter().filter(|result| result.status != check::LinkStatus::Ok) {
+                println!("{}\t{}\t{}", result.status, result.path.display(), result.url);
+            }
+            return Ok(());
         }
-    }
-
-    #[tokio::test]
-    async fn test_youtube_url_identification() {
-        let config = load_test_config();
-
-        let urls = vec![
-            "https://www.youtube.com/watch?v=y4evLICF8kk",
-            "https://www.youtube.com/watch?v=U3HndX2QnSo",
-            "https://youtu.be/EkDxsQRbIwoA",
-            "https://youtu.be/m7lnIdudEy8?si=VE-14Y1Sk93RdA5u",
-        ];
-
-        for url in urls {
-            let link_type = LinkType::from_url(url, &config).expect("Failed to identify link type");
-            assert!(matches!(link_type, LinkType::YouTube(..)));
+        Some(Command::Cache { command: CacheCommand::Prune }) => {
+            let stats = cache::prune(&config.cache)?;
+            println!("Removed {} expired cache entries, kept {}", stats.removed, stats.kept);
+            return Ok(());
         }
-    }
-
-    #[tokio::test]
-    async fn test_weblink_identification() {
-        let config = load_test_config();
-
-        let weblink_urls = vec![
-            "https://parrot.ai/",
-            "https://pdfgpt.io/",
-        ];
-
-        for url in weblink_urls {
-            let link_type = LinkType::from_url(url, &config).expect("Failed to identify link type");
-            assert!(matches!(link_type, LinkType::WebLink(..)));
+        Some(Command::Serve { bind, token }) => {
+            let mut options = config.serve.clone();
+            options.bind = bind.clone().unwrap_or(options.bind);
+            options.token = token.clone().or(options.token);
+            return serve::serve(config, options).await;
         }
+        Some(Command::Watch { inbox, once }) => {
+            if let Some(inbox) = inbox {
+                config.watch.inbox = Some(inbox.clone());
+            }
+            let inbox = config.watch.resolved_inbox(&vault::vault_root(&config.vault)?)?;
+            return watch::watch(&inbox, &config, *once).await;
+        }
+        Some(Command::Queue { command: QueueCommand::List }) => {
+            print!("{}", queue::render_list(&queue::RetryQueue::open(&config)?.items));
+            return Ok(());
+        }
+        Some(Command::Queue { command: QueueCommand::Retry }) => {
+            let results = retry_queue(&config, true).await?;
+            print!("{}", batch::render_summary(&results));
+            return Ok(());
+        }
+        Some(Command::Batch { file, concurrency }) => {
+            retry_queue_on_start(&config).await;
+            return run_batch(file, *concurrency, &config).await;
+        }
+        Some(Command::Import { path, format, history, min_visits, since, folders, concurrency }) => {
+            if let Some(folders) = folders {
+                config.import.folders = *folders;
+            }
+            let history = history.then(|| browser::HistoryFilter {
+                min_visits: *min_visits,
+                since: since.and_then(|date| date.and_hms_opt(0, 0, 0)).map(|date| date.and_utc()),
+            });
+            let bookmarks = import::read_bookmarks(path, *format, history.as_ref())?;
+            return run_import(path, bookmarks, *concurrency, &config).await;
+        }
+        None => {}
     }
 
-    #[tokio::test]
-    async fn test_invalid_shorts_url_format() {
-        let config = load_test_config();
-        let invalid_shorts_url = "https://www.youtube.com/notshorts/gGrqPbb6fuM";
-        let link_type = LinkType::from_url(invalid_shorts_url, &config).expect("Failed to identify link type");
-        assert!(matches!(link_type, LinkType::WebLink(..)), "Expected a WebLink for invalid Shorts URL format");
-    }
-
-    #[tokio::test]
-    async fn test_invalid_youtube_url_format() {
-        let config = load_test_config();
-        let invalid_youtube_url = "https://www.notyoutube.com/watch?v=y4evLICF8kk";
-        let link_type = LinkType::from_url(invalid_youtube_url, &config).expect("Failed to identify link type");
-        assert!(matches!(link_type, LinkType::WebLink(..)), "Expected a WebLink for invalid YouTube URL format");
-    }
-
-    #[tokio::test]
-    async fn test_fetch_metadata_nonexistent_video() {
-        let non_existent_video_id = "thisdoesnotexist12345";
-        let result = fetch_video_metadata(&YOUTUBE_API_KEY, non_existent_video_id).await;
-        assert!(result.is_err(), "Expected an error for non-existent video metadata fetch");
-    }
-
-    #[test]
-    fn test_generate_embed_code_non_integer() {
-        let video_id = "y4evLICF8kk";
-        let embed_code = generate_embed_code(video_id, 0, 0);
-        assert!(embed_code.contains("width=\"0\""), "Embed code should contain width=\"0\"");
-        assert!(embed_code.contains("height=\"0\""), "Embed code should contain height=\"0\"");
-    }
-
-    #[tokio::test]
-    async fn test_create_markdown_special_characters() {
-        let title = "Test: Special/Characters?*";
-        let description = "A test video.";
-        let embed_code = "<iframe...></iframe>"; // Example embed code
-        let url = "https://www.example.com";
-        let author = "Test Channel";
-        let tags = vec![String::from("test")];
-        let config = load_test_config();
-
-        let result = create_markdown_file(
-            title,
-            description,
-            &embed_code,
-            url,
-            author,
-            &tags,
-            &config.vault,
-            "test_folder",
-            &config.frontmatter
-        ).await;
-
-        assert!(result.is_ok(), "Failed to create markdown file with special characters in title");
+    match &args.url {
+        Some(url) if args.dry_run || args.stdout || args.json => preview_url(url, &config, args).await,
+        Some(url) => {
+            retry_queue_on_start(&config).await;
+            let result = batch::BatchResult { index: 0, url: url.clone(), outcome: handle_url(url, &config).await };
+            queue::record_results(&config, &[Capture::new(url)], std::slice::from_ref(&result));
+            let path = result.outcome?;
+            info!("Created {}", path.display());
+            Ok(())
+        }
+        None => Err(eyre!("No URL provided")),
     }
 }
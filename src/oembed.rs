@@ -0,0 +1,235 @@
+use log::{debug, warn};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use eyre::{eyre, Result};
+use lazy_static::lazy_static;
+use reqwest::Url;
+use scraper::{Html, Selector};
+
+use crate::error::Error;
+use crate::html::html_to_text;
+use crate::http::HttpClient;
+use crate::{generate_image_embed_code, Config, Note};
+
+lazy_static! {
+    static ref PROVIDERS: Vec<Provider> = load_providers(include_str!("oembed_providers.json"))
+        .expect("bundled oEmbed providers list is valid");
+}
+
+#[derive(Deserialize, Debug)]
+struct ProviderEndpoint {
+    #[serde(default)]
+    schemes: Vec<String>,
+    url: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ProviderEntry {
+    provider_name: String,
+    endpoints: Vec<ProviderEndpoint>,
+}
+
+#[derive(Debug)]
+pub struct Provider {
+    pub name: String,
+    pub endpoint: String,
+    schemes: Vec<Regex>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct OEmbed {
+    pub kind: String,
+    pub title: String,
+    pub author_name: String,
+    pub author_url: String,
+    pub provider_name: String,
+    pub thumbnail_url: String,
+    pub url: String,
+    pub html: String,
+}
+
+fn scheme_to_regex(scheme: &str) -> Result<Regex> {
+    let pattern = scheme.split('*').map(regex::escape).collect::<Vec<_>>().join(".*");
+    Ok(Regex::new(&format!("^{}$", pattern))?)
+}
+
+fn load_providers(json: &str) -> Result<Vec<Provider>> {
+    let entries: Vec<ProviderEntry> = serde_json::from_str(json)?;
+    let mut providers = Vec::new();
+    for entry in entries {
+        for endpoint in entry.endpoints {
+            providers.push(Provider {
+                name: entry.provider_name.clone(),
+                endpoint: endpoint.url.replace("{format}", "json"),
+                schemes: endpoint.schemes.iter().map(|s| scheme_to_regex(s)).collect::<Result<Vec<_>>>()?,
+            });
+        }
+    }
+    Ok(providers)
+}
+
+pub fn find_provider(url: &str) -> Option<&'static Provider> {
+    PROVIDERS.iter().find(|provider| provider.schemes.iter().any(|scheme| scheme.is_match(url)))
+}
+
+pub fn discover_endpoint(page_url: &str, html: &str) -> Option<String> {
+    let document = Html::parse_document(html);
+    let selector = Selector::parse(r#"link[type="application/json+oembed"]"#).expect("valid selector");
+    let href = document.select(&selector).next()?.value().attr("href")?;
+    Url::parse(page_url).and_then(|base| base.join(href)).ok().map(String::from)
+}
+
+fn page_title(html: &str) -> String {
+    let document = Html::parse_document(html);
+    let selector = Selector::parse("title").expect("valid selector");
+    document.select(&selector).next()
+        .map(|title| html_to_text(&title.inner_html()))
+        .unwrap_or_default()
+}
+
+pub fn parse_oembed(response: &Value) -> OEmbed {
+    let text = |key: &str| response[key].as_str().unwrap_or_default().to_string();
+    OEmbed {
+        kind: text("type"),
+        title: text("title"),
+        author_name: text("author_name"),
+        author_url: text("author_url"),
+        provider_name: text("provider_name"),
+        thumbnail_url: text("thumbnail_url"),
+        url: text("url"),
+        html: text("html"),
+    }
+}
+
+async fn request_oembed(client: &HttpClient, endpoint: &str, url: &str, width: usize, height: usize) -> Result<OEmbed> {
+    debug!("request_oembed: endpoint={} url={} width={} height={}", endpoint, url, width, height);
+    let mut request_url = Url::parse(endpoint)?;
+    if !request_url.query_pairs().any(|(key, _)| key == "url") {
+        request_url.query_pairs_mut().append_pair("url", url);
+    }
+    if !request_url.query_pairs().any(|(key, _)| key == "format") {
+        request_url.query_pairs_mut().append_pair("format", "json");
+    }
+    request_url.query_pairs_mut()
+        .append_pair("maxwidth", &width.to_string())
+        .append_pair("maxheight", &height.to_string());
+
+    let response = client.get_cached("oembed", request_url.as_str()).await?;
+    if !response.status().is_success() {
+        return Err(Error::http(endpoint, response.status()).into());
+    }
+    Ok(parse_oembed(&client.json::<Value>(response).await?))
+}
+
+pub async fn fetch_oembed(client: &HttpClient, url: &str, width: usize, height: usize) -> Result<(OEmbed, String)> {
+    debug!("fetch_oembed: url={} width={} height={}", url, width, height);
+    if let Some(provider) = find_provider(url) {
+        debug!("Using bundled oEmbed provider {}", provider.name);
+        match request_oembed(client, &provider.endpoint, url, width, height).await {
+            Ok(oembed) => return Ok((oembed, String::new())),
+            Err(e) => warn!("Bundled oEmbed provider {} failed for {}: {}", provider.name, url, e),
+        }
+    }
+    discover_oembed(client, url, width, height).await?.ok_or_else(|| eyre!("No oEmbed endpoint found for {}", url))
+}
+
+/// Fetches the page at `url` and requests the oEmbed endpoint it advertises, returning the
+/// response and the page title. `Ok(None)` means the page advertises no endpoint.
+pub async fn discover_oembed(client: &HttpClient, url: &str, width: usize, height: usize) -> Result<Option<(OEmbed, String)>> {
+    debug!("discover_oembed: url={} width={} height={}", url, width, height);
+    let response = client.get_cached("oembed", url).await?;
+    if !response.status().is_success() {
+        return Err(Error::http(url, response.status()).into());
+    }
+    let html = client.text(response).await?;
+    let Some(endpoint) = discover_endpoint(url, &html) else {
+        return Ok(None);
+    };
+    let oembed = request_oembed(client, &endpoint, url, width, height).await?;
+    Ok(Some((oembed, page_title(&html))))
+}
+
+fn generate_oembed_embed_code(oembed: &OEmbed, width: usize, height: usize) -> String {
+    if !oembed.html.is_empty() {
+        oembed.html.clone()
+    } else if oembed.kind == "photo" && !oembed.url.is_empty() {
+        generate_image_embed_code(&oembed.url, width, height)
+    } else if !oembed.thumbnail_url.is_empty() {
+        generate_image_embed_code(&oembed.thumbnail_url, width, height)
+    } else {
+        String::new()
+    }
+}
+
+pub async fn handle_oembed_url(url: &str, folder: &str, width: usize, height: usize, config: &Config) -> Result<Note> {
+    debug!("handle_oembed_url: url={} folder={} width={} height={} config={:?}", url, folder, width, height, config);
+    let (oembed, page_title) = fetch_oembed(config.http()?, url, width, height).await?;
+    Ok(oembed_note(url, folder, width, height, &oembed, &page_title))
+}
+
+pub fn oembed_note(url: &str, folder: &str, width: usize, height: usize, oembed: &OEmbed, page_title: &str) -> Note {
+    let title = [oembed.title.as_str(), page_title]
+        .into_iter()
+        .find(|title| !title.is_empty())
+        .unwrap_or(url)
+        .to_string();
+    let mut description = String::new();
+    if !oembed.provider_name.is_empty() {
+        description += &format!("**Provider:** {}", oembed.provider_name);
+    }
+    if !oembed.author_url.is_empty() {
+        description += &format!("\n**Author:** [{}]({})", oembed.author_name, oembed.author_url);
+    }
+    if !oembed.thumbnail_url.is_empty() && !oembed.html.is_empty() {
+        description += &format!("\n\n![thumbnail]({})", oembed.thumbnail_url);
+    }
+    let mut tags = Vec::new();
+    if !oembed.provider_name.is_empty() {
+        tags.push(oembed.provider_name.clone());
+    }
+    Note {
+        title,
+        description: description.trim().to_string(),
+        embed_code: generate_oembed_embed_code(oembed, width, height),
+        url: url.to_string(),
+        folder: folder.to_string(),
+        author: oembed.author_name.clone(),
+        tags,
+        ..Note::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_find_provider_from_bundled_list() {
+        assert_eq!(find_provider("https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC").map(|p| p.name.as_str()), Some("Spotify"));
+        assert_eq!(find_provider("https://codepen.io/team/codepen/pen/PNaGbb").map(|p| p.name.as_str()), Some("CodePen"));
+        assert_eq!(find_provider("https://www.figma.com/file/abc/Design").map(|p| p.endpoint.as_str()), Some("https://www.figma.com/api/oembed"));
+        assert!(find_provider("https://example.com/nothing").is_none());
+    }
+
+    #[test]
+    fn test_discover_endpoint() {
+        let html = r#"<html><head>
+            <link rel="alternate" type="application/json+oembed" href="/oembed?url=https%3A%2F%2Fexample.com%2Fa&format=json" title="A">
+        </head></html>"#;
+        assert_eq!(
+            discover_endpoint("https://example.com/a", html),
+            Some("https://example.com/oembed?url=https%3A%2F%2Fexample.com%2Fa&format=json".to_string())
+        );
+        assert_eq!(discover_endpoint("https://example.com/a", "<html></html>"), None);
+    }
+
+    #[test]
+    fn test_embed_code_prefers_html_then_photo() {
+        let rich = parse_oembed(&json!({"type": "rich", "html": "<iframe src=\"x\"></iframe>", "thumbnail_url": "t.png"}));
+        assert_eq!(generate_oembed_embed_code(&rich, 10, 20), "<iframe src=\"x\"></iframe>");
+        let photo = parse_oembed(&json!({"type": "photo", "url": "https://live.staticflickr.com/1.jpg"}));
+        assert_eq!(generate_oembed_embed_code(&photo, 10, 20), generate_image_embed_code("https://live.staticflickr.com/1.jpg", 10, 20));
+    }
+}
@@ -0,0 +1,354 @@
+use log::{debug, warn};
+use regex::Regex;
+use serde_json::Value;
+use eyre::{eyre, Result};
+use lazy_static::lazy_static;
+
+use crate::error::Error;
+use crate::html::html_to_markdown;
+use crate::http::HttpClient;
+use crate::{Config, Note};
+
+lazy_static! {
+    static ref CRATES_REGEX: Regex = Regex::new(r"^https?://(?:www\.)?crates\.io/crates/([A-Za-z0-9_-]+)").expect("valid regex");
+    static ref DOCSRS_REGEX: Regex = Regex::new(r"^https?://docs\.rs/(?:crate/)?([A-Za-z0-9_-]+)").expect("valid regex");
+    static ref NPM_REGEX: Regex = Regex::new(r"^https?://(?:www\.)?npmjs\.com/package/((?:@[^/]+/)?[^/?#]+)").expect("valid regex");
+    static ref PYPI_REGEX: Regex = Regex::new(r"^https?://(?:www\.)?pypi\.org/project/([^/?#]+)").expect("valid regex");
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Registry {
+    Crates,
+    Npm,
+    PyPI,
+}
+
+impl Registry {
+    fn name(&self) -> &'static str {
+        match self {
+            Registry::Crates => "crates.io",
+            Registry::Npm => "npm",
+            Registry::PyPI => "pypi",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Package {
+    pub registry: Registry,
+    pub name: String,
+    pub version: String,
+    pub description: String,
+    pub repository: String,
+    pub homepage: String,
+    pub license: String,
+    pub author: String,
+    pub downloads: Option<u64>,
+    pub tags: Vec<String>,
+    pub readme: String,
+}
+
+impl Package {
+    fn new(registry: Registry, name: &str) -> Package {
+        Package {
+            registry,
+            name: name.to_string(),
+            version: String::new(),
+            description: String::new(),
+            repository: String::new(),
+            homepage: String::new(),
+            license: String::new(),
+            author: String::new(),
+            downloads: None,
+            tags: Vec::new(),
+            readme: String::new(),
+        }
+    }
+}
+
+pub fn parse_package_url(url: &str) -> Option<(Registry, String)> {
+    if let Some(caps) = CRATES_REGEX.captures(url).or_else(|| DOCSRS_REGEX.captures(url)) {
+        return Some((Registry::Crates, caps[1].to_string()));
+    }
+    if let Some(caps) = NPM_REGEX.captures(url) {
+        return Some((Registry::Npm, caps[1].to_string()));
+    }
+    PYPI_REGEX.captures(url).map(|caps| (Registry::PyPI, caps[1].to_string()))
+}
+
+async fn get(client: &HttpClient, url: &str) -> Result<reqwest::Response> {
+    debug!("get: url={}", url);
+    let response = client.get_cached("package", url).await?;
+    if !response.status().is_success() {
+        return Err(Error::http(url, response.status()).into());
+    }
+    Ok(response)
+}
+
+fn push_tags(tags: &mut Vec<String>, values: &Value) {
+    for tag in values.as_array().into_iter().flatten().filter_map(|tag| tag.as_str()) {
+        let tag = tag.trim().to_string();
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+}
+
+pub fn parse_crate(response: &Value) -> Result<Package> {
+    let krate = &response["crate"];
+    let name = krate["name"].as_str().ok_or_else(|| eyre!("crates.io response has no crate name"))?;
+    let mut package = Package::new(Registry::Crates, name);
+    package.version = krate["max_stable_version"].as_str()
+        .or_else(|| krate["newest_version"].as_str())
+        .unwrap_or_default()
+        .to_string();
+    package.description = krate["description"].as_str().unwrap_or_default().trim().to_string();
+    package.repository = krate["repository"].as_str().unwrap_or_default().to_string();
+    package.homepage = krate["homepage"].as_str().unwrap_or_default().to_string();
+    package.downloads = krate["downloads"].as_u64();
+    package.license = response["versions"].as_array()
+        .and_then(|versions| versions.iter().find(|v| v["num"].as_str() == Some(package.version.as_str())).or(versions.first()))
+        .and_then(|version| version["license"].as_str())
+        .unwrap_or_default()
+        .to_string();
+    push_tags(&mut package.tags, &krate["keywords"]);
+    push_tags(&mut package.tags, &krate["categories"]);
+    Ok(package)
+}
+
+pub fn parse_npm(response: &Value) -> Result<Package> {
+    let name = response["name"].as_str().ok_or_else(|| eyre!("npm response has no package name"))?;
+    let mut package = Package::new(Registry::Npm, name);
+    package.version = response["dist-tags"]["latest"].as_str().unwrap_or_default().to_string();
+    let latest = &response["versions"][package.version.as_str()];
+    package.description = response["description"].as_str().or_else(|| latest["description"].as_str()).unwrap_or_default().to_string();
+    let repository = if response["repository"].is_string() { &response["repository"] } else { &response["repository"]["url"] };
+    package.repository = repository.as_str().unwrap_or_default()
+        .trim_start_matches("git+")
+        .trim_end_matches(".git")
+        .replace("git://", "https://")
+        .replace("ssh://git@", "https://")
+        .to_string();
+    package.homepage = response["homepage"].as_str().unwrap_or_default().to_string();
+    package.license = response["license"].as_str().or_else(|| latest["license"].as_str()).unwrap_or_default().to_string();
+    package.author = response["author"]["name"].as_str()
+        .or_else(|| response["author"].as_str())
+        .unwrap_or_default()
+        .to_string();
+    package.readme = response["readme"].as_str().unwrap_or_default().to_string();
+    push_tags(&mut package.tags, &response["keywords"]);
+    Ok(package)
+}
+
+pub fn parse_pypi(response: &Value) -> Result<Package> {
+    let info = &response["info"];
+    let name = info["name"].as_str().ok_or_else(|| eyre!("PyPI response has no project name"))?;
+    let mut package = Package::new(Registry::PyPI, name);
+    package.version = info["version"].as_str().unwrap_or_default().to_string();
+    package.description = info["summary"].as_str().unwrap_or_default().to_string();
+    let project_urls = &info["project_urls"];
+    package.repository = ["Source", "Source Code", "Repository", "Code", "GitHub"]
+        .iter()
+        .find_map(|key| project_urls[*key].as_str())
+        .unwrap_or_default()
+        .to_string();
+    package.homepage = info["home_page"].as_str()
+        .or_else(|| project_urls["Homepage"].as_str())
+        .unwrap_or_default()
+        .to_string();
+    package.license = info["license_expression"].as_str()
+        .or_else(|| info["license"].as_str().filter(|license| license.len() < 64))
+        .unwrap_or_default()
+        .to_string();
+    package.author = info["author"].as_str().unwrap_or_default().to_string();
+    package.readme = info["description"].as_str().unwrap_or_default().to_string();
+    let keywords = info["keywords"].as_str().unwrap_or_default();
+    let separator = if keywords.contains(',') { ',' } else { ' ' };
+    for keyword in keywords.split(separator).map(str::trim).filter(|k| !k.is_empty()) {
+        package.tags.push(keyword.to_string());
+    }
+    for classifier in info["classifiers"].as_array().into_iter().flatten().filter_map(|c| c.as_str()) {
+        if let Some(topic) = classifier.strip_prefix("Topic :: ") {
+            let topic = topic.rsplit(" :: ").next().unwrap_or(topic).to_string();
+            if !package.tags.contains(&topic) {
+                package.tags.push(topic);
+            }
+        }
+    }
+    Ok(package)
+}
+
+async fn fetch_crate(client: &HttpClient, name: &str) -> Result<Package> {
+    let response = client.json::<Value>(get(client, &format!("https://crates.io/api/v1/crates/{}", name)).await?).await?;
+    let mut package = parse_crate(&response)?;
+    let readme_url = format!("https://crates.io/api/v1/crates/{}/{}/readme", package.name, package.version);
+    match get(client, &readme_url).await {
+        Ok(response) => package.readme = html_to_markdown(&client.text(response).await?),
+        Err(e) => warn!("Failed to fetch README for crate {}: {}", name, e),
+    }
+    Ok(package)
+}
+
+async fn fetch_npm(client: &HttpClient, name: &str) -> Result<Package> {
+    let encoded = name.replace('/', "%2f");
+    let response = client.json::<Value>(get(client, &format!("https://registry.npmjs.org/{}", encoded)).await?).await?;
+    let mut package = parse_npm(&response)?;
+    match get(client, &format!("https://api.npmjs.org/downloads/point/last-month/{}", name)).await {
+        Ok(response) => package.downloads = client.json::<Value>(response).await?["downloads"].as_u64(),
+        Err(e) => warn!("Failed to fetch download count for npm package {}: {}", name, e),
+    }
+    Ok(package)
+}
+
+async fn fetch_pypi(client: &HttpClient, name: &str) -> Result<Package> {
+    let response = client.json::<Value>(get(client, &format!("https://pypi.org/pypi/{}/json", name)).await?).await?;
+    let mut package = parse_pypi(&response)?;
+    match get(client, &format!("https://pypistats.org/api/packages/{}/recent", name.to_lowercase())).await {
+        Ok(response) => package.downloads = client.json::<Value>(response).await?["data"]["last_month"].as_u64(),
+        Err(e) => warn!("Failed to fetch download count for PyPI project {}: {}", name, e),
+    }
+    Ok(package)
+}
+
+pub async fn fetch_package(client: &HttpClient, url: &str) -> Result<Package> {
+    debug!("fetch_package: url={}", url);
+    let (registry, name) = parse_package_url(url).ok_or_else(|| eyre!("Unsupported package registry URL: {}", url))?;
+    match registry {
+        Registry::Crates => fetch_crate(client, &name).await,
+        Registry::Npm => fetch_npm(client, &name).await,
+        Registry::PyPI => fetch_pypi(client, &name).await,
+    }
+}
+
+fn package_fields(package: &Package) -> Vec<(String, serde_yaml::Value)> {
+    let mut fields = vec![
+        ("registry".to_string(), serde_yaml::Value::from(package.registry.name())),
+        ("package".to_string(), serde_yaml::Value::from(package.name.as_str())),
+        ("version".to_string(), serde_yaml::Value::from(package.version.as_str())),
+    ];
+    if !package.license.is_empty() {
+        fields.push(("license".to_string(), serde_yaml::Value::from(package.license.as_str())));
+    }
+    if !package.repository.is_empty() {
+        fields.push(("repository".to_string(), serde_yaml::Value::from(package.repository.as_str())));
+    }
+    if let Some(downloads) = package.downloads {
+        fields.push(("downloads".to_string(), serde_yaml::Value::from(downloads)));
+    }
+    fields
+}
+
+fn render_description(package: &Package) -> String {
+    let mut description = package.description.clone();
+    if !package.homepage.is_empty() && package.homepage != package.repository {
+        description += &format!("\n\n**Homepage:** {}", package.homepage);
+    }
+    if !package.readme.is_empty() {
+        description += &format!("\n\n## README\n{}", package.readme.trim());
+    }
+    description
+}
+
+pub async fn handle_package_url(url: &str, folder: &str, config: &Config) -> Result<Note> {
+    debug!("handle_package_url: url={} folder={} config={:?}", url, folder, config);
+    let package = fetch_package(config.http()?, url).await?;
+    let embed_code = if package.repository.is_empty() {
+        String::new()
+    } else {
+        format!("[{}]({})", package.repository, package.repository)
+    };
+    Ok(Note {
+        id: package.name.clone(),
+        title: package.name.clone(),
+        description: render_description(&package),
+        embed_code,
+        url: url.to_string(),
+        folder: folder.to_string(),
+        author: package.author.clone(),
+        tags: package.tags.clone(),
+        note_type: Some("library".to_string()),
+        fields: package_fields(&package),
+        ..Note::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_package_url() {
+        assert_eq!(parse_package_url("https://crates.io/crates/serde_json"), Some((Registry::Crates, "serde_json".into())));
+        assert_eq!(parse_package_url("https://docs.rs/tokio/latest/tokio/"), Some((Registry::Crates, "tokio".into())));
+        assert_eq!(parse_package_url("https://docs.rs/crate/regex/1.10.2"), Some((Registry::Crates, "regex".into())));
+        assert_eq!(parse_package_url("https://www.npmjs.com/package/@types/node?activeTab=readme"), Some((Registry::Npm, "@types/node".into())));
+        assert_eq!(parse_package_url("https://pypi.org/project/requests/2.31.0/"), Some((Registry::PyPI, "requests".into())));
+        assert_eq!(parse_package_url("https://github.com/serde-rs/json"), None);
+    }
+
+    #[test]
+    fn test_parse_crate() {
+        let response = json!({
+            "crate": {
+                "name": "serde_json",
+                "max_stable_version": "1.0.108",
+                "newest_version": "1.0.109-beta",
+                "description": "A JSON serialization file format\n",
+                "repository": "https://github.com/serde-rs/json",
+                "downloads": 300000000u64,
+                "keywords": ["json", "serde"],
+                "categories": ["encoding"]
+            },
+            "versions": [
+                {"num": "1.0.109-beta", "license": "MIT"},
+                {"num": "1.0.108", "license": "MIT OR Apache-2.0"}
+            ]
+        });
+        let package = parse_crate(&response).expect("crate");
+        assert_eq!(package.version, "1.0.108");
+        assert_eq!(package.license, "MIT OR Apache-2.0");
+        assert_eq!(package.description, "A JSON serialization file format");
+        assert_eq!(package.tags, vec!["json".to_string(), "serde".to_string(), "encoding".to_string()]);
+        assert_eq!(package.downloads, Some(300000000));
+    }
+
+    #[test]
+    fn test_parse_npm_and_pypi() {
+        let npm = parse_npm(&json!({
+            "name": "left-pad",
+            "dist-tags": {"latest": "1.3.0"},
+            "versions": {"1.3.0": {"license": "WTFPL"}},
+            "description": "String left pad",
+            "repository": {"type": "git", "url": "git+https://github.com/stevemao/left-pad.git"},
+            "author": {"name": "azer"},
+            "keywords": ["leftpad", "padding"],
+            "readme": "# left-pad"
+        })).expect("npm");
+        assert_eq!(npm.repository, "https://github.com/stevemao/left-pad");
+        assert_eq!(npm.license, "WTFPL");
+        assert_eq!(npm.author, "azer");
+
+        let pypi = parse_pypi(&json!({"info": {
+            "name": "requests",
+            "version": "2.31.0",
+            "summary": "Python HTTP for Humans.",
+            "project_urls": {"Source": "https://github.com/psf/requests"},
+            "license": "Apache 2.0",
+            "keywords": "http, client",
+            "classifiers": ["Topic :: Internet :: WWW/HTTP", "License :: OSI Approved"],
+            "description": "# Requests"
+        }})).expect("pypi");
+        assert_eq!(pypi.repository, "https://github.com/psf/requests");
+        assert_eq!(pypi.tags, vec!["http".to_string(), "client".to_string(), "WWW/HTTP".to_string()]);
+    }
+
+    #[test]
+    fn test_package_fields() {
+        let mut package = Package::new(Registry::Crates, "regex");
+        package.version = "1.10.2".into();
+        package.downloads = Some(42);
+        let fields = package_fields(&package);
+        assert_eq!(fields.iter().map(|(key, _)| key.as_str()).collect::<Vec<_>>(), vec!["registry", "package", "version", "downloads"]);
+    }
+}
@@ -0,0 +1,274 @@
+use log::{debug, warn};
+use eyre::{eyre, Result};
+use lopdf::{decode_text_string, Document};
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+use crate::filename::safe_file_stem;
+use crate::http::HttpClient;
+use crate::{summarize_text_with_chatgpt, Attachment, Config, Link, Note};
+
+const DEFAULT_MAX_SIZE_MB: u64 = 20;
+const DEFAULT_PAGES: u32 = 5;
+const MAX_SUMMARY_CHARS: usize = 12000;
+const MAX_DECOMPRESSED_PAGE_SIZE: usize = 16 * 1024 * 1024;
+const MAX_ATTACHMENT_STEM: usize = 80;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfOptions {
+    pub max_size: u64,
+    pub pages: u32,
+    pub attachments: Option<String>,
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        PdfOptions {
+            max_size: DEFAULT_MAX_SIZE_MB * 1024 * 1024,
+            pages: DEFAULT_PAGES,
+            attachments: None,
+        }
+    }
+}
+
+impl PdfOptions {
+    pub fn from_link(link: &Link) -> PdfOptions {
+        PdfOptions {
+            max_size: link.max_size_mb.unwrap_or(DEFAULT_MAX_SIZE_MB) * 1024 * 1024,
+            pages: link.pages.unwrap_or(DEFAULT_PAGES),
+            attachments: link.attachments.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct PdfDocument {
+    pub title: String,
+    pub author: String,
+    pub page_count: usize,
+    pub text: String,
+}
+
+pub fn is_pdf_path(url: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    path.to_lowercase().ends_with(".pdf")
+}
+
+pub async fn is_pdf_content_type(client: &HttpClient, url: &str) -> bool {
+    debug!("is_pdf_content_type: url={}", url);
+    match client.head(url).await {
+        Ok(response) => response.headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_lowercase().starts_with("application/pdf"))
+            .unwrap_or(false),
+        Err(e) => {
+            debug!("HEAD request failed for {}: {}", url, e);
+            false
+        }
+    }
+}
+
+async fn download_pdf(client: &HttpClient, url: &str, max_size: u64) -> Result<Vec<u8>> {
+    debug!("download_pdf: url={} max_size={}", url, max_size);
+    let response = client.get(url).await?;
+    if !response.status().is_success() {
+        return Err(Error::http(url, response.status()).into());
+    }
+    let bytes = client.bytes_limited(response, max_size).await?;
+    if !bytes.starts_with(b"%PDF") {
+        return Err(eyre!("Response from {} is not a PDF", url));
+    }
+    Ok(bytes)
+}
+
+fn info_string(document: &Document, key: &[u8]) -> String {
+    document.trailer.get(b"Info")
+        .and_then(|info| document.dereference(info))
+        .and_then(|(_, info)| info.as_dict())
+        .and_then(|info| info.get_deref(key, document))
+        .and_then(decode_text_string)
+        .map(|value| value.trim().to_string())
+        .unwrap_or_default()
+}
+
+pub fn extract_pdf(bytes: &[u8], pages: u32) -> Result<PdfDocument> {
+    debug!("extract_pdf: bytes.len={} pages={}", bytes.len(), pages);
+    let document = Document::load_mem(bytes).map_err(|e| eyre!("Failed to parse PDF: {}", e))?;
+    let page_numbers: Vec<u32> = document.get_pages().keys().copied().take(pages as usize).collect();
+
+    let mut text = String::new();
+    for page in &page_numbers {
+        match document.extract_text_with_limit(&[*page], MAX_DECOMPRESSED_PAGE_SIZE) {
+            Ok(page_text) => text.push_str(&page_text),
+            Err(e) => warn!("Failed to extract text from PDF page {}: {}", page, e),
+        }
+    }
+
+    Ok(PdfDocument {
+        title: info_string(&document, b"Title"),
+        author: info_string(&document, b"Author"),
+        page_count: document.get_pages().len(),
+        text: text.split_whitespace().collect::<Vec<_>>().join(" "),
+    })
+}
+
+fn title_from_url(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let name = path.trim_end_matches('/').rsplit('/').next().unwrap_or_default();
+    name.trim_end_matches(".pdf").trim_end_matches(".PDF").replace(['_', '-', '+'], " ")
+}
+
+/// `<title> <hash>.pdf`: the content hash keeps different PDFs with the same (or no) title apart,
+/// while saving the same PDF twice reuses its file.
+pub fn attachment_name(title: &str, bytes: &[u8]) -> String {
+    let hash = Sha256::digest(bytes).iter().take(4).map(|byte| format!("{:02x}", byte)).collect::<String>();
+    let stem = safe_file_stem(title, MAX_ATTACHMENT_STEM);
+    let stem = if stem.is_empty() { "document".to_string() } else { stem };
+    format!("{} {}.pdf", stem, hash)
+}
+
+pub async fn handle_pdf_url(url: &str, folder: &str, options: &PdfOptions, config: &Config) -> Result<Note> {
+    debug!("handle_pdf_url: url={} folder={} options={:?} config={:?}", url, folder, options, config);
+    let bytes = download_pdf(config.http()?, url, options.max_size).await?;
+    let pdf = extract_pdf(&bytes, options.pages)?;
+
+    let excerpt: String = pdf.text.chars().take(MAX_SUMMARY_CHARS).collect();
+    let (llm_title, summary, llm_author, _, tags) = if excerpt.is_empty() {
+        warn!("No text could be extracted from {}", url);
+        (String::new(), String::new(), String::new(), String::new(), Vec::new())
+    } else {
+        match summarize_text_with_chatgpt(config, url, &excerpt).await {
+            Ok(result) => result,
+            Err(e) => {
+                warn!("Failed to summarize PDF {}: {}", url, e);
+                let fallback: String = pdf.text.chars().take(1000).collect();
+                (String::new(), fallback, String::new(), String::new(), Vec::new())
+            }
+        }
+    };
+
+    let title = [&pdf.title, &llm_title]
+        .into_iter()
+        .find(|title| !title.is_empty() && !title.starts_with("No Title"))
+        .cloned()
+        .unwrap_or_else(|| title_from_url(url));
+    let author = if pdf.author.is_empty() { llm_author } else { pdf.author.clone() };
+
+    let mut attachments = Vec::new();
+    let embed_code = match &options.attachments {
+        Some(attachments_folder) => {
+            let file_name = attachment_name(&title, &bytes);
+            let embed_code = format!("![[{}]]", file_name);
+            attachments.push(Attachment { folder: attachments_folder.clone(), file_name, bytes });
+            embed_code
+        }
+        None => format!("[PDF ({} pages)]({})", pdf.page_count, url),
+    };
+
+    Ok(Note {
+        title,
+        description: summary,
+        embed_code,
+        url: url.to_string(),
+        folder: folder.to_string(),
+        author,
+        tags,
+        attachments,
+        ..Note::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::content::{Content, Operation};
+    use lopdf::{dictionary, Object, Stream, StringFormat};
+
+    fn build_pdf(pages: &[&str]) -> Vec<u8> {
+        let mut document = Document::with_version("1.5");
+        let pages_id = document.new_object_id();
+        let font_id = document.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Courier",
+        });
+        let resources_id = document.add_object(dictionary! {
+            "Font" => dictionary! { "F1" => font_id },
+        });
+        let mut kids = Vec::new();
+        for text in pages {
+            let content = Content {
+                operations: vec![
+                    Operation::new("BT", vec![]),
+                    Operation::new("Tf", vec!["F1".into(), 12.into()]),
+                    Operation::new("Td", vec![100.into(), 600.into()]),
+                    Operation::new("Tj", vec![Object::string_literal(*text)]),
+                    Operation::new("ET", vec![]),
+                ],
+            };
+            let content_id = document.add_object(Stream::new(dictionary! {}, content.encode().expect("encode content")));
+            let page_id = document.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "Contents" => content_id,
+            });
+            kids.push(page_id.into());
+        }
+        let count = kids.len() as i64;
+        document.objects.insert(pages_id, Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => count,
+            "Resources" => resources_id,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+        }));
+        let catalog_id = document.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        let info_id = document.add_object(dictionary! {
+            "Title" => Object::String(b"A Whitepaper".to_vec(), StringFormat::Literal),
+            "Author" => Object::String(b"Jane Doe".to_vec(), StringFormat::Literal),
+        });
+        document.trailer.set("Root", catalog_id);
+        document.trailer.set("Info", info_id);
+        let mut bytes = Vec::new();
+        document.save_to(&mut bytes).expect("save pdf");
+        bytes
+    }
+
+    #[test]
+    fn test_extract_pdf_info_and_first_pages() {
+        let bytes = build_pdf(&["Abstract page", "Introduction page", "Appendix page"]);
+        let pdf = extract_pdf(&bytes, 2).expect("extract pdf");
+        assert_eq!(pdf.title, "A Whitepaper");
+        assert_eq!(pdf.author, "Jane Doe");
+        assert_eq!(pdf.page_count, 3);
+        assert!(pdf.text.contains("Abstract page"));
+        assert!(pdf.text.contains("Introduction page"));
+        assert!(!pdf.text.contains("Appendix page"));
+    }
+
+    #[test]
+    fn test_extract_pdf_rejects_garbage() {
+        assert!(extract_pdf(b"not a pdf", 1).is_err());
+    }
+
+    #[test]
+    fn test_is_pdf_path_and_title_from_url() {
+        assert!(is_pdf_path("https://www.rfc-editor.org/rfc/rfc9110.pdf"));
+        assert!(is_pdf_path("https://example.com/paper.PDF?download=1"));
+        assert!(!is_pdf_path("https://example.com/pdf/viewer"));
+        assert_eq!(title_from_url("https://example.com/papers/attention_is-all+you.pdf?x=1"), "attention is all you");
+    }
+
+    #[test]
+    fn test_attachment_name_separates_same_titles() {
+        let first = attachment_name("Untitled", b"%PDF-1 first");
+        assert!(first.starts_with("Untitled ") && first.ends_with(".pdf"), "got: {}", first);
+        assert_ne!(first, attachment_name("Untitled", b"%PDF-1 second"));
+        assert_eq!(first, attachment_name("Untitled", b"%PDF-1 first"));
+        assert!(attachment_name("???", b"%PDF").starts_with("document "));
+    }
+}
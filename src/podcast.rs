@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+
+use log::debug;
+use eyre::{eyre, Result};
+use reqwest::Url;
+use roxmltree::{Document, Node, ParsingOptions};
+use scraper::{Html, Selector};
+
+use crate::error::Error;
+use crate::html::html_to_markdown;
+use crate::http::HttpClient;
+use crate::{Config, Note};
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PodcastEpisode {
+    pub title: String,
+    pub show: String,
+    pub author: String,
+    pub published_at: String,
+    pub duration: String,
+    pub show_notes: String,
+    pub audio_url: String,
+    pub audio_type: String,
+    pub link: String,
+    pub tags: Vec<String>,
+}
+
+async fn fetch_text(client: &HttpClient, url: &str) -> Result<(String, String)> {
+    debug!("fetch_text: url={}", url);
+    let response = client.get_cached("podcast", url).await?;
+    if !response.status().is_success() {
+        return Err(Error::http(url, response.status()).into());
+    }
+    let content_type = response.headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_lowercase();
+    Ok((content_type, client.text(response).await?))
+}
+
+fn looks_like_feed(content_type: &str, body: &str) -> bool {
+    let head = body.trim_start();
+    content_type.contains("rss")
+        || (content_type.contains("xml") && !content_type.contains("html"))
+        || (head.starts_with("<?xml") && head.contains("<rss"))
+        || head.starts_with("<rss")
+}
+
+pub fn discover_feed_url(page_url: &str, html: &str) -> Option<String> {
+    let document = Html::parse_document(html);
+    let selector = Selector::parse(r#"link[rel~="alternate"][type="application/rss+xml"]"#).expect("valid selector");
+    let href = document.select(&selector).next()?.value().attr("href")?;
+    Url::parse(page_url).and_then(|base| base.join(href)).ok().map(String::from)
+}
+
+// `prefix` is a host, optionally followed by a path, as written in the rule's `feeds`
+fn matches_prefix(url: &str, prefix: &str) -> bool {
+    let Ok(parsed) = Url::parse(url) else { return false };
+    let Some(host) = parsed.host_str() else { return false };
+    let location = format!("{}{}", host.trim_start_matches("www."), parsed.path());
+    let prefix = prefix.trim_start_matches("www.").trim_end_matches('/');
+    location.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+fn mapped_feed_url(url: &str, feeds: &HashMap<String, String>) -> Option<String> {
+    feeds.iter()
+        .filter(|(prefix, _)| matches_prefix(url, prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, feed)| feed.clone())
+}
+
+/// Whether `url` belongs to a show listed in a rule's `feeds`.
+pub fn has_mapped_feed(url: &str, feeds: &HashMap<String, String>) -> bool {
+    feeds.keys().any(|prefix| matches_prefix(url, prefix))
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.is_element() && child.tag_name().name() == name)
+}
+
+fn child_text(node: Node, name: &str) -> String {
+    child(node, name)
+        .map(|child| child.descendants().filter(|n| n.is_text()).filter_map(|n| n.text()).collect::<String>())
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+fn normalize(url: &str) -> String {
+    url.trim()
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_start_matches("www.")
+        .trim_end_matches('/')
+        .to_string()
+}
+
+fn format_duration(duration: &str) -> String {
+    if duration.is_empty() || duration.contains(':') {
+        return duration.to_string();
+    }
+    match duration.parse::<u64>() {
+        Ok(seconds) if seconds >= 3600 => format!("{}:{:02}:{:02}", seconds / 3600, seconds % 3600 / 60, seconds % 60),
+        Ok(seconds) => format!("{}:{:02}", seconds / 60, seconds % 60),
+        Err(_) => duration.to_string(),
+    }
+}
+
+fn parse_item(channel: Node, item: Node) -> PodcastEpisode {
+    let enclosure = child(item, "enclosure");
+    let notes = [child_text(item, "encoded"), child_text(item, "description"), child_text(item, "summary")]
+        .into_iter()
+        .find(|notes| !notes.is_empty())
+        .unwrap_or_default();
+    let mut tags: Vec<String> = child_text(item, "keywords")
+        .split(',')
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    for category in item.children().filter(|n| n.is_element() && n.tag_name().name() == "category") {
+        let category = category.text().unwrap_or_default().trim().to_string();
+        if !category.is_empty() && !tags.contains(&category) {
+            tags.push(category);
+        }
+    }
+    let author = [child_text(item, "author"), child_text(channel, "author")]
+        .into_iter()
+        .find(|author| !author.is_empty())
+        .unwrap_or_default();
+
+    PodcastEpisode {
+        title: child_text(item, "title"),
+        show: child_text(channel, "title"),
+        author,
+        published_at: child_text(item, "pubDate"),
+        duration: format_duration(&child_text(item, "duration")),
+        show_notes: html_to_markdown(&notes),
+        audio_url: enclosure.and_then(|e| e.attribute("url")).unwrap_or_default().to_string(),
+        audio_type: enclosure.and_then(|e| e.attribute("type")).unwrap_or("audio/mpeg").to_string(),
+        link: child_text(item, "link"),
+        tags,
+    }
+}
+
+pub fn find_episode(feed: &str, url: &str, page_title: Option<&str>) -> Result<PodcastEpisode> {
+    debug!("find_episode: feed.len={} url={} page_title={:?}", feed.len(), url, page_title);
+    let options = ParsingOptions { allow_dtd: true, ..ParsingOptions::default() };
+    let document = Document::parse_with_options(feed, options).map_err(|e| eyre!("Failed to parse RSS feed: {}", e))?;
+    let channel = document.descendants()
+        .find(|node| node.is_element() && node.tag_name().name() == "channel")
+        .ok_or_else(|| eyre!("RSS feed has no channel"))?;
+    let items: Vec<Node> = channel.children().filter(|n| n.is_element() && n.tag_name().name() == "item").collect();
+
+    let target = normalize(url);
+    let by_url = items.iter().find(|item| {
+        let enclosure = child(**item, "enclosure").and_then(|e| e.attribute("url")).unwrap_or_default();
+        [child_text(**item, "link"), child_text(**item, "guid"), enclosure.to_string()]
+            .iter()
+            .any(|candidate| !candidate.is_empty() && normalize(candidate) == target)
+    });
+    let by_title = || page_title.and_then(|page_title| {
+        let page_title = page_title.to_lowercase();
+        items.iter().find(|item| {
+            let title = child_text(**item, "title").to_lowercase();
+            !title.is_empty() && page_title.contains(&title)
+        })
+    });
+
+    by_url.or_else(by_title)
+        .map(|item| parse_item(channel, *item))
+        .ok_or_else(|| eyre!("No episode matching {} found in feed", url))
+}
+
+fn page_title(html: &str) -> Option<String> {
+    let document = Html::parse_document(html);
+    let og_title = Selector::parse(r#"meta[property="og:title"]"#).expect("valid selector");
+    let title = Selector::parse("title").expect("valid selector");
+    document.select(&og_title).next()
+        .and_then(|meta| meta.value().attr("content").map(String::from))
+        .or_else(|| document.select(&title).next().map(|t| t.text().collect::<String>()))
+        .map(|title| title.trim().to_string())
+        .filter(|title| !title.is_empty())
+}
+
+pub async fn fetch_podcast_episode(client: &HttpClient, url: &str, feeds: &HashMap<String, String>) -> Result<PodcastEpisode> {
+    debug!("fetch_podcast_episode: url={} feeds={:?}", url, feeds);
+    if let Some(feed_url) = mapped_feed_url(url, feeds) {
+        let (_, feed) = fetch_text(client, &feed_url).await?;
+        if let Ok(episode) = find_episode(&feed, url, None) {
+            return Ok(episode);
+        }
+        let (_, page) = fetch_text(client, url).await?;
+        return find_episode(&feed, url, page_title(&page).as_deref());
+    }
+
+    let (content_type, body) = fetch_text(client, url).await?;
+    if looks_like_feed(&content_type, &body) {
+        let fragment = url.split_once('#').map(|(_, fragment)| fragment).unwrap_or(url);
+        return find_episode(&body, fragment, None);
+    }
+
+    let feed_url = discover_feed_url(url, &body)
+        .ok_or_else(|| eyre!("No RSS feed found for {}; add it to the podcast rule's feeds mapping", url))?;
+    let (_, feed) = fetch_text(client, &feed_url).await?;
+    find_episode(&feed, url, page_title(&body).as_deref())
+}
+
+fn generate_audio_embed_code(episode: &PodcastEpisode) -> String {
+    format!(
+        "<audio controls preload=\"none\" src=\"{}\" type=\"{}\"></audio>",
+        episode.audio_url, episode.audio_type
+    )
+}
+
+fn render_description(episode: &PodcastEpisode) -> String {
+    let mut details = vec![format!("**Show:** {}", episode.show)];
+    if !episode.published_at.is_empty() {
+        details.push(format!("**Published:** {}", episode.published_at));
+    }
+    if !episode.duration.is_empty() {
+        details.push(format!("**Duration:** {}", episode.duration));
+    }
+    format!("{}\n\n## Show Notes\n{}", details.join("\n"), episode.show_notes)
+}
+
+pub async fn handle_podcast_url(url: &str, folder: &str, feeds: &HashMap<String, String>, config: &Config) -> Result<Note> {
+    debug!("handle_podcast_url: url={} folder={} config={:?}", url, folder, config);
+    let episode = fetch_podcast_episode(config.http()?, url, feeds).await?;
+    let embed_code = if episode.audio_url.is_empty() { String::new() } else { generate_audio_embed_code(&episode) };
+    let description = render_description(&episode);
+    let author = if episode.author.is_empty() { episode.show } else { episode.author };
+    Ok(Note {
+        title: episode.title,
+        description,
+        embed_code,
+        url: url.to_string(),
+        folder: folder.to_string(),
+        author,
+        tags: episode.tags,
+        ..Note::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd" xmlns:content="http://purl.org/rss/1.0/modules/content/">
+  <channel>
+    <title>Rustacean Station</title>
+    <itunes:author>Rustacean Station</itunes:author>
+    <item>
+      <title>Episode 1: Async</title>
+      <link>https://rustacean-station.org/episode/async/</link>
+      <guid>ep-1</guid>
+      <pubDate>Mon, 01 Jan 2024 12:00:00 GMT</pubDate>
+      <itunes:duration>3725</itunes:duration>
+      <itunes:keywords>rust, async</itunes:keywords>
+      <content:encoded><![CDATA[<p>We talk about <b>futures</b>.</p>]]></content:encoded>
+      <enclosure url="https://cdn.example.com/ep1.mp3" type="audio/mpeg" length="1"/>
+    </item>
+    <item>
+      <title>Episode 2: Embedded</title>
+      <link>https://rustacean-station.org/episode/embedded/</link>
+      <description>Microcontrollers</description>
+      <enclosure url="https://cdn.example.com/ep2.mp3" type="audio/mpeg" length="1"/>
+    </item>
+  </channel>
+</rss>"#;
+
+    #[test]
+    fn test_find_episode_by_link() {
+        let episode = find_episode(FEED, "https://www.rustacean-station.org/episode/async", None).expect("episode");
+        assert_eq!(episode.title, "Episode 1: Async");
+        assert_eq!(episode.show, "Rustacean Station");
+        assert_eq!(episode.duration, "1:02:05");
+        assert_eq!(episode.show_notes, "We talk about **futures**.");
+        assert_eq!(episode.audio_url, "https://cdn.example.com/ep1.mp3");
+        assert_eq!(episode.tags, vec!["rust".to_string(), "async".to_string()]);
+    }
+
+    #[test]
+    fn test_find_episode_by_page_title_and_enclosure() {
+        let episode = find_episode(FEED, "https://other.host/e2", Some("Episode 2: Embedded | Rustacean Station")).expect("episode");
+        assert_eq!(episode.show_notes, "Microcontrollers");
+        let episode = find_episode(FEED, "https://cdn.example.com/ep2.mp3", None).expect("episode");
+        assert_eq!(episode.title, "Episode 2: Embedded");
+        assert!(find_episode(FEED, "https://nowhere.example.com", None).is_err());
+    }
+
+    #[test]
+    fn test_discover_feed_url_resolves_relative_href() {
+        let html = r#"<html><head><link rel="alternate" type="application/rss+xml" title="Feed" href="/podcast.rss"></head></html>"#;
+        assert_eq!(
+            discover_feed_url("https://rustacean-station.org/episode/async/", html),
+            Some("https://rustacean-station.org/podcast.rss".to_string())
+        );
+    }
+
+    #[test]
+    fn test_mapped_feed_url_prefers_longest_prefix() {
+        let mut feeds = HashMap::new();
+        feeds.insert("example.fm".to_string(), "https://example.fm/all.rss".to_string());
+        feeds.insert("example.fm/show-b".to_string(), "https://example.fm/b.rss".to_string());
+        assert_eq!(mapped_feed_url("https://www.example.fm/show-b/ep-3", &feeds), Some("https://example.fm/b.rss".to_string()));
+        assert_eq!(mapped_feed_url("https://example.fm/show-a/ep-1", &feeds), Some("https://example.fm/all.rss".to_string()));
+        assert_eq!(mapped_feed_url("https://other.fm/ep-1", &feeds), None);
+        assert_eq!(mapped_feed_url("https://other.fm/?ref=example.fm", &feeds), None);
+        assert!(has_mapped_feed("https://example.fm/show-a/ep-1", &feeds));
+    }
+}
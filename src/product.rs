@@ -0,0 +1,468 @@
+use log::{debug, warn};
+use regex::Regex;
+use serde_json::Value;
+use eyre::{eyre, Result};
+use lazy_static::lazy_static;
+use scraper::{Html, Selector};
+
+use crate::error::{self, Error};
+use crate::html::html_to_text;
+use crate::http::HttpClient;
+use crate::{generate_image_embed_code, read_frontmatter, today, update, vault, Config, Note};
+
+lazy_static! {
+    static ref STEAM_REGEX: Regex = Regex::new(r"^https?://store\.steampowered\.com/app/(\d+)").expect("valid regex");
+    static ref SHOPIFY_REGEX: Regex = Regex::new(r"^(https?://[^/]+/(?:collections/[^/]+/)?products/[^/?#]+)").expect("valid regex");
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Product {
+    pub name: String,
+    pub brand: String,
+    pub description: String,
+    pub price: Option<f64>,
+    pub currency: String,
+    pub availability: String,
+    pub images: Vec<String>,
+    pub rating: Option<f64>,
+    pub review_count: Option<u64>,
+}
+
+async fn get(client: &HttpClient, url: &str) -> Result<reqwest::Response> {
+    debug!("get: url={}", url);
+    let response = client.get(url).await?;
+    if !response.status().is_success() {
+        return Err(Error::http(url, response.status()).into());
+    }
+    Ok(response)
+}
+
+fn as_number(value: &Value) -> Option<f64> {
+    value.as_f64().or_else(|| value.as_str().and_then(|s| s.trim().replace(',', "").parse().ok()))
+}
+
+fn is_product(value: &Value) -> bool {
+    match &value["@type"] {
+        Value::String(kind) => kind == "Product" || kind.ends_with("/Product"),
+        Value::Array(kinds) => kinds.iter().any(|kind| kind.as_str() == Some("Product")),
+        _ => false,
+    }
+}
+
+fn find_product(value: &Value) -> Option<&Value> {
+    if is_product(value) {
+        return Some(value);
+    }
+    match value {
+        Value::Array(items) => items.iter().find_map(find_product),
+        Value::Object(_) => value["@graph"].as_array().and_then(|items| items.iter().find_map(find_product)),
+        _ => None,
+    }
+}
+
+fn image_urls(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(url) => vec![url.clone()],
+        Value::Array(items) => items.iter().flat_map(image_urls).collect(),
+        Value::Object(_) => value["url"].as_str().or_else(|| value["contentUrl"].as_str()).map(String::from).into_iter().collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn availability_name(availability: &str) -> String {
+    availability.rsplit('/').next().unwrap_or(availability).to_string()
+}
+
+pub fn parse_json_ld(html: &str) -> Option<Product> {
+    let document = Html::parse_document(html);
+    let selector = Selector::parse(r#"script[type="application/ld+json"]"#).expect("valid selector");
+    let product = document.select(&selector)
+        .filter_map(|script| serde_json::from_str::<Value>(&script.text().collect::<String>()).ok())
+        .find_map(|json| find_product(&json).cloned())?;
+
+    let offers = match &product["offers"] {
+        Value::Array(offers) => offers.first().cloned().unwrap_or(Value::Null),
+        offers => offers.clone(),
+    };
+    let price = as_number(&offers["price"])
+        .or_else(|| as_number(&offers["lowPrice"]))
+        .or_else(|| as_number(&offers["priceSpecification"]["price"]));
+    let brand = match &product["brand"] {
+        Value::String(brand) => brand.clone(),
+        brand => brand["name"].as_str().unwrap_or_default().to_string(),
+    };
+
+    Some(Product {
+        name: html_to_text(product["name"].as_str().unwrap_or_default()),
+        brand,
+        description: html_to_text(product["description"].as_str().unwrap_or_default()),
+        price,
+        currency: offers["priceCurrency"].as_str()
+            .or_else(|| offers["priceSpecification"]["priceCurrency"].as_str())
+            .unwrap_or_default()
+            .to_string(),
+        availability: availability_name(offers["availability"].as_str().unwrap_or_default()),
+        images: image_urls(&product["image"]),
+        rating: as_number(&product["aggregateRating"]["ratingValue"]),
+        review_count: as_number(&product["aggregateRating"]["reviewCount"])
+            .or_else(|| as_number(&product["aggregateRating"]["ratingCount"]))
+            .map(|count| count as u64),
+    })
+}
+
+fn meta_content(document: &Html, selectors: &[&str]) -> String {
+    selectors.iter()
+        .filter_map(|selector| Selector::parse(selector).ok())
+        .find_map(|selector| document.select(&selector).next().and_then(|meta| meta.value().attr("content").map(String::from)))
+        .unwrap_or_default()
+}
+
+fn element_text(document: &Html, selectors: &[&str]) -> String {
+    selectors.iter()
+        .filter_map(|selector| Selector::parse(selector).ok())
+        .find_map(|selector| document.select(&selector).next().map(|element| element.text().collect::<String>()))
+        .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+        .unwrap_or_default()
+}
+
+pub fn parse_html_fallback(html: &str) -> Product {
+    let document = Html::parse_document(html);
+    let price_text = meta_content(&document, &[r#"meta[property="product:price:amount"]"#, r#"meta[property="og:price:amount"]"#]);
+    let price_text = if price_text.is_empty() {
+        element_text(&document, &["span.a-price span.a-offscreen", "#priceblock_ourprice"])
+            .trim_start_matches(|c: char| !c.is_ascii_digit())
+            .to_string()
+    } else {
+        price_text
+    };
+    let image = meta_content(&document, &[r#"meta[property="og:image"]"#]);
+    let name = element_text(&document, &["#productTitle"]);
+    Product {
+        name: if name.is_empty() { meta_content(&document, &[r#"meta[property="og:title"]"#]) } else { name },
+        brand: element_text(&document, &["#bylineInfo"]),
+        description: meta_content(&document, &[r#"meta[property="og:description"]"#, r#"meta[name="description"]"#]),
+        price: as_number(&Value::String(price_text)),
+        currency: meta_content(&document, &[r#"meta[property="product:price:currency"]"#, r#"meta[property="og:price:currency"]"#]),
+        availability: element_text(&document, &["#availability"]),
+        images: if image.is_empty() { Vec::new() } else { vec![image] },
+        ..Product::default()
+    }
+}
+
+// OpenGraph product markup or an Amazon product title; a plain og:title is not enough
+fn has_product_markup(html: &str) -> bool {
+    let document = Html::parse_document(html);
+    meta_content(&document, &[r#"meta[property="og:type"]"#]).starts_with("product")
+        || !element_text(&document, &["#productTitle"]).is_empty()
+}
+
+pub fn parse_shopify(response: &Value) -> Option<Product> {
+    let product = &response["product"];
+    let variant = product["variants"].as_array()?.first()?;
+    Some(Product {
+        name: product["title"].as_str()?.to_string(),
+        brand: product["vendor"].as_str().unwrap_or_default().to_string(),
+        description: html_to_text(product["body_html"].as_str().unwrap_or_default()),
+        price: as_number(&variant["price"]),
+        images: product["images"].as_array().map_or_else(Vec::new, |images| {
+            images.iter().filter_map(|image| image["src"].as_str().map(String::from)).collect()
+        }),
+        ..Product::default()
+    })
+}
+
+pub fn parse_steam(app_id: &str, response: &Value) -> Result<Product> {
+    let app = &response[app_id];
+    if app["success"].as_bool() != Some(true) {
+        return Err(eyre!("Steam app {} not found", app_id));
+    }
+    let data = &app["data"];
+    let (price, currency) = if data["is_free"].as_bool() == Some(true) {
+        (Some(0.0), String::new())
+    } else {
+        (
+            data["price_overview"]["final"].as_f64().map(|cents| cents / 100.0),
+            data["price_overview"]["currency"].as_str().unwrap_or_default().to_string(),
+        )
+    };
+    let mut images: Vec<String> = data["header_image"].as_str().map(String::from).into_iter().collect();
+    images.extend(data["screenshots"].as_array().into_iter().flatten().take(4).filter_map(|s| s["path_full"].as_str().map(String::from)));
+    Ok(Product {
+        name: data["name"].as_str().unwrap_or_default().to_string(),
+        brand: data["developers"][0].as_str().or_else(|| data["publishers"][0].as_str()).unwrap_or_default().to_string(),
+        description: html_to_text(data["short_description"].as_str().unwrap_or_default()),
+        price,
+        currency,
+        availability: if data["release_date"]["coming_soon"].as_bool() == Some(true) { "PreOrder".to_string() } else { "InStock".to_string() },
+        images,
+        rating: data["metacritic"]["score"].as_f64(),
+        review_count: data["recommendations"]["total"].as_u64(),
+    })
+}
+
+pub async fn fetch_product(client: &HttpClient, url: &str) -> Result<Product> {
+    debug!("fetch_product: url={}", url);
+    if let Some(caps) = STEAM_REGEX.captures(url) {
+        let app_id = &caps[1];
+        let response = get(client, &format!("https://store.steampowered.com/api/appdetails?appids={}", app_id)).await?;
+        let response = client.json::<Value>(response).await?;
+        return parse_steam(app_id, &response);
+    }
+
+    let html = client.text(get(client, url).await?).await?;
+    let fallback = parse_html_fallback(&html);
+    if let Some(mut product) = parse_json_ld(&html) {
+        if product.currency.is_empty() {
+            product.currency = fallback.currency;
+        }
+        return Ok(product);
+    }
+    if let Some(caps) = SHOPIFY_REGEX.captures(url) {
+        match get(client, &format!("{}.json", &caps[1])).await {
+            Ok(response) => {
+                if let Some(mut product) = parse_shopify(&client.json::<Value>(response).await?) {
+                    product.currency = fallback.currency;
+                    return Ok(product);
+                }
+            }
+            Err(e) => warn!("Shopify product endpoint failed for {}: {}", url, e),
+        }
+    }
+    if fallback.name.is_empty() || (fallback.price.is_none() && !has_product_markup(&html)) {
+        return Err(Error::MetadataMissing(format!("no product metadata found for {}", url)).into());
+    }
+    Ok(fallback)
+}
+
+fn price_entry(product: &Product, date: &str) -> serde_yaml::Value {
+    let mut entry = serde_yaml::Mapping::new();
+    entry.insert("date".into(), date.into());
+    entry.insert("price".into(), product.price.map(serde_yaml::Value::from).unwrap_or(serde_yaml::Value::Null));
+    entry.insert("currency".into(), product.currency.as_str().into());
+    if !product.availability.is_empty() {
+        entry.insert("availability".into(), product.availability.as_str().into());
+    }
+    serde_yaml::Value::Mapping(entry)
+}
+
+pub fn append_price_history(existing: Option<&serde_yaml::Value>, product: &Product, date: &str) -> Vec<serde_yaml::Value> {
+    let mut history = existing.and_then(|value| value.as_sequence()).cloned().unwrap_or_default();
+    let entry = price_entry(product, date);
+    // a second capture on the same day replaces that day's snapshot instead of duplicating it
+    if history.last().map(|last| last["date"] == entry["date"]).unwrap_or(false) {
+        history.pop();
+    }
+    history.push(entry);
+    history
+}
+
+fn product_fields(product: &Product, price_history: Vec<serde_yaml::Value>) -> Vec<(String, serde_yaml::Value)> {
+    let mut fields = Vec::new();
+    if !product.brand.is_empty() {
+        fields.push(("brand".to_string(), product.brand.as_str().into()));
+    }
+    if let Some(price) = product.price {
+        fields.push(("price".to_string(), price.into()));
+    }
+    if !product.currency.is_empty() {
+        fields.push(("currency".to_string(), product.currency.as_str().into()));
+    }
+    if !product.availability.is_empty() {
+        fields.push(("availability".to_string(), product.availability.as_str().into()));
+    }
+    if let Some(rating) = product.rating {
+        fields.push(("rating".to_string(), rating.into()));
+    }
+    if let Some(review_count) = product.review_count {
+        fields.push(("reviews".to_string(), review_count.into()));
+    }
+    fields.push(("price_history".to_string(), serde_yaml::Value::Sequence(price_history)));
+    fields
+}
+
+pub async fn handle_product_url(url: &str, folder: &str, width: usize, height: usize, config: &Config) -> Result<Note> {
+    debug!("handle_product_url: url={} folder={} width={} height={} config={:?}", url, folder, width, height, config);
+    let product = match fetch_product(config.http()?, url).await {
+        Ok(product) => product,
+        // The rule's regex also matches pages that merely live under /products/
+        Err(report) if matches!(error::classify(&report), Some(Error::MetadataMissing(_))) => {
+            debug!("No product data for {}, handling it as a web link: {}", url, report);
+            return crate::handle_weblink_url(url, folder, width, height, config).await;
+        }
+        Err(report) => return Err(report),
+    };
+    let embed_code = product.images.first()
+        .map(|image| generate_image_embed_code(image, width, height))
+        .unwrap_or_default();
+    let mut description = product.description.clone();
+    if product.images.len() > 1 {
+        description += "\n\n### Images\n";
+        description += &product.images.iter().skip(1).map(|image| format!("![]({})", image)).collect::<Vec<_>>().join("\n");
+    }
+    let tags = if product.brand.is_empty() { Vec::new() } else { vec![product.brand.clone()] };
+
+    let mut note = Note {
+        title: product.name.clone(),
+        description,
+        embed_code,
+        url: url.to_string(),
+        folder: folder.to_string(),
+        author: product.brand.clone(),
+        tags,
+        note_type: Some("product".to_string()),
+        ..Note::default()
+    };
+
+    let (date, _, _) = today();
+    // The note may have been moved since it was created, so look for it through the whole vault
+    let existing = vault::vault_root(&config.vault).ok()
+        .and_then(|root| update::find_note_by_url(&root, url))
+        .and_then(|path| read_frontmatter(&path));
+    let price_history = append_price_history(existing.as_ref().and_then(|fm| fm.get("price_history")), &product, &date);
+    note.fields = product_fields(&product, price_history);
+    Ok(note)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_json_ld_product_in_graph() {
+        let html = r#"<html><head><script type="application/ld+json">
+            {"@context": "https://schema.org", "@graph": [
+                {"@type": "WebSite", "name": "Shop"},
+                {"@type": "Product", "name": "Paisley Jacket", "brand": {"@type": "Brand", "name": "Alohahoo"},
+                 "image": ["https://cdn.shop/1.jpg", "https://cdn.shop/2.jpg"],
+                 "offers": [{"@type": "Offer", "price": "39.99", "priceCurrency": "USD", "availability": "https://schema.org/InStock"}],
+                 "aggregateRating": {"ratingValue": "4.6", "reviewCount": 128}}
+            ]}
+        </script></head></html>"#;
+        let product = parse_json_ld(html).expect("product");
+        assert_eq!(product.name, "Paisley Jacket");
+        assert_eq!(product.brand, "Alohahoo");
+        assert_eq!(product.price, Some(39.99));
+        assert_eq!(product.currency, "USD");
+        assert_eq!(product.availability, "InStock");
+        assert_eq!(product.images.len(), 2);
+        assert_eq!(product.rating, Some(4.6));
+        assert_eq!(product.review_count, Some(128));
+    }
+
+    #[test]
+    fn test_parse_steam_appdetails() {
+        let response = json!({"1894430": {"success": true, "data": {
+            "name": "Plan B: Terraform",
+            "developers": ["Gaddy Games"],
+            "short_description": "A terraforming game",
+            "price_overview": {"currency": "EUR", "final": 1799},
+            "header_image": "https://cdn.steam/header.jpg",
+            "release_date": {"coming_soon": false}
+        }}});
+        let product = parse_steam("1894430", &response).expect("product");
+        assert_eq!(product.price, Some(17.99));
+        assert_eq!(product.currency, "EUR");
+        assert_eq!(product.brand, "Gaddy Games");
+        assert!(parse_steam("1", &json!({"1": {"success": false}})).is_err());
+    }
+
+    #[test]
+    fn test_parse_shopify_product_json() {
+        let response = json!({"product": {"title": "Jacket", "vendor": "Alohahoo", "body_html": "<p>Warm</p>",
+            "variants": [{"price": "29.50"}], "images": [{"src": "https://cdn.shopify.com/a.jpg"}]}});
+        let product = parse_shopify(&response).expect("product");
+        assert_eq!(product.price, Some(29.5));
+        assert_eq!(product.description, "Warm");
+    }
+
+    #[test]
+    fn test_append_price_history() {
+        let mut product = Product { price: Some(10.0), currency: "USD".into(), ..Product::default() };
+        let history = append_price_history(None, &product, "2024-01-01");
+        assert_eq!(history.len(), 1);
+        let existing = serde_yaml::Value::Sequence(history);
+        product.price = Some(8.0);
+        let history = append_price_history(Some(&existing), &product, "2024-02-01");
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1]["price"], serde_yaml::Value::from(8.0));
+        let existing = serde_yaml::Value::Sequence(history);
+        let history = append_price_history(Some(&existing), &product, "2024-02-01");
+        assert_eq!(history.len(), 2, "same-day refresh replaces the last snapshot");
+    }
+
+    #[tokio::test]
+    async fn test_handle_product_url_from_served_page() {
+        use crate::testing::{fixture, mock_config};
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/products/anvil"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(fixture("product.html"), "text/html"))
+            .mount(&server)
+            .await;
+        let vault = tempfile::tempdir().unwrap();
+        let config = mock_config(&server, vault.path());
+
+        let note = handle_product_url(&format!("{}/products/anvil", server.uri()), "products", 100, 100, &config).await.unwrap();
+        assert_eq!(note.title, "Acme Anvil");
+        assert_eq!(note.author, "Acme");
+        let price = note.fields.iter().find(|(key, _)| key == "price").map(|(_, value)| value.clone());
+        assert_eq!(price, Some(serde_yaml::Value::from(99.99)));
+    }
+
+    #[tokio::test]
+    async fn test_handle_product_url_extends_history_of_moved_note() {
+        use crate::testing::{fixture, mock_config};
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/products/anvil"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(fixture("product.html"), "text/html"))
+            .mount(&server)
+            .await;
+        let vault = tempfile::tempdir().unwrap();
+        let url = format!("{}/products/anvil", server.uri());
+        std::fs::create_dir(vault.path().join("archive")).unwrap();
+        std::fs::write(
+            vault.path().join("archive/anvil.md"),
+            format!("---\nurl: {}\nprice_history:\n- date: 2000-01-01\n  price: 120.0\n---\n\n{}\nbody\n{}\n", url, update::MANAGED_START, update::MANAGED_END),
+        ).unwrap();
+        let config = mock_config(&server, vault.path());
+
+        let note = handle_product_url(&url, "products", 100, 100, &config).await.unwrap();
+        let history = note.fields.iter().find(|(key, _)| key == "price_history").map(|(_, value)| value.clone()).unwrap();
+        let history = history.as_sequence().unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0]["price"], serde_yaml::Value::from(120.0));
+    }
+
+    #[tokio::test]
+    async fn test_handle_product_url_without_product_data_is_a_weblink() {
+        use crate::testing::{fixture, mock_config};
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let page = r#"<html><head><meta property="og:title" content="Our new products"><meta property="og:type" content="article"></head></html>"#;
+        Mock::given(method("GET"))
+            .and(path("/blog/products/launch"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(page, "text/html"))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(fixture("openai_chat.json"), "application/json"))
+            .mount(&server)
+            .await;
+        let config = mock_config(&server, std::path::Path::new("/unused"));
+
+        let note = handle_product_url(&format!("{}/blog/products/launch", server.uri()), "products", 100, 100, &config).await.unwrap();
+        assert_eq!(note.title, "Understanding Ownership in Rust");
+        assert_eq!(note.note_type, None);
+    }
+}
@@ -0,0 +1,321 @@
+use log::{debug, info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+use chrono::{DateTime, Utc};
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::batch::{run_captures, BatchResult};
+use crate::capture::Capture;
+use crate::error::{self, Error};
+use crate::{vault, Config};
+
+/// The `queue:` config section. Items that failed `max_attempts` times stay queued
+/// but are only retried by an explicit `queue retry`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct QueueConfig {
+    pub file: Option<PathBuf>,
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_auto_retry")]
+    pub auto_retry: bool,
+}
+
+fn default_max_attempts() -> u32 { 10 }
+fn default_auto_retry() -> bool { true }
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        serde_yaml::from_str("{}").expect("defaults deserialize")
+    }
+}
+
+impl QueueConfig {
+    /// `file` if set, otherwise `$XDG_STATE_HOME/obsidian-link/queue.json` (or the platform equivalent).
+    pub fn resolved_file(&self) -> Option<PathBuf> {
+        match &self.file {
+            Some(file) => file.to_str().and_then(|file| crate::expanduser(file).ok()),
+            None => dirs::state_dir().or_else(dirs::data_local_dir).map(|dir| dir.join(env!("CARGO_PKG_NAME")).join("queue.json")),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QueueItem {
+    pub url: String,
+    pub kind: String,
+    pub error: String,
+    pub attempts: u32,
+    pub first_failed: DateTime<Utc>,
+    pub last_failed: DateTime<Utc>,
+    /// What the original run knew about the URL; missing from queues written by older versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capture: Option<Capture>,
+}
+
+impl QueueItem {
+    pub fn capture(&self) -> Capture {
+        self.capture.clone().unwrap_or_else(|| Capture::new(&self.url))
+    }
+}
+
+// Serializes queue updates within this process; the lock file covers other processes
+static QUEUE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Failures worth retrying later: network trouble, rate limits, exhausted quotas and LLM errors.
+pub fn is_retriable(report: &eyre::Report) -> bool {
+    error::classify(report).is_some_and(|error| error.is_transient() || matches!(error, Error::LlmFailed { .. }))
+}
+
+#[derive(Debug, Default)]
+pub struct RetryQueue {
+    path: PathBuf,
+    pub items: Vec<QueueItem>,
+}
+
+impl RetryQueue {
+    pub fn load(path: &Path) -> Result<Self> {
+        debug!("RetryQueue::load: path={}", path.display());
+        let items = match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text).map_err(|e| eyre!("Retry queue {} is corrupt: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(eyre!("Failed to read retry queue {}: {}", path.display(), e)),
+        };
+        Ok(RetryQueue { path: path.to_path_buf(), items })
+    }
+
+    pub fn open(config: &Config) -> Result<Self> {
+        Self::load(&queue_file(config)?)
+    }
+
+    /// Loads the queue at `path`, applies `change` and saves the result while holding both
+    /// the process and the file lock, so concurrent runs never drop each other's updates.
+    pub fn update<T>(path: &Path, change: impl FnOnce(&mut RetryQueue) -> T) -> Result<T> {
+        debug!("RetryQueue::update: path={}", path.display());
+        let _guard = QUEUE_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let parent = path.parent().ok_or_else(|| eyre!("Invalid retry queue path: {}", path.display()))?;
+        fs::create_dir_all(parent)?;
+        let lock_path = path.with_extension("lock");
+        let lock = fs::OpenOptions::new().create(true).truncate(false).write(true).open(&lock_path)
+            .map_err(|e| eyre!("Failed to open queue lock {}: {}", lock_path.display(), e))?;
+        lock.lock().map_err(|e| eyre!("Failed to lock {}: {}", lock_path.display(), e))?;
+        let mut queue = Self::load(path)?;
+        let value = change(&mut queue);
+        queue.save()?;
+        Ok(value)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        debug!("RetryQueue::save: path={} items={}", self.path.display(), self.items.len());
+        let parent = self.path.parent().ok_or_else(|| eyre!("Invalid retry queue path: {}", self.path.display()))?;
+        fs::create_dir_all(parent)?;
+        let root = parent.canonicalize()?;
+        let file_name = self.path.file_name().ok_or_else(|| eyre!("Invalid retry queue path: {}", self.path.display()))?;
+        vault::write_atomic(&root, &root.join(file_name), serde_json::to_string_pretty(&self.items)?.as_bytes())
+    }
+
+    pub fn record_failure(&mut self, capture: &Capture, report: &eyre::Report) {
+        let url = capture.url.as_str();
+        let now = Utc::now();
+        let kind = error::classify(report).map_or("unknown", |error| error.kind()).to_string();
+        let message = report.to_string();
+        match self.items.iter_mut().find(|item| item.url == url) {
+            Some(item) => {
+                item.attempts += 1;
+                item.last_failed = now;
+                item.kind = kind;
+                item.error = message;
+                item.capture = Some(capture.clone());
+            }
+            None => self.items.push(QueueItem {
+                url: url.to_string(),
+                kind,
+                error: message,
+                attempts: 1,
+                first_failed: now,
+                last_failed: now,
+                capture: Some(capture.clone()),
+            }),
+        }
+    }
+
+    pub fn remove(&mut self, url: &str) {
+        self.items.retain(|item| item.url != url);
+    }
+
+    /// Queues retriable failures and drops successes. `captures` are the inputs the results
+    /// were produced from, indexed by [`BatchResult::index`]. Returns how many results were queued.
+    pub fn apply(&mut self, captures: &[Capture], results: &[BatchResult]) -> usize {
+        let mut queued = 0;
+        for result in results {
+            match &result.outcome {
+                Ok(_) => self.remove(&result.url),
+                Err(report) if is_retriable(report) => {
+                    self.record_failure(&capture_for(captures, result), report);
+                    queued += 1;
+                }
+                // A permanent failure will not get better by retrying
+                Err(report) => {
+                    if self.items.iter().any(|item| item.url == result.url) {
+                        warn!("Dropping {} from the retry queue: {}", result.url, report);
+                    }
+                    self.remove(&result.url);
+                }
+            }
+        }
+        queued
+    }
+}
+
+fn queue_file(config: &Config) -> Result<PathBuf> {
+    config.queue.resolved_file().ok_or_else(|| eyre!("No location for the retry queue could be determined"))
+}
+
+fn capture_for(captures: &[Capture], result: &BatchResult) -> Capture {
+    captures.get(result.index)
+        .filter(|capture| capture.url == result.url)
+        .cloned()
+        .unwrap_or_else(|| Capture::new(&result.url))
+}
+
+/// Records the outcome of `results` in the persistent queue, warning rather than failing
+/// the run if the queue cannot be written.
+pub fn record_results(config: &Config, captures: &[Capture], results: &[BatchResult]) {
+    let outcome = queue_file(config).and_then(|path| RetryQueue::update(&path, |queue| queue.apply(captures, results)));
+    match outcome {
+        Ok(0) => {}
+        Ok(queued) => info!("Queued {} failed URLs for retry", queued),
+        Err(e) => warn!("Failed to update the retry queue: {}", e),
+    }
+}
+
+/// Retries queued URLs. Unless `all` is set, items that already failed `max_attempts` times are skipped.
+pub async fn retry_queued<F>(config: &Config, all: bool, progress: F) -> Result<Vec<BatchResult>>
+where
+    F: FnMut(usize, usize, &BatchResult),
+{
+    let path = queue_file(config)?;
+    let captures = RetryQueue::load(&path)?.items.iter()
+        .filter(|item| all || item.attempts < config.queue.max_attempts)
+        .map(QueueItem::capture)
+        .collect::<Vec<_>>();
+    debug!("retry_queued: all={} captures.len={}", all, captures.len());
+    if captures.is_empty() {
+        return Ok(Vec::new());
+    }
+    let results = run_captures(&captures, config, config.batch.concurrency, progress).await;
+    RetryQueue::update(&path, |queue| queue.apply(&captures, &results))?;
+    Ok(results)
+}
+
+/// Tab-separated listing: `attempts last_failed kind url error`.
+pub fn render_list(items: &[QueueItem]) -> String {
+    items.iter()
+        .map(|item| format!("{}\t{}\t{}\t{}\t{}\n", item.attempts, item.last_failed.to_rfc3339(), item.kind, item.url, item.error))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{fixture, mock_config};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn result(index: usize, url: &str, outcome: Result<PathBuf>) -> BatchResult {
+        BatchResult { index, url: url.to_string(), outcome }
+    }
+
+    #[test]
+    fn test_apply_queues_only_retriable_failures() {
+        let mut queue = RetryQueue::default();
+        let offline = Error::FetchFailed { url: "https://a".into(), status: None, message: "connection refused".into() };
+        let results = vec![
+            result(0, "https://a", Err(offline.clone().into())),
+            result(1, "https://b", Err(Error::NoMatchingRule("https://b".into()).into())),
+            result(2, "https://c", Err(Error::LlmFailed { status: Some(400), message: "bad".into() }.into())),
+        ];
+        let captures = ["https://a", "https://b", "https://c"].map(Capture::new);
+        assert_eq!(queue.apply(&captures, &results), 2);
+        assert_eq!(queue.items.iter().map(|item| item.url.as_str()).collect::<Vec<_>>(), vec!["https://a", "https://c"]);
+
+        let captures = ["https://a", "https://c"].map(Capture::new);
+        queue.apply(&captures, &[result(0, "https://a", Err(offline.into())), result(1, "https://c", Ok(PathBuf::from("c.md")))]);
+        assert_eq!(queue.items.len(), 1);
+        assert_eq!(queue.items[0].attempts, 2);
+        assert_eq!(queue.items[0].kind, "fetch_failed");
+    }
+
+    #[test]
+    fn test_queue_round_trips_through_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state/queue.json");
+        let mut queue = RetryQueue::load(&path).unwrap();
+        assert!(queue.items.is_empty());
+        let capture = Capture {
+            tags: vec!["imported".into()],
+            fields: vec![("read".into(), serde_yaml::Value::Bool(true))],
+            ..Capture::new("https://a")
+        };
+        queue.record_failure(&capture, &Error::QuotaExceeded("youtube".into()).into());
+        queue.save().unwrap();
+        let loaded = RetryQueue::load(&path).unwrap();
+        assert_eq!(loaded.items, queue.items);
+        assert_eq!(loaded.items[0].capture(), capture);
+        assert!(render_list(&loaded.items).starts_with("1\t"));
+    }
+
+    #[tokio::test]
+    async fn test_retry_queued_removes_successes() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/videos"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(fixture("youtube_video.json"), "application/json"))
+            .mount(&server)
+            .await;
+        let vault = tempfile::tempdir().unwrap();
+        let mut config = mock_config(&server, vault.path());
+        config.queue.file = Some(vault.path().join("queue.json"));
+        config.queue.max_attempts = 3;
+
+        let mut queue = RetryQueue::open(&config).unwrap();
+        let offline: eyre::Report = Error::FetchFailed { url: String::new(), status: None, message: "offline".into() }.into();
+        let saved = Capture { tags: vec!["queued".into()], ..Capture::new("https://www.youtube.com/watch?v=dQw4w9WgXcQ") };
+        queue.record_failure(&saved, &offline);
+        for _ in 0..3 {
+            queue.record_failure(&Capture::new("https://www.youtube.com/watch?v=9bZkp7q19f0"), &offline);
+        }
+        queue.save().unwrap();
+
+        let results = retry_queued(&config, false, |_, _, _| {}).await.unwrap();
+        assert_eq!(results.len(), 1, "items past max_attempts are not retried automatically");
+        let note = std::fs::read_to_string(results[0].outcome.as_ref().unwrap()).unwrap();
+        assert!(note.contains("queued"), "capture tags survive the retry: {}", note);
+        let remaining = RetryQueue::open(&config).unwrap().items;
+        assert_eq!(remaining.iter().map(|item| item.url.as_str()).collect::<Vec<_>>(), vec!["https://www.youtube.com/watch?v=9bZkp7q19f0"]);
+    }
+
+    #[tokio::test]
+    async fn test_retry_queued_drops_permanent_failures() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/videos"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(fixture("youtube_empty.json"), "application/json"))
+            .mount(&server)
+            .await;
+        let vault = tempfile::tempdir().unwrap();
+        let mut config = mock_config(&server, vault.path());
+        config.queue.file = Some(vault.path().join("queue.json"));
+
+        let mut queue = RetryQueue::open(&config).unwrap();
+        let offline: eyre::Report = Error::FetchFailed { url: String::new(), status: None, message: "offline".into() }.into();
+        queue.record_failure(&Capture::new("https://www.youtube.com/watch?v=missing0000"), &offline);
+        queue.save().unwrap();
+
+        let results = retry_queued(&config, true, |_, _, _| {}).await.unwrap();
+        assert!(matches!(error::classify(results[0].outcome.as_ref().unwrap_err()), Some(Error::MetadataMissing(_))));
+        assert!(RetryQueue::open(&config).unwrap().items.is_empty());
+    }
+}
@@ -0,0 +1,168 @@
+use log::debug;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::time::Instant;
+
+/// A refill rate plus how many tokens may be spent at once after sitting idle.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    pub per_minute: f64,
+    #[serde(default = "default_burst")]
+    pub burst: f64,
+}
+
+fn default_burst() -> f64 { 1.0 }
+
+/// The `http.rate_limits` config section. `per_host` applies to every host without an
+/// entry in `hosts`; `youtube` is counted in Data API quota units and `llm` in requests.
+/// Setting any of them to `~` removes the limit.
+#[derive(Deserialize, Debug, Clone)]
+pub struct RateLimitConfig {
+    #[serde(default = "default_per_host")]
+    pub per_host: Option<Rate>,
+    #[serde(default)]
+    pub hosts: HashMap<String, Rate>,
+    #[serde(default = "default_youtube")]
+    pub youtube: Option<Rate>,
+    #[serde(default = "default_llm")]
+    pub llm: Option<Rate>,
+}
+
+fn default_per_host() -> Option<Rate> { Some(Rate { per_minute: 120.0, burst: 10.0 }) }
+fn default_youtube() -> Option<Rate> { Some(Rate { per_minute: 100.0, burst: 10.0 }) }
+fn default_llm() -> Option<Rate> { Some(Rate { per_minute: 60.0, burst: 5.0 }) }
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        serde_yaml::from_str("{}").expect("defaults deserialize")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quota {
+    YouTube,
+    Llm,
+}
+
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: Rate,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    pub fn new(rate: Rate) -> Self {
+        let burst = rate.burst.max(1.0);
+        TokenBucket { rate: Rate { burst, ..rate }, state: Mutex::new((burst, Instant::now())) }
+    }
+
+    /// Takes `cost` tokens, returning how long the caller must wait before they are available.
+    /// Tokens are reserved immediately, so concurrent callers queue up behind each other.
+    fn reserve(&self, cost: f64) -> Duration {
+        let per_second = self.rate.per_minute / 60.0;
+        if per_second <= 0.0 {
+            return Duration::ZERO;
+        }
+        let mut state = self.state.lock().expect("token bucket lock poisoned");
+        let (tokens, last) = *state;
+        let now = Instant::now();
+        let tokens = (tokens + now.duration_since(last).as_secs_f64() * per_second).min(self.rate.burst) - cost.min(self.rate.burst);
+        *state = (tokens, now);
+        if tokens >= 0.0 { Duration::ZERO } else { Duration::from_secs_f64(-tokens / per_second) }
+    }
+
+    pub async fn acquire(&self, cost: f64) {
+        let wait = self.reserve(cost);
+        if !wait.is_zero() {
+            debug!("TokenBucket::acquire: waiting {:?}", wait);
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Token buckets shared by every clone of an [`crate::http::HttpClient`].
+#[derive(Debug, Default)]
+pub struct RateLimits {
+    config: RateLimitConfig,
+    hosts: Mutex<HashMap<String, Arc<TokenBucket>>>,
+    youtube: Option<TokenBucket>,
+    llm: Option<TokenBucket>,
+}
+
+impl RateLimits {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        RateLimits {
+            config: config.clone(),
+            hosts: Mutex::new(HashMap::new()),
+            youtube: config.youtube.map(TokenBucket::new),
+            llm: config.llm.map(TokenBucket::new),
+        }
+    }
+
+    fn host_rate(&self, host: &str) -> Option<Rate> {
+        self.config.hosts.iter()
+            .filter(|(domain, _)| host == domain.as_str() || host.ends_with(&format!(".{}", domain)))
+            .max_by_key(|(domain, _)| domain.len())
+            .map(|(_, rate)| *rate)
+            .or(self.config.per_host)
+    }
+
+    pub async fn acquire_host(&self, host: &str) {
+        let bucket = {
+            let mut hosts = self.hosts.lock().expect("rate limit lock poisoned");
+            match hosts.get(host) {
+                Some(bucket) => Some(bucket.clone()),
+                None => self.host_rate(host).map(|rate| {
+                    let bucket = Arc::new(TokenBucket::new(rate));
+                    hosts.insert(host.to_string(), bucket.clone());
+                    bucket
+                }),
+            }
+        };
+        if let Some(bucket) = bucket {
+            bucket.acquire(1.0).await;
+        }
+    }
+
+    pub async fn acquire_quota(&self, quota: Quota, cost: f64) {
+        let bucket = match quota {
+            Quota::YouTube => &self.youtube,
+            Quota::Llm => &self.llm,
+        };
+        if let Some(bucket) = bucket {
+            bucket.acquire(cost).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_token_bucket_allows_burst_then_waits() {
+        let bucket = TokenBucket::new(Rate { per_minute: 60.0, burst: 2.0 });
+        let start = Instant::now();
+        bucket.acquire(1.0).await;
+        bucket.acquire(1.0).await;
+        assert!(start.elapsed() < Duration::from_millis(10));
+        bucket.acquire(1.0).await;
+        assert!(start.elapsed() >= Duration::from_millis(990), "waited {:?}", start.elapsed());
+    }
+
+    #[test]
+    fn test_host_rate_prefers_most_specific_domain() {
+        let config: RateLimitConfig = serde_yaml::from_str(
+            "per_host: { per_minute: 10 }\nhosts:\n  github.com: { per_minute: 30 }\n  api.github.com: { per_minute: 5000, burst: 50 }\nllm: ~\n"
+        ).unwrap();
+        let limits = RateLimits::new(&config);
+        assert_eq!(limits.host_rate("api.github.com"), Some(Rate { per_minute: 5000.0, burst: 50.0 }));
+        assert_eq!(limits.host_rate("gist.github.com").map(|rate| rate.per_minute), Some(30.0));
+        assert_eq!(limits.host_rate("example.com").map(|rate| rate.per_minute), Some(10.0));
+        assert!(limits.llm.is_none());
+        assert!(limits.youtube.is_some());
+    }
+}
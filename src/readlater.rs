@@ -0,0 +1,287 @@
+use log::debug;
+
+use chrono::{DateTime, Utc};
+use eyre::{eyre, Result};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::html::html_to_text;
+use crate::import::{attributes, parse_timestamp, split_tags, Bookmark};
+
+lazy_static! {
+    static ref POCKET_ITEM_REGEX: Regex = Regex::new(r"(?is)<h1[^>]*>(.*?)</h1>|<a\s([^>]*)>(.*?)</a>").expect("valid regex");
+}
+
+fn read_csv<T: DeserializeOwned>(text: &str, service: &str) -> Result<Vec<T>> {
+    csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(text.trim_start_matches('\u{feff}').as_bytes())
+        .deserialize()
+        .collect::<std::result::Result<Vec<T>, _>>()
+        .map_err(|e| eyre!("Not a {} CSV export: {}", service, e))
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.map(|value| value.trim().to_string()).filter(|value| !value.is_empty())
+}
+
+fn parse_rfc3339(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value.trim()).ok().map(|date| date.with_timezone(&Utc))
+}
+
+/// Pocket's HTML export lists items under "Unread" and "Read Archive" headings.
+pub fn parse_pocket_html(html: &str) -> Vec<Bookmark> {
+    debug!("parse_pocket_html: html.len={}", html.len());
+    let mut bookmarks = Vec::new();
+    let mut read = None;
+    for caps in POCKET_ITEM_REGEX.captures_iter(html) {
+        if let Some(heading) = caps.get(1) {
+            read = Some(html_to_text(heading.as_str()).to_lowercase().contains("archive"));
+            continue;
+        }
+        let attrs = attributes(&caps[2]);
+        let Some(url) = attrs.get("href") else { continue };
+        bookmarks.push(Bookmark {
+            url: url.trim().to_string(),
+            title: non_empty(Some(html_to_text(&caps[3]))),
+            added: attrs.get("time_added").and_then(|value| parse_timestamp(value)),
+            tags: attrs.get("tags").map(|tags| split_tags(tags)).unwrap_or_default(),
+            read,
+            ..Bookmark::default()
+        });
+    }
+    bookmarks
+}
+
+#[derive(Deserialize, Debug)]
+struct PocketRow {
+    url: String,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    time_added: Option<String>,
+    #[serde(default)]
+    tags: Option<String>,
+    #[serde(default)]
+    status: Option<String>,
+}
+
+/// Pocket's later CSV export: `title,url,time_added,tags,status` with `|`-separated tags.
+pub fn parse_pocket_csv(text: &str) -> Result<Vec<Bookmark>> {
+    let rows: Vec<PocketRow> = read_csv(text, "Pocket")?;
+    Ok(rows.into_iter()
+        .map(|row| Bookmark {
+            url: row.url,
+            title: non_empty(row.title),
+            added: row.time_added.as_deref().and_then(parse_timestamp),
+            tags: row.tags.unwrap_or_default().split('|').map(str::trim).filter(|tag| !tag.is_empty()).map(String::from).collect(),
+            read: row.status.map(|status| status.eq_ignore_ascii_case("archive")),
+            ..Bookmark::default()
+        })
+        .collect())
+}
+
+/// Pocket exported HTML until 2024 and CSV afterwards; either is accepted.
+pub fn parse_pocket(text: &str) -> Result<Vec<Bookmark>> {
+    if text.trim_start_matches('\u{feff}').trim_start().starts_with('<') {
+        Ok(parse_pocket_html(text))
+    } else {
+        parse_pocket_csv(text)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct RaindropRow {
+    url: String,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    note: Option<String>,
+    #[serde(default)]
+    folder: Option<String>,
+    #[serde(default)]
+    tags: Option<String>,
+    #[serde(default)]
+    created: Option<String>,
+    #[serde(default)]
+    highlights: Option<String>,
+}
+
+/// Raindrop.io's CSV export. Items in the "Unsorted" collection get no folder.
+pub fn parse_raindrop(text: &str) -> Result<Vec<Bookmark>> {
+    let rows: Vec<RaindropRow> = read_csv(text, "Raindrop.io")?;
+    Ok(rows.into_iter()
+        .map(|row| Bookmark {
+            url: row.url,
+            title: non_empty(row.title),
+            folders: non_empty(row.folder)
+                .filter(|folder| !folder.eq_ignore_ascii_case("unsorted"))
+                .map(|folder| folder.split('/').map(str::trim).filter(|part| !part.is_empty()).map(String::from).collect())
+                .unwrap_or_default(),
+            added: row.created.as_deref().and_then(parse_rfc3339),
+            tags: split_tags(&row.tags.unwrap_or_default()),
+            notes: non_empty(row.note),
+            highlights: row.highlights.unwrap_or_default()
+                .split("Highlight:")
+                .map(str::trim)
+                .filter(|highlight| !highlight.is_empty())
+                .map(String::from)
+                .collect(),
+            ..Bookmark::default()
+        })
+        .collect())
+}
+
+#[derive(Deserialize, Debug)]
+struct PinboardPost {
+    href: String,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    extended: Option<String>,
+    #[serde(default)]
+    time: Option<String>,
+    #[serde(default)]
+    toread: Option<String>,
+    #[serde(default)]
+    tags: Option<String>,
+}
+
+/// Pinboard's JSON export: `description` is the title, `extended` the notes, tags are space-separated.
+pub fn parse_pinboard(json: &str) -> Result<Vec<Bookmark>> {
+    let posts: Vec<PinboardPost> = serde_json::from_str(json).map_err(|e| eyre!("Not a Pinboard JSON export: {}", e))?;
+    Ok(posts.into_iter()
+        .map(|post| Bookmark {
+            url: post.href,
+            title: non_empty(post.description),
+            added: post.time.as_deref().and_then(parse_rfc3339),
+            tags: post.tags.unwrap_or_default().split_whitespace().map(String::from).collect(),
+            notes: non_empty(post.extended),
+            read: post.toread.map(|toread| toread != "yes"),
+            ..Bookmark::default()
+        })
+        .collect())
+}
+
+#[derive(Deserialize, Debug)]
+struct InstapaperRow {
+    #[serde(rename = "URL")]
+    url: String,
+    #[serde(rename = "Title", default)]
+    title: Option<String>,
+    #[serde(rename = "Selection", default)]
+    selection: Option<String>,
+    #[serde(rename = "Folder", default)]
+    folder: Option<String>,
+    #[serde(rename = "Timestamp", default)]
+    timestamp: Option<String>,
+    #[serde(rename = "Tags", default)]
+    tags: Option<String>,
+}
+
+fn instapaper_tags(value: &str) -> Vec<String> {
+    // Newer exports write the tags as a JSON list
+    match serde_json::from_str::<Vec<String>>(value) {
+        Ok(tags) => tags,
+        Err(_) => split_tags(value),
+    }
+}
+
+/// Instapaper's CSV export. "Unread" and "Archive" are read states; any other folder is kept.
+pub fn parse_instapaper(text: &str) -> Result<Vec<Bookmark>> {
+    let rows: Vec<InstapaperRow> = read_csv(text, "Instapaper")?;
+    Ok(rows.into_iter()
+        .map(|row| {
+            let folder = non_empty(row.folder).unwrap_or_default();
+            let read = match folder.to_lowercase().as_str() {
+                "unread" => Some(false),
+                "archive" => Some(true),
+                _ => None,
+            };
+            Bookmark {
+                url: row.url,
+                title: non_empty(row.title),
+                folders: if read.is_none() && !folder.is_empty() { vec![folder] } else { Vec::new() },
+                added: row.timestamp.as_deref().and_then(parse_timestamp),
+                tags: instapaper_tags(row.tags.as_deref().unwrap_or_default()),
+                highlights: non_empty(row.selection).into_iter().collect(),
+                read,
+                ..Bookmark::default()
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::import_bookmarks;
+    use crate::testing::{fixture, mock_config};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_parse_pocket_html_and_csv() {
+        let html = r#"<!DOCTYPE html><html><head><title>Pocket Export</title></head><body>
+<h1>Unread</h1>
+<ul><li><a href="https://a.example/" time_added="1500000000" tags="rust,later">A &amp; B</a></li></ul>
+<h1>Read Archive</h1>
+<ul><li><a href="https://b.example/" time_added="1600000000" tags="">B</a></li></ul>
+</body></html>"#;
+        let bookmarks = parse_pocket(html).unwrap();
+        assert_eq!(bookmarks.len(), 2);
+        assert_eq!((bookmarks[0].title.as_deref(), bookmarks[0].read), (Some("A & B"), Some(false)));
+        assert_eq!(bookmarks[0].tags, vec!["rust", "later"]);
+        assert_eq!(bookmarks[1].read, Some(true));
+
+        let csv = "title,url,time_added,cursor,tags,status\nA,https://a.example/,1500000000,,rust|later,unread\n";
+        let bookmarks = parse_pocket(csv).unwrap();
+        assert_eq!(bookmarks[0].tags, vec!["rust", "later"]);
+        assert_eq!(bookmarks[0].added, DateTime::from_timestamp(1_500_000_000, 0));
+        assert_eq!(bookmarks[0].read, Some(false));
+    }
+
+    #[test]
+    fn test_parse_raindrop_pinboard_and_instapaper() {
+        let raindrop = "id,title,note,excerpt,url,folder,tags,created,cover,highlights,favorite\n\
+1,Rust,my note,,https://a.example/,Dev/Rust,\"rust, lang\",2023-05-01T12:00:00.000Z,,\"Highlight:first\nHighlight:second\",false\n";
+        let bookmark = &parse_raindrop(raindrop).unwrap()[0];
+        assert_eq!(bookmark.folders, vec!["Dev", "Rust"]);
+        assert_eq!(bookmark.tags, vec!["rust", "lang"]);
+        assert_eq!(bookmark.highlights, vec!["first", "second"]);
+        assert_eq!(bookmark.notes.as_deref(), Some("my note"));
+        assert_eq!(bookmark.added, parse_rfc3339("2023-05-01T12:00:00Z"));
+
+        let pinboard = r#"[{"href":"https://b.example/","description":"B","extended":"why","time":"2020-01-02T03:04:05Z","toread":"yes","tags":"a b"}]"#;
+        let bookmark = &parse_pinboard(pinboard).unwrap()[0];
+        assert_eq!((bookmark.title.as_deref(), bookmark.notes.as_deref(), bookmark.read), (Some("B"), Some("why"), Some(false)));
+        assert_eq!(bookmark.tags, vec!["a", "b"]);
+
+        let instapaper = "URL,Title,Selection,Folder,Timestamp,Tags\nhttps://c.example/,C,quoted,Archive,1500000000,\"[\"\"x\"\"]\"\nhttps://d.example/,D,,Essays,1500000000,\n";
+        let bookmarks = parse_instapaper(instapaper).unwrap();
+        assert_eq!((bookmarks[0].read, bookmarks[0].highlights.clone(), bookmarks[0].tags.clone()), (Some(true), vec!["quoted".to_string()], vec!["x".to_string()]));
+        assert_eq!((bookmarks[1].read, bookmarks[1].folders.clone()), (None, vec!["Essays".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn test_imported_item_maps_into_frontmatter_and_body() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/videos"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(fixture("youtube_video.json"), "application/json"))
+            .mount(&server)
+            .await;
+        let vault = tempfile::tempdir().unwrap();
+        let config = mock_config(&server, vault.path());
+        let pinboard = r#"[{"href":"https://www.youtube.com/watch?v=dQw4w9WgXcQ","description":"Video","extended":"watch again","time":"2017-07-14T02:40:00Z","toread":"no","tags":"music"}]"#;
+
+        let (results, _) = import_bookmarks(parse_pinboard(pinboard).unwrap(), &config, 1, |_, _, _| {}).await;
+        let content = std::fs::read_to_string(results[0].outcome.as_ref().unwrap()).unwrap();
+        assert!(content.contains("date: 2017-07-13\n"), "got: {}", content);
+        assert!(content.contains("  - music\n"), "got: {}", content);
+        assert!(content.contains("read: true\n"), "got: {}", content);
+        assert!(content.contains("## Notes\nwatch again"), "got: {}", content);
+    }
+}
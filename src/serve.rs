@@ -0,0 +1,452 @@
+use log::{debug, info, warn};
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use axum::extract::{Query, Request, State};
+use axum::http::{header, HeaderMap, HeaderValue, Method, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::{DateTime, Local};
+use eyre::{eyre, Result};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+
+use crate::batch::{self, BatchResult};
+use crate::capture::Capture;
+use crate::error::{self, Error};
+use crate::html::escape_html;
+use crate::http::urlencode;
+use crate::{queue, Config};
+
+/// The `serve:` config section. Every request must carry the token, as `Authorization: Bearer <token>`
+/// (or `?token=` on GET requests, for bookmarklets); without a configured `token` one is generated
+/// and kept in `token_file`. `cors_origins` may contain `*`; `hosts` lists extra `Host` headers to
+/// accept besides `bind` (and `localhost` for loopback binds).
+#[derive(Deserialize, Debug, Clone)]
+pub struct ServeConfig {
+    #[serde(default = "default_bind")]
+    pub bind: String,
+    pub token: Option<String>,
+    pub token_file: Option<PathBuf>,
+    #[serde(default)]
+    pub cors_origins: Vec<String>,
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    #[serde(default = "default_recent")]
+    pub recent: usize,
+}
+
+fn default_bind() -> String { "127.0.0.1:8765".to_string() }
+fn default_recent() -> usize { 50 }
+
+impl Default for ServeConfig {
+    fn default() -> Self {
+        serde_yaml::from_str("{}").expect("defaults deserialize")
+    }
+}
+
+impl ServeConfig {
+    /// `token_file` if set, otherwise `$XDG_STATE_HOME/obsidian-link/serve-token` (or the platform equivalent).
+    pub fn resolved_token_file(&self) -> Option<PathBuf> {
+        match &self.token_file {
+            Some(file) => file.to_str().and_then(|file| crate::expanduser(file).ok()),
+            None => dirs::state_dir().or_else(dirs::data_local_dir).map(|dir| dir.join(env!("CARGO_PKG_NAME")).join("serve-token")),
+        }
+    }
+}
+
+fn generate_token() -> Result<String> {
+    let mut bytes = [0u8; 24];
+    getrandom::getrandom(&mut bytes).map_err(|e| eyre!("Failed to generate a token: {}", e))?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Reads the token saved in `path`, generating and saving one (readable only by the user) if there is none.
+pub fn load_or_create_token(path: &Path) -> Result<String> {
+    debug!("load_or_create_token: path={}", path.display());
+    if let Ok(token) = std::fs::read_to_string(path) {
+        if !token.trim().is_empty() {
+            return Ok(token.trim().to_string());
+        }
+    }
+    let token = generate_token()?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    std::io::Write::write_all(&mut options.open(path)?, token.as_bytes())
+        .map_err(|e| eyre!("Failed to save the serve token to {}: {}", path.display(), e))?;
+    info!("Generated a serve token in {}", path.display());
+    Ok(token)
+}
+
+#[derive(Debug)]
+pub struct RecentCapture {
+    pub at: DateTime<Local>,
+    pub url: String,
+    pub outcome: std::result::Result<PathBuf, String>,
+}
+
+pub struct ServerState {
+    config: Config,
+    options: ServeConfig,
+    recent: Mutex<VecDeque<RecentCapture>>,
+    write_lock: Mutex<()>,
+}
+
+impl ServerState {
+    pub fn new(config: Config, options: ServeConfig) -> Arc<Self> {
+        Arc::new(ServerState { config, options, recent: Mutex::new(VecDeque::new()), write_lock: Mutex::new(()) })
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct CaptureQuery {
+    url: Option<String>,
+    title: Option<String>,
+    selection: Option<String>,
+    tags: Option<String>,
+    folder: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+fn status_for(report: &eyre::Report) -> StatusCode {
+    match error::classify(report) {
+        Some(error) if error.is_transient() => StatusCode::SERVICE_UNAVAILABLE,
+        Some(Error::NoMatchingRule(_) | Error::MetadataMissing(_)) => StatusCode::UNPROCESSABLE_ENTITY,
+        Some(Error::Duplicate(_)) => StatusCode::CONFLICT,
+        Some(Error::FetchFailed { .. } | Error::LlmFailed { .. } | Error::ResponseTooLarge { .. }) => StatusCode::BAD_GATEWAY,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+async fn run_capture(state: &ServerState, capture: &Capture) -> Result<PathBuf> {
+    info!("Capturing {}", capture.url);
+    let config = &state.config;
+    let outcome = batch::process_capture(capture, config, &state.write_lock).await;
+    let result = BatchResult { index: 0, url: capture.url.clone(), outcome };
+    queue::record_results(config, std::slice::from_ref(capture), std::slice::from_ref(&result));
+
+    let mut recent = state.recent.lock().await;
+    recent.push_front(RecentCapture {
+        at: Local::now(),
+        url: capture.url.clone(),
+        outcome: result.outcome.as_ref().map(PathBuf::clone).map_err(|report| report.to_string()),
+    });
+    recent.truncate(state.options.recent);
+    result.outcome
+}
+
+async fn capture_post(State(state): State<Arc<ServerState>>, Json(capture): Json<Capture>) -> Response {
+    match run_capture(&state, &capture).await {
+        Ok(path) => Json(json!({ "url": capture.url, "path": path })).into_response(),
+        Err(report) => (status_for(&report), Json(error::to_json(&report))).into_response(),
+    }
+}
+
+async fn capture_get(State(state): State<Arc<ServerState>>, Query(query): Query<CaptureQuery>) -> Response {
+    let Some(url) = query.url.filter(|url| !url.trim().is_empty()) else {
+        return (StatusCode::BAD_REQUEST, Html(page("Missing url", "<p>Pass the page to capture as <code>?url=</code>.</p>"))).into_response();
+    };
+    let capture = Capture {
+        url: url.trim().to_string(),
+        title: query.title,
+        selection: query.selection,
+        tags: query.tags.unwrap_or_default().split(',').map(str::trim).filter(|tag| !tag.is_empty()).map(String::from).collect(),
+        folder: query.folder,
+        ..Capture::default()
+    };
+    match run_capture(&state, &capture).await {
+        Ok(path) => Html(page("Saved", &format!("<p>Saved {}</p><p><code>{}</code></p>", escape_html(&capture.url), escape_html(&path.display().to_string())))).into_response(),
+        Err(report) => (status_for(&report), Html(page("Capture failed", &format!("<p>{}</p>", escape_html(&format!("{:#}", report)))))).into_response(),
+    }
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!doctype html><html><head><meta charset=\"utf-8\"><title>{0} - obsidian-link</title></head><body><h1>{0}</h1>{1}</body></html>",
+        escape_html(title), body
+    )
+}
+
+pub fn bookmarklet(options: &ServeConfig) -> String {
+    let token = options.token.as_ref().map(|token| format!("+'&token={}'", urlencode(token))).unwrap_or_default();
+    format!(
+        "javascript:(()=>{{window.open('http://{}/capture?url='+encodeURIComponent(location.href)+'&title='+encodeURIComponent(document.title)+'&selection='+encodeURIComponent(String(getSelection())){});}})()",
+        options.bind, token
+    )
+}
+
+async fn status_page(State(state): State<Arc<ServerState>>) -> Html<String> {
+    let recent = state.recent.lock().await;
+    let rows = recent.iter().map(|capture| {
+        let (status, detail) = match &capture.outcome {
+            Ok(path) => ("saved", path.display().to_string()),
+            Err(message) => ("failed", message.clone()),
+        };
+        format!(
+            "<tr><td>{}</td><td>{}</td><td><a href=\"{2}\">{2}</a></td><td>{3}</td></tr>",
+            capture.at.format("%Y-%m-%d %H:%M:%S"), status, escape_html(&capture.url), escape_html(&detail)
+        )
+    }).collect::<String>();
+    Html(page("obsidian-link", &format!(
+        "<p>Vault: <code>{}</code></p><p>Drag this to your bookmarks bar: <a href=\"{}\">Save to Obsidian</a></p>\
+         <h2>Recent captures ({})</h2><table><tr><th>Time</th><th>Status</th><th>URL</th><th>Note</th></tr>{}</table>",
+        escape_html(&state.config.vault.display().to_string()), escape_html(&bookmarklet(&state.options)), recent.len(), rows
+    )))
+}
+
+fn is_authorized(options: &ServeConfig, request: &Request) -> bool {
+    let Some(token) = &options.token else {
+        return false;
+    };
+    let bearer = request.headers().get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let query = (request.method() == Method::GET)
+        .then(|| Query::<TokenQuery>::try_from_uri(request.uri()).ok().and_then(|query| query.0.token))
+        .flatten();
+    bearer.is_some_and(|given| token_matches(given, token)) || query.is_some_and(|given| token_matches(&given, token))
+}
+
+// Comparing fixed-length digests without short-circuiting keeps the timing independent of the token
+fn token_matches(given: &str, token: &str) -> bool {
+    let (given, token) = (Sha256::digest(given.as_bytes()), Sha256::digest(token.as_bytes()));
+    given.iter().zip(token.iter()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+// DNS rebinding gives a hostile page our address under its own host name, so only known names are served
+fn is_allowed_host(options: &ServeConfig, headers: &HeaderMap) -> bool {
+    let Some(host) = headers.get(header::HOST).and_then(|value| value.to_str().ok()) else {
+        return false;
+    };
+    if host == options.bind || options.hosts.iter().any(|allowed| allowed == host) {
+        return true;
+    }
+    let Ok(bind) = options.bind.parse::<SocketAddr>() else {
+        return false;
+    };
+    if bind.ip().is_loopback() && host == format!("localhost:{}", bind.port()) {
+        return true;
+    }
+    // An IP literal cannot be rebound
+    host.parse::<SocketAddr>().is_ok_and(|addr| addr.port() == bind.port() && (bind.ip().is_unspecified() || addr.ip() == bind.ip()))
+}
+
+fn is_allowed_origin(options: &ServeConfig, origin: &str) -> bool {
+    options.cors_origins.iter().any(|allowed| allowed == "*" || allowed == origin)
+}
+
+// Pages may embed `<img src=".../capture?...">` or script requests to us; only the user's own
+// top-level navigation (the bookmarklet) or an allowed origin may come from another site
+fn is_cross_site(options: &ServeConfig, headers: &HeaderMap) -> bool {
+    let value = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    if let Some(origin) = value("origin") {
+        let same_origin = value("host").is_some_and(|host| origin == format!("http://{}", host));
+        if !same_origin && !is_allowed_origin(options, origin) {
+            return true;
+        }
+        return false;
+    }
+    match value("sec-fetch-site") {
+        Some("cross-site" | "same-site") => !(value("sec-fetch-mode") == Some("navigate") && value("sec-fetch-dest") == Some("document")),
+        _ => false,
+    }
+}
+
+fn forbidden(message: &str) -> Response {
+    (StatusCode::FORBIDDEN, Json(json!({ "error": { "kind": "forbidden", "message": message } }))).into_response()
+}
+
+fn cors_headers(options: &ServeConfig, request_headers: &HeaderMap, headers: &mut HeaderMap) {
+    let Some(origin) = request_headers.get(header::ORIGIN).and_then(|value| value.to_str().ok()) else {
+        return;
+    };
+    if !is_allowed_origin(options, origin) {
+        return;
+    }
+    if let Ok(origin) = HeaderValue::from_str(origin) {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, HeaderValue::from_static("GET, POST, OPTIONS"));
+        headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, HeaderValue::from_static("authorization, content-type"));
+        headers.insert(header::VARY, HeaderValue::from_static("origin"));
+    }
+}
+
+// Preflight requests carry no credentials, so CORS is answered before the token is checked
+async fn guard(State(state): State<Arc<ServerState>>, request: Request, next: Next) -> Response {
+    let request_headers = request.headers().clone();
+    let mut response = if !is_allowed_host(&state.options, &request_headers) {
+        forbidden("unknown Host header")
+    } else if request.method() == Method::OPTIONS {
+        StatusCode::NO_CONTENT.into_response()
+    } else if is_cross_site(&state.options, &request_headers) {
+        forbidden("cross-site requests are not accepted")
+    } else if !is_authorized(&state.options, &request) {
+        (StatusCode::UNAUTHORIZED, Json(json!({ "error": { "kind": "unauthorized", "message": "missing or invalid bearer token" } }))).into_response()
+    } else {
+        next.run(request).await
+    };
+    cors_headers(&state.options, &request_headers, response.headers_mut());
+    response
+}
+
+pub fn router(state: Arc<ServerState>) -> Router {
+    Router::new()
+        .route("/", get(status_page))
+        .route("/capture", get(capture_get).post(capture_post))
+        .layer(middleware::from_fn_with_state(state.clone(), guard))
+        .with_state(state)
+}
+
+pub async fn serve(config: Config, mut options: ServeConfig) -> Result<()> {
+    if options.token.is_none() {
+        let file = options.resolved_token_file().ok_or_else(|| eyre!("No serve.token is set and no location to keep a generated one"))?;
+        options.token = Some(load_or_create_token(&file)?);
+    }
+    let listener = TcpListener::bind(&options.bind).await
+        .map_err(|e| eyre!("Failed to listen on {}: {}", options.bind, e))?;
+    let address = listener.local_addr()?;
+    options.bind = address.to_string();
+    if !address.ip().is_loopback() {
+        warn!("Listening on a non-loopback address; anyone who can reach {} and knows the token can create notes", address);
+    }
+    debug!("serve: bind={} hosts={:?} cors_origins={:?}", options.bind, options.hosts, options.cors_origins);
+    info!("Listening on http://{}/?token=<token>; bookmarklet: {}", address, bookmarklet(&options));
+    axum::serve(listener, router(ServerState::new(config, options))).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{fixture, mock_config};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    async fn spawn(config: Config, mut options: ServeConfig) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        options.bind = address.to_string();
+        tokio::spawn(async move { axum::serve(listener, router(ServerState::new(config, options))).await });
+        format!("http://{}", address)
+    }
+
+    fn options(yaml: &str) -> ServeConfig {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_post_capture_requires_token_and_writes_note() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/videos"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(fixture("youtube_video.json"), "application/json"))
+            .mount(&server)
+            .await;
+        let vault = tempfile::tempdir().unwrap();
+        let mut config = mock_config(&server, vault.path());
+        config.queue.file = Some(vault.path().join("queue.json"));
+        let base = spawn(config, options("token: secret")).await;
+        let client = reqwest::Client::new();
+        let body = json!({ "url": "https://www.youtube.com/watch?v=dQw4w9WgXcQ", "tags": ["inbox"] });
+
+        let response = client.post(format!("{}/capture", base)).json(&body).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+        let response = client.post(format!("{}/capture", base)).bearer_auth("secret").json(&body).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let created: serde_json::Value = response.json().await.unwrap();
+        let path = PathBuf::from(created["path"].as_str().unwrap());
+        assert!(std::fs::read_to_string(&path).unwrap().contains("  - inbox\n"));
+
+        let status = client.get(format!("{}/?token=secret", base)).send().await.unwrap().text().await.unwrap();
+        assert!(status.contains("Recent captures (1)"));
+        assert!(status.contains("watch?v=dQw4w9WgXcQ"));
+    }
+
+    #[tokio::test]
+    async fn test_cors_preflight_and_get_errors() {
+        let vault = tempfile::tempdir().unwrap();
+        let mut config = crate::testing::test_config();
+        config.vault = vault.path().to_path_buf();
+        let base = spawn(config, options("token: secret\ncors_origins: [\"https://example.com\"]")).await;
+        let client = reqwest::Client::new();
+
+        let response = client.request(reqwest::Method::OPTIONS, format!("{}/capture", base))
+            .header("Origin", "https://example.com")
+            .send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()["access-control-allow-origin"], "https://example.com");
+
+        let response = client.get(format!("{}/capture?token=secret", base)).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        let response = client.get(format!("{}/capture?token=secret", base)).header("Origin", "https://evil.example").send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+        assert!(response.headers().get("access-control-allow-origin").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_rejects_missing_token_cross_site_and_foreign_hosts() {
+        let vault = tempfile::tempdir().unwrap();
+        let config = || {
+            let mut config = crate::testing::test_config();
+            config.vault = vault.path().to_path_buf();
+            config
+        };
+        let client = reqwest::Client::new();
+
+        let open = spawn(config(), options("{}")).await;
+        let response = client.get(format!("{}/", open)).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED, "no token configured means nothing is accepted");
+
+        let base = spawn(config(), options("token: secret")).await;
+        let image = client.get(format!("{}/capture?url=https://a.example&token=secret", base))
+            .header("Sec-Fetch-Site", "cross-site").header("Sec-Fetch-Mode", "no-cors").header("Sec-Fetch-Dest", "image")
+            .send().await.unwrap();
+        assert_eq!(image.status(), reqwest::StatusCode::FORBIDDEN);
+        let navigation = client.get(format!("{}/capture?token=secret", base))
+            .header("Sec-Fetch-Site", "cross-site").header("Sec-Fetch-Mode", "navigate").header("Sec-Fetch-Dest", "document")
+            .send().await.unwrap();
+        assert_eq!(navigation.status(), reqwest::StatusCode::BAD_REQUEST, "the bookmarklet's navigation is let through");
+        let rebound = client.get(format!("{}/?token=secret", base)).header("Host", "attacker.example").send().await.unwrap();
+        assert_eq!(rebound.status(), reqwest::StatusCode::FORBIDDEN);
+        let port = base.rsplit(':').next().unwrap();
+        let localhost = client.get(format!("http://localhost:{}/?token=secret", port)).send().await.unwrap();
+        assert_eq!(localhost.status(), reqwest::StatusCode::OK);
+    }
+
+    #[test]
+    fn test_token_is_generated_once_and_encoded_in_bookmarklet() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("state/serve-token");
+        let token = load_or_create_token(&file).unwrap();
+        assert_eq!(token.len(), 48);
+        assert_eq!(load_or_create_token(&file).unwrap(), token);
+        let bookmarklet = bookmarklet(&options("token: \"a&b c\""));
+        assert!(bookmarklet.contains("&token=a%26b+c'"), "got: {}", bookmarklet);
+    }
+
+    #[test]
+    fn test_status_for_maps_error_kinds() {
+        assert_eq!(status_for(&Error::NoMatchingRule("x".into()).into()), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(status_for(&Error::QuotaExceeded("youtube".into()).into()), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(status_for(&Error::Duplicate("a.md".into()).into()), StatusCode::CONFLICT);
+        assert_eq!(status_for(&eyre::eyre!("boom")), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -0,0 +1,555 @@
+use log::{debug, warn};
+use regex::Regex;
+use serde_json::Value;
+use eyre::{eyre, Result};
+use lazy_static::lazy_static;
+
+use crate::error::Error;
+use crate::html::{escape_html, html_to_markdown, html_to_text};
+use crate::http::HttpClient;
+use crate::{Config, Note};
+
+lazy_static! {
+    static ref MASTODON_REGEX: Regex = Regex::new(r"^https?://([^/]+)/@([^/]+)/(\d+)").expect("valid regex");
+    static ref BLUESKY_REGEX: Regex = Regex::new(r"^https?://bsky\.app/profile/([^/]+)/post/([a-z0-9]+)").expect("valid regex");
+    static ref X_REGEX: Regex = Regex::new(r"^https?://(?:www\.|mobile\.)?(?:twitter|x)\.com/([^/]+)/status(?:es)?/(\d+)").expect("valid regex");
+    static ref HASHTAG_REGEX: Regex = Regex::new(r"(?:^|\s)#(\w+)").expect("valid regex");
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Platform {
+    Mastodon,
+    Bluesky,
+    X,
+}
+
+impl Platform {
+    fn name(&self) -> &'static str {
+        match self {
+            Platform::Mastodon => "Mastodon",
+            Platform::Bluesky => "Bluesky",
+            Platform::X => "X",
+        }
+    }
+
+    fn embed_class(&self) -> &'static str {
+        match self {
+            Platform::Mastodon => "mastodon-embed",
+            Platform::Bluesky => "bluesky-embed",
+            Platform::X => "twitter-tweet",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Media {
+    pub kind: String,
+    pub url: String,
+    pub alt: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SocialPost {
+    pub platform: Platform,
+    pub id: String,
+    pub url: String,
+    pub handle: String,
+    pub display_name: String,
+    pub published_at: String,
+    pub text: String,
+    pub media: Vec<Media>,
+    pub quoted: Option<Box<SocialPost>>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmbedStyle {
+    Markdown,
+    Blockquote,
+}
+
+impl EmbedStyle {
+    pub fn from_config(embed: Option<&str>) -> Result<EmbedStyle> {
+        match embed.unwrap_or("markdown") {
+            "markdown" => Ok(EmbedStyle::Markdown),
+            "blockquote" => Ok(EmbedStyle::Blockquote),
+            other => Err(eyre!("Unknown embed style '{}', expected 'markdown' or 'blockquote'", other)),
+        }
+    }
+}
+
+pub fn detect_platform(url: &str) -> Option<Platform> {
+    if BLUESKY_REGEX.is_match(url) {
+        Some(Platform::Bluesky)
+    } else if X_REGEX.is_match(url) {
+        Some(Platform::X)
+    } else if MASTODON_REGEX.is_match(url) {
+        Some(Platform::Mastodon)
+    } else {
+        None
+    }
+}
+
+async fn get_json(client: &HttpClient, url: &str) -> Result<Value> {
+    debug!("get_json: url={}", url);
+    let response = client.get_cached("social", url).await?;
+    if !response.status().is_success() {
+        return Err(Error::http(url, response.status()).into());
+    }
+    client.json::<Value>(response).await
+}
+
+fn parse_mastodon_status(status: &Value, instance: &str) -> SocialPost {
+    let account = &status["account"];
+    let acct = account["acct"].as_str().unwrap_or_default();
+    let handle = if acct.contains('@') { acct.to_string() } else { format!("{}@{}", acct, instance) };
+    let media = status["media_attachments"].as_array().map_or_else(Vec::new, |items| {
+        items.iter().map(|item| Media {
+            kind: item["type"].as_str().unwrap_or("image").to_string(),
+            url: item["url"].as_str().unwrap_or_default().to_string(),
+            alt: item["description"].as_str().unwrap_or_default().to_string(),
+        }).collect()
+    });
+    let quoted = status["quote"]["quoted_status"].as_object()
+        .map(|_| Box::new(parse_mastodon_status(&status["quote"]["quoted_status"], instance)));
+
+    SocialPost {
+        platform: Platform::Mastodon,
+        id: status["id"].as_str().unwrap_or_default().to_string(),
+        url: status["url"].as_str().unwrap_or_default().to_string(),
+        handle,
+        display_name: account["display_name"].as_str().unwrap_or_default().to_string(),
+        published_at: status["created_at"].as_str().unwrap_or_default().to_string(),
+        text: html_to_markdown(status["content"].as_str().unwrap_or_default()),
+        media,
+        quoted,
+        // Hashtags are links in the HTML content, so take them from the API rather than the text
+        tags: status["tags"].as_array().map_or_else(Vec::new, |tags| {
+            tags.iter().filter_map(|tag| tag["name"].as_str().map(String::from)).collect()
+        }),
+    }
+}
+
+fn mastodon_self_thread(root: &SocialPost, context: &Value, instance: &str) -> Vec<SocialPost> {
+    let mut thread = Vec::new();
+    let mut parent_id = root.id.clone();
+    let descendants = context["descendants"].as_array().cloned().unwrap_or_default();
+    while let Some(next) = descendants.iter().find(|status| {
+        status["in_reply_to_id"].as_str() == Some(parent_id.as_str())
+            && parse_mastodon_status(status, instance).handle == root.handle
+    }) {
+        let post = parse_mastodon_status(next, instance);
+        parent_id = post.id.clone();
+        thread.push(post);
+    }
+    thread
+}
+
+async fn fetch_mastodon(client: &HttpClient, url: &str, unroll: bool) -> Result<Vec<SocialPost>> {
+    debug!("fetch_mastodon: url={} unroll={}", url, unroll);
+    let caps = MASTODON_REGEX.captures(url).ok_or_else(|| eyre!("Failed to parse Mastodon URL"))?;
+    let instance = &caps[1];
+    let id = &caps[3];
+    let status = get_json(client, &format!("https://{}/api/v1/statuses/{}", instance, id)).await?;
+    let root = parse_mastodon_status(&status, instance);
+    let mut posts = vec![root.clone()];
+    if unroll {
+        let context = get_json(client, &format!("https://{}/api/v1/statuses/{}/context", instance, id)).await?;
+        posts.extend(mastodon_self_thread(&root, &context, instance));
+    }
+    Ok(posts)
+}
+
+fn bluesky_post_url(handle: &str, uri: &str) -> String {
+    let rkey = uri.rsplit('/').next().unwrap_or_default();
+    format!("https://bsky.app/profile/{}/post/{}", handle, rkey)
+}
+
+fn parse_bluesky_media(embed: &Value) -> Vec<Media> {
+    let embed = if embed["media"].is_object() { &embed["media"] } else { embed };
+    let mut media = Vec::new();
+    if let Some(images) = embed["images"].as_array() {
+        media.extend(images.iter().map(|image| Media {
+            kind: "image".to_string(),
+            url: image["fullsize"].as_str().unwrap_or_default().to_string(),
+            alt: image["alt"].as_str().unwrap_or_default().to_string(),
+        }));
+    }
+    if let Some(playlist) = embed["playlist"].as_str() {
+        media.push(Media {
+            kind: "video".to_string(),
+            url: playlist.to_string(),
+            alt: embed["alt"].as_str().unwrap_or_default().to_string(),
+        });
+    }
+    media
+}
+
+fn parse_bluesky_quoted(embed: &Value) -> Option<Box<SocialPost>> {
+    // app.bsky.embed.record#view nests the record once, recordWithMedia#view twice
+    let record = if embed["record"]["record"].is_object() { &embed["record"]["record"] } else { &embed["record"] };
+    let value = &record["value"];
+    if !value.is_object() {
+        return None;
+    }
+    let handle = record["author"]["handle"].as_str().unwrap_or_default();
+    let uri = record["uri"].as_str().unwrap_or_default();
+    Some(Box::new(SocialPost {
+        platform: Platform::Bluesky,
+        id: uri.to_string(),
+        url: bluesky_post_url(handle, uri),
+        handle: handle.to_string(),
+        display_name: record["author"]["displayName"].as_str().unwrap_or_default().to_string(),
+        published_at: value["createdAt"].as_str().unwrap_or_default().to_string(),
+        text: value["text"].as_str().unwrap_or_default().to_string(),
+        media: record["embeds"].as_array()
+            .and_then(|embeds| embeds.first())
+            .map(parse_bluesky_media)
+            .unwrap_or_default(),
+        quoted: None,
+        tags: hashtags(value["text"].as_str().unwrap_or_default()),
+    }))
+}
+
+fn parse_bluesky_post(post: &Value) -> SocialPost {
+    let handle = post["author"]["handle"].as_str().unwrap_or_default();
+    let uri = post["uri"].as_str().unwrap_or_default();
+    SocialPost {
+        platform: Platform::Bluesky,
+        id: uri.to_string(),
+        url: bluesky_post_url(handle, uri),
+        handle: handle.to_string(),
+        display_name: post["author"]["displayName"].as_str().unwrap_or_default().to_string(),
+        published_at: post["record"]["createdAt"].as_str().unwrap_or_default().to_string(),
+        text: post["record"]["text"].as_str().unwrap_or_default().to_string(),
+        media: parse_bluesky_media(&post["embed"]),
+        quoted: parse_bluesky_quoted(&post["embed"]),
+        tags: hashtags(post["record"]["text"].as_str().unwrap_or_default()),
+    }
+}
+
+fn parse_bluesky_thread(thread: &Value, unroll: bool) -> Result<Vec<SocialPost>> {
+    let root = &thread["post"];
+    if !root.is_object() {
+        return Err(eyre!("Bluesky post not found"));
+    }
+    let author = root["author"]["did"].as_str().unwrap_or_default();
+    let mut posts = vec![parse_bluesky_post(root)];
+    if !unroll {
+        return Ok(posts);
+    }
+    let mut node = thread;
+    while let Some(reply) = node["replies"].as_array().and_then(|replies| {
+        replies.iter()
+            .filter(|reply| reply["post"]["author"]["did"].as_str() == Some(author))
+            .min_by_key(|reply| reply["post"]["record"]["createdAt"].as_str().unwrap_or_default().to_string())
+    }) {
+        posts.push(parse_bluesky_post(&reply["post"]));
+        node = reply;
+    }
+    Ok(posts)
+}
+
+async fn fetch_bluesky(client: &HttpClient, url: &str, unroll: bool) -> Result<Vec<SocialPost>> {
+    debug!("fetch_bluesky: url={} unroll={}", url, unroll);
+    let caps = BLUESKY_REGEX.captures(url).ok_or_else(|| eyre!("Failed to parse Bluesky URL"))?;
+    let uri = format!("at://{}/app.bsky.feed.post/{}", &caps[1], &caps[2]);
+    let depth = if unroll { 100 } else { 0 };
+    let response = get_json(client, &format!(
+        "https://public.api.bsky.app/xrpc/app.bsky.feed.getPostThread?uri={}&depth={}&parentHeight=0",
+        uri, depth
+    )).await?;
+    parse_bluesky_thread(&response["thread"], unroll)
+}
+
+fn parse_x_tweet(tweet: &Value) -> SocialPost {
+    let handle = tweet["user"]["screen_name"].as_str().unwrap_or_default();
+    let id = tweet["id_str"].as_str().unwrap_or_default();
+    let media = tweet["mediaDetails"].as_array().map_or_else(Vec::new, |items| {
+        items.iter().map(|item| Media {
+            kind: match item["type"].as_str() {
+                Some("photo") | None => "image".to_string(),
+                Some(other) => other.to_string(),
+            },
+            url: item["media_url_https"].as_str().unwrap_or_default().to_string(),
+            alt: item["ext_alt_text"].as_str().unwrap_or_default().to_string(),
+        }).collect()
+    });
+    SocialPost {
+        platform: Platform::X,
+        id: id.to_string(),
+        url: format!("https://x.com/{}/status/{}", handle, id),
+        handle: handle.to_string(),
+        display_name: tweet["user"]["name"].as_str().unwrap_or_default().to_string(),
+        published_at: tweet["created_at"].as_str().unwrap_or_default().to_string(),
+        text: tweet["text"].as_str().unwrap_or_default().to_string(),
+        media,
+        quoted: tweet["quoted_tweet"].as_object().map(|_| Box::new(parse_x_tweet(&tweet["quoted_tweet"]))),
+        tags: hashtags(tweet["text"].as_str().unwrap_or_default()),
+    }
+}
+
+fn x_syndication_token(id: &str) -> String {
+    // mirrors the token computed by the embed widget: (id / 1e15 * PI) in base 36 without zeros or dots
+    let value = id.parse::<f64>().unwrap_or_default() / 1e15 * std::f64::consts::PI;
+    let mut integer = value.trunc() as u64;
+    let mut digits = Vec::new();
+    while integer > 0 {
+        digits.push(std::char::from_digit((integer % 36) as u32, 36).unwrap_or('0'));
+        integer /= 36;
+    }
+    let mut token: String = digits.into_iter().rev().collect();
+    let mut fraction = value.fract();
+    for _ in 0..8 {
+        fraction *= 36.0;
+        token.push(std::char::from_digit(fraction.trunc() as u32, 36).unwrap_or('0'));
+        fraction = fraction.fract();
+    }
+    token.replace('0', "")
+}
+
+async fn fetch_x(client: &HttpClient, url: &str, unroll: bool) -> Result<Vec<SocialPost>> {
+    debug!("fetch_x: url={} unroll={}", url, unroll);
+    let caps = X_REGEX.captures(url).ok_or_else(|| eyre!("Failed to parse X URL"))?;
+    let id = &caps[2];
+    if unroll {
+        warn!("Thread unrolling is not available for X posts; saving the single post");
+    }
+    let tweet = get_json(client, &format!(
+        "https://cdn.syndication.twimg.com/tweet-result?id={}&token={}",
+        id, x_syndication_token(id)
+    )).await?;
+    if tweet["id_str"].as_str().is_none() {
+        return Err(eyre!("X post not found for id={}", id));
+    }
+    Ok(vec![parse_x_tweet(&tweet)])
+}
+
+pub async fn fetch_social_posts(client: &HttpClient, url: &str, unroll: bool) -> Result<Vec<SocialPost>> {
+    debug!("fetch_social_posts: url={} unroll={}", url, unroll);
+    match detect_platform(url) {
+        Some(Platform::Mastodon) => fetch_mastodon(client, url, unroll).await,
+        Some(Platform::Bluesky) => fetch_bluesky(client, url, unroll).await,
+        Some(Platform::X) => fetch_x(client, url, unroll).await,
+        None => Err(eyre!("Unsupported social post URL: {}", url)),
+    }
+}
+
+fn author_label(post: &SocialPost) -> String {
+    if post.display_name.is_empty() {
+        format!("@{}", post.handle)
+    } else {
+        format!("{} (@{})", post.display_name, post.handle)
+    }
+}
+
+fn post_title(post: &SocialPost) -> String {
+    let snippet = html_to_text(&post.text);
+    let snippet: String = snippet.chars().take(60).collect();
+    let name = if post.display_name.is_empty() { &post.handle } else { &post.display_name };
+    if snippet.is_empty() {
+        format!("{} on {} {}", name, post.platform.name(), post.id)
+    } else {
+        format!("{} on {} - {}", name, post.platform.name(), snippet.trim())
+    }
+}
+
+fn quote_lines(text: &str, prefix: &str) -> String {
+    text.lines()
+        .map(|line| if line.is_empty() { prefix.trim_end().to_string() } else { format!("{}{}", prefix, line) })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn render_media(media: &[Media]) -> String {
+    media.iter()
+        .map(|item| match item.kind.as_str() {
+            "image" | "gifv" => format!("![{}]({})", item.alt, item.url),
+            _ => format!("[{}]({})", if item.alt.is_empty() { &item.kind } else { &item.alt }, item.url),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn render_markdown(posts: &[SocialPost]) -> String {
+    let mut sections = Vec::new();
+    for post in posts {
+        let mut section = quote_lines(&post.text, "> ");
+        section += &format!("\n>\n> — {} [{}]({})", author_label(post), post.published_at, post.url);
+        if let Some(quoted) = &post.quoted {
+            section += &format!(
+                "\n>\n{}\n> > — {} [{}]({})",
+                quote_lines(&quoted.text, "> > "),
+                author_label(quoted),
+                quoted.published_at,
+                quoted.url
+            );
+        }
+        sections.push(section);
+    }
+    sections.join("\n\n")
+}
+
+fn render_blockquote(posts: &[SocialPost]) -> String {
+    posts.iter()
+        .map(|post| {
+            let paragraphs = post.text.split("\n\n")
+                .map(|paragraph| format!("<p>{}</p>", escape_html(paragraph).replace('\n', "<br>")))
+                .collect::<String>();
+            format!(
+                "<blockquote class=\"{}\">{}&mdash; {} <a href=\"{}\">{}</a></blockquote>",
+                post.platform.embed_class(),
+                paragraphs,
+                escape_html(&author_label(post)),
+                post.url,
+                post.published_at
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn render_description(posts: &[SocialPost]) -> String {
+    let root = &posts[0];
+    let mut description = format!(
+        "Posted by {} on {} at {}",
+        author_label(root),
+        root.platform.name(),
+        root.published_at
+    );
+    if posts.len() > 1 {
+        description += &format!(" (thread of {} posts)", posts.len());
+    }
+    let media: Vec<Media> = posts.iter()
+        .flat_map(|post| post.media.iter().cloned().chain(post.quoted.iter().flat_map(|q| q.media.clone())))
+        .collect();
+    if !media.is_empty() {
+        description += &format!("\n\n### Media\n{}", render_media(&media));
+    }
+    description
+}
+
+fn hashtags(text: &str) -> Vec<String> {
+    HASHTAG_REGEX.captures_iter(text).map(|caps| caps[1].to_string()).collect()
+}
+
+fn extract_hashtags(posts: &[SocialPost]) -> Vec<String> {
+    let mut tags = Vec::new();
+    for tag in posts.iter().flat_map(|post| &post.tags) {
+        if !tags.contains(tag) {
+            tags.push(tag.clone());
+        }
+    }
+    tags
+}
+
+pub async fn handle_social_url(url: &str, folder: &str, unroll: bool, embed: EmbedStyle, config: &Config) -> Result<Note> {
+    debug!("handle_social_url: url={} folder={} unroll={} embed={:?} config={:?}", url, folder, unroll, embed, config);
+    let posts = fetch_social_posts(config.http()?, url, unroll).await?;
+    let root = posts.first().ok_or_else(|| eyre!("No posts found for {}", url))?;
+    let embed_code = match embed {
+        EmbedStyle::Markdown => render_markdown(&posts),
+        EmbedStyle::Blockquote => render_blockquote(&posts),
+    };
+    Ok(Note {
+        title: post_title(root),
+        description: render_description(&posts),
+        embed_code,
+        url: url.to_string(),
+        folder: folder.to_string(),
+        author: author_label(root),
+        tags: extract_hashtags(&posts),
+        ..Note::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture;
+    use serde_json::json;
+
+    #[test]
+    fn test_detect_platform() {
+        assert_eq!(detect_platform("https://mastodon.social/@Gargron/109324312145128301"), Some(Platform::Mastodon));
+        assert_eq!(detect_platform("https://bsky.app/profile/jay.bsky.team/post/3kqw6j2y7ps2x"), Some(Platform::Bluesky));
+        assert_eq!(detect_platform("https://x.com/rustlang/status/1734250405498732803"), Some(Platform::X));
+        assert_eq!(detect_platform("https://twitter.com/rustlang/status/1734250405498732803?s=20"), Some(Platform::X));
+        assert_eq!(detect_platform("https://example.com/articles/1"), None);
+    }
+
+    #[test]
+    fn test_parse_mastodon_status_with_media_and_quote() {
+        let status = json!({
+            "id": "2",
+            "url": "https://fosstodon.org/@alice/2",
+            "created_at": "2024-01-02T03:04:05.000Z",
+            "content": "<p>Hello <a href=\"https://fosstodon.org/tags/rust\">#<span>rust</span></a></p>",
+            "account": {"acct": "alice", "display_name": "Alice"},
+            "media_attachments": [{"type": "image", "url": "https://files/1.png", "description": "a crab"}],
+            "quote": {"state": "accepted", "quoted_status": {
+                "id": "1",
+                "url": "https://mastodon.social/@bob/1",
+                "created_at": "2024-01-01T00:00:00.000Z",
+                "content": "<p>original</p>",
+                "account": {"acct": "bob@mastodon.social", "display_name": "Bob"},
+                "media_attachments": []
+            }}
+        });
+        let post = parse_mastodon_status(&status, "fosstodon.org");
+        assert_eq!(post.handle, "alice@fosstodon.org");
+        assert_eq!(post.media, vec![Media { kind: "image".into(), url: "https://files/1.png".into(), alt: "a crab".into() }]);
+        let quoted = post.quoted.expect("quoted post");
+        assert_eq!(quoted.handle, "bob@mastodon.social");
+        assert_eq!(quoted.text, "original");
+    }
+
+    #[test]
+    fn test_mastodon_tags_come_from_the_api() {
+        let status: Value = serde_json::from_str(&fixture("mastodon_status.json")).unwrap();
+        let post = parse_mastodon_status(&status, "fosstodon.org");
+        assert!(post.text.contains("rustlang"));
+        assert_eq!(extract_hashtags(&[post]), vec!["rustlang".to_string(), "opensource".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_bluesky_thread_unrolls_author_replies_only() {
+        let post = |rkey: &str, did: &str, text: &str, created: &str| json!({
+            "uri": format!("at://{}/app.bsky.feed.post/{}", did, rkey),
+            "author": {"did": did, "handle": format!("{}.bsky.social", did), "displayName": did},
+            "record": {"text": text, "createdAt": created}
+        });
+        let thread = json!({
+            "post": post("a", "alice", "1/ start #rust", "2024-01-01T00:00:00Z"),
+            "replies": [
+                {"post": post("x", "bob", "nice", "2024-01-01T00:00:30Z"), "replies": []},
+                {"post": post("b", "alice", "2/ more", "2024-01-01T00:01:00Z"), "replies": [
+                    {"post": post("c", "alice", "3/ end", "2024-01-01T00:02:00Z"), "replies": []}
+                ]}
+            ]
+        });
+        let posts = parse_bluesky_thread(&thread, true).expect("thread");
+        assert_eq!(posts.iter().map(|p| p.text.as_str()).collect::<Vec<_>>(), vec!["1/ start #rust", "2/ more", "3/ end"]);
+        assert_eq!(posts[0].url, "https://bsky.app/profile/alice.bsky.social/post/a");
+        assert_eq!(extract_hashtags(&posts), vec!["rust".to_string()]);
+        assert_eq!(parse_bluesky_thread(&thread, false).expect("single").len(), 1);
+    }
+
+    #[test]
+    fn test_render_blockquote_escapes_text() {
+        let post = SocialPost {
+            platform: Platform::X,
+            id: "1".into(),
+            url: "https://x.com/a/status/1".into(),
+            handle: "a".into(),
+            display_name: "A".into(),
+            published_at: "Mon Jan 01 00:00:00 +0000 2024".into(),
+            text: "a < b\nc".into(),
+            media: vec![],
+            quoted: None,
+            tags: vec![],
+        };
+        let html = render_blockquote(&[post]);
+        assert!(html.starts_with("<blockquote class=\"twitter-tweet\"><p>a &lt; b<br>c</p>"));
+    }
+}
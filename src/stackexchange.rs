@@ -0,0 +1,190 @@
+use log::debug;
+use regex::Regex;
+use serde_json::Value;
+use eyre::{eyre, Result};
+use lazy_static::lazy_static;
+
+use crate::error::Error;
+use crate::html::{html_to_markdown, html_to_text};
+use crate::http::HttpClient;
+use crate::{Config, Note};
+
+const API_BASE: &str = "https://api.stackexchange.com/2.3";
+
+lazy_static! {
+    static ref QUESTION_REGEX: Regex = Regex::new(
+        r"^https?://((?:[a-z0-9-]+\.)*(?:stackoverflow\.com|stackexchange\.com|superuser\.com|serverfault\.com|askubuntu\.com|mathoverflow\.net|stackapps\.com))/(?:questions|q)/(\d+)"
+    ).expect("valid regex");
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Answer {
+    pub author: String,
+    pub score: i64,
+    pub accepted: bool,
+    pub body: String,
+    pub link: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Question {
+    pub site: String,
+    pub id: String,
+    pub title: String,
+    pub tags: Vec<String>,
+    pub score: i64,
+    pub asker: String,
+    pub body: String,
+    pub link: String,
+    pub answer: Option<Answer>,
+}
+
+pub fn parse_question_url(url: &str) -> Option<(String, String)> {
+    QUESTION_REGEX.captures(url).map(|caps| (caps[1].trim_start_matches("www.").to_string(), caps[2].to_string()))
+}
+
+async fn get_json(client: &HttpClient, url: &str) -> Result<Value> {
+    debug!("get_json: url={}", url);
+    let response = client.get_cached("stackexchange", url).await?;
+    let status = response.status();
+    let body = client.json::<Value>(response).await?;
+    if !status.is_success() {
+        let message = body["error_message"].as_str().unwrap_or_default().to_string();
+        if body["error_name"] == "throttle_violation" {
+            return Err(Error::QuotaExceeded(format!("Stack Exchange API: {}", message)).into());
+        }
+        return Err(Error::FetchFailed { url: url.to_string(), status: Some(status.as_u16()), message }.into());
+    }
+    Ok(body)
+}
+
+pub fn parse_question(site: &str, item: &Value) -> Question {
+    Question {
+        site: site.to_string(),
+        id: item["question_id"].as_u64().map(|id| id.to_string()).unwrap_or_default(),
+        title: html_to_text(item["title"].as_str().unwrap_or_default()),
+        tags: item["tags"].as_array().map_or_else(Vec::new, |tags| {
+            tags.iter().filter_map(|tag| tag.as_str().map(String::from)).collect()
+        }),
+        score: item["score"].as_i64().unwrap_or_default(),
+        asker: html_to_text(item["owner"]["display_name"].as_str().unwrap_or_default()),
+        body: html_to_markdown(item["body"].as_str().unwrap_or_default()),
+        link: item["link"].as_str().unwrap_or_default().to_string(),
+        answer: None,
+    }
+}
+
+pub fn select_answer(answers: &Value, accepted_answer_id: Option<u64>) -> Option<Answer> {
+    let items = answers["items"].as_array()?;
+    let accepted = accepted_answer_id.and_then(|id| items.iter().find(|item| item["answer_id"].as_u64() == Some(id)));
+    let item = accepted.or_else(|| items.iter().max_by_key(|item| item["score"].as_i64().unwrap_or_default()))?;
+    Some(Answer {
+        author: html_to_text(item["owner"]["display_name"].as_str().unwrap_or_default()),
+        score: item["score"].as_i64().unwrap_or_default(),
+        accepted: item["is_accepted"].as_bool().unwrap_or(false),
+        body: html_to_markdown(item["body"].as_str().unwrap_or_default()),
+        link: item["link"].as_str().unwrap_or_default().to_string(),
+    })
+}
+
+pub async fn fetch_question(client: &HttpClient, url: &str) -> Result<Question> {
+    debug!("fetch_question: url={}", url);
+    let (site, id) = parse_question_url(url).ok_or_else(|| eyre!("Not a Stack Exchange question URL: {}", url))?;
+    let response = get_json(client, &format!("{}/questions/{}?site={}&filter=withbody", API_BASE, id, site)).await?;
+    let item = response["items"].as_array()
+        .and_then(|items| items.first())
+        .ok_or_else(|| eyre!("Question {} not found on {}", id, site))?;
+    let mut question = parse_question(&site, item);
+
+    let answers = get_json(client, &format!(
+        "{}/questions/{}/answers?site={}&filter=withbody&sort=votes&order=desc&pagesize=30",
+        API_BASE, id, site
+    )).await?;
+    question.answer = select_answer(&answers, item["accepted_answer_id"].as_u64());
+    Ok(question)
+}
+
+fn render_description(question: &Question) -> String {
+    let mut description = format!("## Question\n{}", question.body);
+    match &question.answer {
+        Some(answer) => {
+            let heading = if answer.accepted { "Accepted Answer" } else { "Top Answer" };
+            description += &format!(
+                "\n\n## {}\nBy {} (score {}) [link]({})\n\n{}",
+                heading, answer.author, answer.score, answer.link, answer.body
+            );
+        }
+        None => description += "\n\n## Answer\nNo answers yet.",
+    }
+    description
+}
+
+pub async fn handle_stackexchange_url(url: &str, folder: &str, config: &Config) -> Result<Note> {
+    debug!("handle_stackexchange_url: url={} folder={} config={:?}", url, folder, config);
+    let question = fetch_question(config.http()?, url).await?;
+    let fields = vec![
+        ("site".to_string(), serde_yaml::Value::from(question.site.as_str())),
+        ("score".to_string(), serde_yaml::Value::from(question.score)),
+    ];
+    Ok(Note {
+        id: question.id.clone(),
+        title: question.title.clone(),
+        description: render_description(&question),
+        url: url.to_string(),
+        folder: folder.to_string(),
+        author: question.asker.clone(),
+        tags: question.tags.clone(),
+        fields,
+        ..Note::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_question_url() {
+        assert_eq!(
+            parse_question_url("https://stackoverflow.com/questions/24145823/how-do-i-convert-a-string-into-a-vector"),
+            Some(("stackoverflow.com".to_string(), "24145823".to_string()))
+        );
+        assert_eq!(
+            parse_question_url("https://unix.stackexchange.com/q/12345"),
+            Some(("unix.stackexchange.com".to_string(), "12345".to_string()))
+        );
+        assert_eq!(parse_question_url("https://stackoverflow.com/users/1/jeff"), None);
+    }
+
+    #[test]
+    fn test_parse_question_decodes_entities() {
+        let item = json!({
+            "question_id": 1,
+            "title": "Why &quot;borrowed value&quot; errors?",
+            "tags": ["rust", "borrow-checker"],
+            "score": 12,
+            "owner": {"display_name": "Ferris"},
+            "body": "<p>Code:</p><pre><code>let x = &amp;y;\n</code></pre>",
+            "link": "https://stackoverflow.com/q/1"
+        });
+        let question = parse_question("stackoverflow.com", &item);
+        assert_eq!(question.title, "Why \"borrowed value\" errors?");
+        assert_eq!(question.body, "Code:\n\n```\nlet x = &y;\n```");
+        assert_eq!(question.tags, vec!["rust".to_string(), "borrow-checker".to_string()]);
+    }
+
+    #[test]
+    fn test_select_answer_prefers_accepted_then_top_voted() {
+        let answers = json!({"items": [
+            {"answer_id": 10, "score": 50, "is_accepted": false, "body": "<p>popular</p>", "owner": {"display_name": "A"}},
+            {"answer_id": 11, "score": 5, "is_accepted": true, "body": "<p>accepted</p>", "owner": {"display_name": "B"}}
+        ]});
+        let answer = select_answer(&answers, Some(11)).expect("answer");
+        assert!(answer.accepted);
+        assert_eq!(answer.body, "accepted");
+        let answer = select_answer(&answers, None).expect("answer");
+        assert_eq!(answer.body, "popular");
+        assert_eq!(select_answer(&json!({"items": []}), None), None);
+    }
+}
@@ -0,0 +1,207 @@
+use log::{debug, warn};
+use std::path::{Path, PathBuf};
+
+use eyre::{eyre, Result};
+
+use crate::check::collect_notes;
+use crate::{expanduser, fetch_note, read_frontmatter, render_note, vault, write_attachment, Config, Note};
+
+pub const MANAGED_START: &str = "<!-- obsidian-link:start -->";
+pub const MANAGED_END: &str = "<!-- obsidian-link:end -->";
+// Capture timestamps describe when the note was first created, so a refresh keeps them
+const PRESERVED_KEYS: &[&str] = &["date", "day", "time"];
+
+fn split_note(content: &str) -> (Option<&str>, &str) {
+    let Some(rest) = content.strip_prefix("---\n") else {
+        return (None, content);
+    };
+    match rest.find("\n---") {
+        Some(end) => {
+            let body = &rest[end + 4..];
+            (Some(&rest[..end + 1]), body.trim_start_matches('\n'))
+        }
+        None => (None, content),
+    }
+}
+
+fn frontmatter_blocks(yaml: &str) -> Vec<(String, String)> {
+    let mut blocks: Vec<(String, String)> = Vec::new();
+    for line in yaml.lines() {
+        let starts_block = !line.starts_with([' ', '\t', '-']) && line.contains(':');
+        match blocks.last_mut() {
+            Some((_, text)) if !starts_block => {
+                text.push_str(line);
+                text.push('\n');
+            }
+            _ => {
+                let key = line.split(':').next().unwrap_or_default().trim().to_string();
+                blocks.push((key, format!("{}\n", line)));
+            }
+        }
+    }
+    blocks
+}
+
+fn block_tags(block: &str) -> Vec<String> {
+    match serde_yaml::from_str::<serde_yaml::Mapping>(block).ok().and_then(|m| m.get("tags").cloned()) {
+        Some(serde_yaml::Value::Sequence(tags)) => tags.iter().filter_map(|tag| tag.as_str().map(String::from)).collect(),
+        Some(serde_yaml::Value::String(tag)) => vec![tag],
+        _ => Vec::new(),
+    }
+}
+
+fn merge_tags(existing: &str, updated: &str) -> String {
+    let mut tags = block_tags(existing);
+    for tag in block_tags(updated) {
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags.iter().fold(String::from("tags:\n"), |block, tag| block + &format!("  - {}\n", tag))
+}
+
+pub fn merge_frontmatter(existing: &str, updated: &str) -> String {
+    let updated_blocks = frontmatter_blocks(updated);
+    let find_updated = |key: &str| updated_blocks.iter().find(|(k, _)| k == key).map(|(_, text)| text);
+    let mut merged = String::new();
+    let mut seen = Vec::new();
+
+    for (key, text) in frontmatter_blocks(existing) {
+        match find_updated(&key) {
+            Some(_) if PRESERVED_KEYS.contains(&key.as_str()) => merged.push_str(&text),
+            Some(new_text) if key == "tags" => merged.push_str(&merge_tags(&text, new_text)),
+            Some(new_text) => merged.push_str(new_text),
+            None => merged.push_str(&text),
+        }
+        seen.push(key);
+    }
+    for (key, text) in &updated_blocks {
+        if !seen.contains(key) {
+            merged.push_str(text);
+        }
+    }
+    merged
+}
+
+fn managed_range(body: &str) -> Option<(usize, usize)> {
+    let start = body.find(MANAGED_START)?;
+    let end = body[start..].find(MANAGED_END)? + start + MANAGED_END.len();
+    Some((start, end))
+}
+
+pub fn merge_note(existing: &str, updated: &str) -> String {
+    let (existing_frontmatter, existing_body) = split_note(existing);
+    let (updated_frontmatter, updated_body) = split_note(updated);
+
+    let frontmatter = merge_frontmatter(existing_frontmatter.unwrap_or_default(), updated_frontmatter.unwrap_or_default());
+    let body = match (managed_range(existing_body), managed_range(updated_body)) {
+        (Some((start, end)), Some((new_start, new_end))) => {
+            format!("{}{}{}", &existing_body[..start], &updated_body[new_start..new_end], &existing_body[end..])
+        }
+        _ => {
+            warn!("Note has no {} markers, leaving its body untouched", MANAGED_START);
+            existing_body.to_string()
+        }
+    };
+    format!("---\n{}---\n\n{}", frontmatter, body)
+}
+
+pub fn set_frontmatter_fields(content: &str, fields: &[(&str, String)]) -> String {
+    let (frontmatter, body) = split_note(content);
+    let updated = fields.iter().fold(String::new(), |block, (key, value)| {
+        let mut field = serde_yaml::Mapping::new();
+        field.insert(serde_yaml::Value::from(*key), serde_yaml::Value::from(value.as_str()));
+        block + &serde_yaml::to_string(&field).unwrap_or_default()
+    });
+    format!("---\n{}---\n\n{}", merge_frontmatter(frontmatter.unwrap_or_default(), &updated), body)
+}
+
+/// The first note anywhere in the vault (by path) whose frontmatter `url` is `url`.
+pub fn find_note_by_url(root: &Path, url: &str) -> Option<PathBuf> {
+    debug!("find_note_by_url: root={} url={}", root.display(), url);
+    collect_notes(root).into_iter().find(|(_, note_url)| note_url == url).map(|(path, _)| path)
+}
+
+fn resolve_target(target: &str, root: &Path) -> Result<PathBuf> {
+    let path = expanduser(target)?;
+    let path = if path.is_absolute() || path.exists() { path } else { root.join(path) };
+    let path = if path.exists() { path } else { path.with_extension("md") };
+    let path = path.canonicalize().map_err(|e| eyre!("Note {} not found: {}", target, e))?;
+    vault::ensure_within(root, &path)?;
+    Ok(path)
+}
+
+pub async fn prepare_update(target: &str, config: &Config) -> Result<(PathBuf, Note, String)> {
+    debug!("prepare_update: target={}", target);
+    let root = vault::vault_root(&config.vault)?;
+    let (path, note) = if target.starts_with("http://") || target.starts_with("https://") {
+        // Notes may have been moved since they were created, so look through the whole vault
+        // before spending any API calls
+        let path = find_note_by_url(&root, target)
+            .ok_or_else(|| eyre!("No existing note for {} in {}", target, root.display()))?;
+        (path, fetch_note(target, config).await?)
+    } else {
+        let path = resolve_target(target, &root)?;
+        let url = read_frontmatter(&path)
+            .and_then(|fm| fm.get("url").and_then(|value| value.as_str().map(String::from)))
+            .ok_or_else(|| eyre!("Note {} has no url in its frontmatter", path.display()))?;
+        (path, fetch_note(&url, config).await?)
+    };
+
+    let existing = std::fs::read_to_string(&path).map_err(|e| eyre!("Failed to read {}: {}", path.display(), e))?;
+    let merged = merge_note(&existing, &render_note(&note, &config.frontmatter));
+    Ok((path, note, merged))
+}
+
+pub async fn update_note(target: &str, config: &Config) -> Result<PathBuf> {
+    debug!("update_note: target={}", target);
+    let root = vault::vault_root(&config.vault)?;
+    let (path, note, merged) = prepare_update(target, config).await?;
+    for attachment in &note.attachments {
+        write_attachment(attachment, &root)?;
+    }
+    vault::write_atomic(&root, &path, merged.as_bytes())?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXISTING: &str = "---\ndate: 2024-01-01\nday: Mon\ntime: 09:00\ntype: link\ntags:\n  - rust\n  - mine\nurl: https://example.com\nauthor: Old Author\nrating: 5\n---\n\n<!-- obsidian-link:start -->\nold embed\n\n## Description\nold description\n<!-- obsidian-link:end -->\n\n## My Notes\n- a highlight\n";
+    const UPDATED: &str = "---\ndate: 2024-06-01\nday: Sat\ntime: 10:00\ntype: link\ntags:\n  - rust\n  - new\nurl: https://example.com\nauthor: New Author\n---\n\n<!-- obsidian-link:start -->\nnew embed\n\n## Description\nnew description\n<!-- obsidian-link:end -->\n";
+
+    #[test]
+    fn test_merge_frontmatter_keeps_user_keys_and_timestamps() {
+        let merged = merge_note(EXISTING, UPDATED);
+        assert!(merged.starts_with("---\ndate: 2024-01-01\nday: Mon\ntime: 09:00\n"), "got: {}", merged);
+        assert!(merged.contains("tags:\n  - rust\n  - mine\n  - new\n"));
+        assert!(merged.contains("author: New Author\n"));
+        assert!(merged.contains("rating: 5\n"));
+    }
+
+    #[test]
+    fn test_merge_body_replaces_only_managed_section() {
+        let merged = merge_note(EXISTING, UPDATED);
+        assert!(merged.contains("new embed\n\n## Description\nnew description\n<!-- obsidian-link:end -->\n\n## My Notes\n- a highlight\n"));
+        assert!(!merged.contains("old description"));
+    }
+
+    #[test]
+    fn test_find_note_by_url_searches_the_whole_vault() {
+        let vault = tempfile::tempdir().unwrap();
+        let root = vault.path();
+        std::fs::create_dir_all(root.join("archive/2024")).unwrap();
+        std::fs::write(root.join("archive/2024/Moved.md"), EXISTING).unwrap();
+        assert_eq!(find_note_by_url(root, "https://example.com"), Some(root.join("archive/2024/Moved.md")));
+        assert_eq!(find_note_by_url(root, "https://example.com/other"), None);
+    }
+
+    #[test]
+    fn test_merge_without_markers_keeps_body() {
+        let legacy = "---\nurl: https://example.com\nauthor: Old\n---\n\nembed\n\n## Description\nmine\n";
+        let merged = merge_note(legacy, UPDATED);
+        assert!(merged.ends_with("---\n\nembed\n\n## Description\nmine\n"));
+        assert!(merged.contains("author: New Author\n"));
+    }
+}
@@ -0,0 +1,136 @@
+use log::debug;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use eyre::{eyre, Result};
+
+use crate::error::Error;
+use crate::expanduser;
+
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathEscapeError {
+    pub path: PathBuf,
+    pub root: PathBuf,
+}
+
+impl fmt::Display for PathEscapeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Refusing to write {} because it resolves outside the vault {}", self.path.display(), self.root.display())
+    }
+}
+
+impl std::error::Error for PathEscapeError {}
+
+pub fn vault_root(vault_path: &Path) -> Result<PathBuf> {
+    debug!("vault_root: vault_path={}", vault_path.display());
+    let vault_path_str = vault_path.to_str().ok_or_else(|| eyre!("Failed to convert vault path to string"))?;
+    let expanded = expanduser(vault_path_str)?;
+    expanded.canonicalize()
+        .map_err(|e| Error::WriteFailed(format!("vault directory {} is not accessible: {}", expanded.display(), e)).into())
+}
+
+pub fn safe_join(root: &Path, relative: impl AsRef<Path>) -> Result<PathBuf> {
+    let relative = relative.as_ref();
+    debug!("safe_join: root={} relative={}", root.display(), relative.display());
+    let mut path = root.to_path_buf();
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            _ => return Err(PathEscapeError { path: root.join(relative), root: root.to_path_buf() }.into()),
+        }
+    }
+    ensure_within(root, &path)?;
+    Ok(path)
+}
+
+// Canonicalizing the deepest existing ancestor catches symlinked folders or files that point outside the vault
+pub fn ensure_within(root: &Path, path: &Path) -> Result<()> {
+    debug!("ensure_within: root={} path={}", root.display(), path.display());
+    let escape = || PathEscapeError { path: path.to_path_buf(), root: root.to_path_buf() };
+    let existing = path.ancestors()
+        .find(|ancestor| ancestor.symlink_metadata().is_ok())
+        .ok_or_else(escape)?;
+    let resolved = existing.canonicalize().map_err(|_| escape())?;
+    if resolved.starts_with(root) {
+        Ok(())
+    } else {
+        Err(escape().into())
+    }
+}
+
+pub fn write_atomic(root: &Path, path: &Path, bytes: &[u8]) -> Result<()> {
+    debug!("write_atomic: root={} path={} bytes.len={}", root.display(), path.display(), bytes.len());
+    let parent = path.parent().ok_or_else(|| eyre!("Invalid note path: {:?}", path))?;
+    let file_name = path.file_name().and_then(|name| name.to_str()).ok_or_else(|| eyre!("Invalid note path: {:?}", path))?;
+    ensure_within(root, path)?;
+
+    fs::create_dir_all(parent).map_err(|e| Error::WriteFailed(format!("failed to create directory {:?}: {}", parent, e)))?;
+    ensure_within(root, path)?;
+
+    let temp_path = parent.join(format!(
+        ".{}.{}-{}.tmp",
+        file_name,
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = (|| -> Result<()> {
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&temp_path)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    })();
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(Error::WriteFailed(format!("failed to write {:?}: {}", path, e)).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_vault() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        (dir, root)
+    }
+
+    #[test]
+    fn test_safe_join_rejects_traversal_and_absolute_paths() {
+        let (_root_dir, root) = temp_vault();
+        assert_eq!(safe_join(&root, "notes/youtube").unwrap(), root.join("notes/youtube"));
+        for bad in ["../outside", "notes/../../outside", "/etc"] {
+            let err = safe_join(&root, bad).unwrap_err();
+            assert!(err.downcast_ref::<PathEscapeError>().is_some(), "{} was accepted", bad);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_out_of_vault_is_rejected() {
+        let (_root_dir, root) = temp_vault();
+        let (_outside_dir, outside) = temp_vault();
+        std::os::unix::fs::symlink(&outside, root.join("escape")).unwrap();
+        let err = write_atomic(&root, &root.join("escape/note.md"), b"x").unwrap_err();
+        assert!(err.downcast_ref::<PathEscapeError>().is_some());
+        assert!(!outside.join("note.md").exists());
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_file_without_leftovers() {
+        let (_root_dir, root) = temp_vault();
+        let path = root.join("folder/note.md");
+        write_atomic(&root, &path, b"first").unwrap();
+        write_atomic(&root, &path, b"second").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        assert_eq!(fs::read_dir(root.join("folder")).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,300 @@
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use eyre::{eyre, Result};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+use tokio::time::Instant;
+
+use crate::batch::{run_batch, BatchResult};
+use crate::error;
+use crate::queue::is_retriable;
+use crate::{expanduser, vault, Config};
+
+lazy_static! {
+    static ref URL_REGEX: Regex = Regex::new(r"https?://[^\s<>\]]+").expect("valid regex");
+    static ref OPEN_TASK_REGEX: Regex = Regex::new(r"^(\s*[-*+] )\[ \] ").expect("valid regex");
+    static ref DONE_TASK_REGEX: Regex = Regex::new(r"^\s*[-*+] \[[xX]\] ").expect("valid regex");
+}
+
+const FAILED_MARKER: &str = "(obsidian-link failed:";
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkStyle {
+    /// `wikilink` for Markdown inboxes, `comment` for anything else.
+    #[default]
+    Auto,
+    /// Replaces the URL with a wikilink to the created note and ticks a `- [ ]` task.
+    Wikilink,
+    /// Ticks the line's task box, turning a bare URL line into a done task.
+    Check,
+    /// Comments the line out with `#` and appends the created note's path.
+    Comment,
+}
+
+/// The `watch:` config section. A relative `inbox` is resolved against the vault.
+#[derive(Deserialize, Debug, Clone)]
+pub struct WatchConfig {
+    pub inbox: Option<PathBuf>,
+    #[serde(default)]
+    pub mark: MarkStyle,
+    #[serde(default = "default_interval")]
+    pub interval_secs: u64,
+    #[serde(default = "default_retry")]
+    pub retry_secs: u64,
+}
+
+fn default_interval() -> u64 { 5 }
+fn default_retry() -> u64 { 300 }
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        serde_yaml::from_str("{}").expect("defaults deserialize")
+    }
+}
+
+impl WatchConfig {
+    pub fn resolved_inbox(&self, vault_root: &Path) -> Result<PathBuf> {
+        let inbox = self.inbox.as_ref().ok_or_else(|| eyre!("No inbox configured; set watch.inbox or pass --inbox"))?;
+        let inbox = expanduser(inbox.to_str().ok_or_else(|| eyre!("Invalid inbox path: {:?}", inbox))?)?;
+        Ok(if inbox.is_absolute() { inbox } else { vault_root.join(inbox) })
+    }
+
+    pub fn style_for(&self, inbox: &Path) -> MarkStyle {
+        match self.mark {
+            MarkStyle::Auto if inbox.extension().is_some_and(|ext| ext == "md") => MarkStyle::Wikilink,
+            MarkStyle::Auto => MarkStyle::Comment,
+            style => style,
+        }
+    }
+}
+
+// Drops sentence punctuation and closing brackets that belong to the surrounding text,
+// keeping parentheses the URL itself opened (as in Wikipedia article names)
+fn trim_url(url: &str) -> &str {
+    let mut url = url;
+    loop {
+        let trimmed = url.trim_end_matches(['.', ',', ';', ':', '!', '?', '\'', '"', '*', '_']);
+        let trimmed = match trimmed.strip_suffix(')') {
+            Some(rest) if trimmed.matches('(').count() < trimmed.matches(')').count() => rest,
+            _ => trimmed,
+        };
+        if trimmed == url {
+            return url;
+        }
+        url = trimmed;
+    }
+}
+
+/// Lines that still hold an unprocessed URL, with the URL they hold.
+pub fn pending_lines(content: &str) -> Vec<(String, String)> {
+    content.lines()
+        .filter(|line| !line.trim_start().starts_with('#') && !DONE_TASK_REGEX.is_match(line) && !line.contains(FAILED_MARKER))
+        .filter_map(|line| URL_REGEX.find(line).map(|url| (line.to_string(), trim_url(url.as_str()).to_string())))
+        .collect()
+}
+
+// A Markdown link around `url` in `line`, with its text
+fn markdown_link<'a>(line: &'a str, url: &str) -> Option<(&'a str, &'a str)> {
+    let pattern = Regex::new(&format!(r"\[([^\]]*)\]\(<?{}>?\)", regex::escape(url))).ok()?;
+    let caps = pattern.captures(line)?;
+    Some((caps.get(0)?.as_str(), caps.get(1)?.as_str()))
+}
+
+fn wikilink(path: &Path, vault_root: &Path, text: &str) -> String {
+    let target = path.strip_prefix(vault_root).unwrap_or(path).with_extension("");
+    if text.trim().is_empty() {
+        format!("[[{}]]", target.to_string_lossy())
+    } else {
+        format!("[[{}|{}]]", target.to_string_lossy(), text.trim())
+    }
+}
+
+fn check(line: &str) -> String {
+    if OPEN_TASK_REGEX.is_match(line) {
+        OPEN_TASK_REGEX.replace(line, "${1}[x] ").into_owned()
+    } else {
+        let indent = &line[..line.len() - line.trim_start().len()];
+        format!("{}- [x] {}", indent, line.trim_start())
+    }
+}
+
+pub fn mark_done(line: &str, url: &str, path: &Path, vault_root: &Path, style: MarkStyle) -> String {
+    match style {
+        MarkStyle::Check => check(line),
+        MarkStyle::Comment => format!("# {} -> {}", line, path.display()),
+        MarkStyle::Wikilink | MarkStyle::Auto => {
+            // A Markdown link is replaced whole, keeping its text as the wikilink alias
+            let (target, text) = markdown_link(line, url).unwrap_or((url, ""));
+            let linked = line.replacen(target, &wikilink(path, vault_root, text), 1);
+            if OPEN_TASK_REGEX.is_match(&linked) { check(&linked) } else { linked }
+        }
+    }
+}
+
+pub fn mark_failed(line: &str, report: &eyre::Report) -> String {
+    let kind = error::classify(report).map_or("unknown", |error| error.kind());
+    format!("{} {} {})", line, FAILED_MARKER, kind)
+}
+
+/// Replaces each original line with its rewrite, matching by content so lines appended
+/// while URLs were being fetched are kept.
+pub fn rewrite_lines(content: &str, replacements: &[(String, String)]) -> String {
+    let mut lines = content.lines().map(String::from).collect::<Vec<_>>();
+    for (original, replacement) in replacements {
+        if let Some(line) = lines.iter_mut().find(|line| *line == original) {
+            *line = replacement.clone();
+        }
+    }
+    let mut rewritten = lines.join("\n");
+    if content.ends_with('\n') {
+        rewritten.push('\n');
+    }
+    rewritten
+}
+
+#[derive(Debug, Default)]
+pub struct WatchState {
+    modified: Option<(SystemTime, u64)>,
+    // Transiently failed URLs and when they may be tried again
+    retry_at: HashMap<String, Instant>,
+}
+
+/// Processes every pending line once, returning the results in inbox order.
+pub async fn process_inbox(inbox: &Path, config: &Config, state: &mut WatchState) -> Result<Vec<BatchResult>> {
+    debug!("process_inbox: inbox={}", inbox.display());
+    let root = vault::vault_root(&config.vault)?;
+    let style = config.watch.style_for(inbox);
+    let content = std::fs::read_to_string(inbox).map_err(|e| eyre!("Failed to read inbox {}: {}", inbox.display(), e))?;
+    let now = Instant::now();
+    let pending = pending_lines(&content).into_iter()
+        .filter(|(_, url)| state.retry_at.get(url).is_none_or(|at| *at <= now))
+        .collect::<Vec<_>>();
+    if pending.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let urls = pending.iter().map(|(_, url)| url.clone()).collect::<Vec<_>>();
+    let results = run_batch(&urls, config, config.batch.concurrency, |done, total, result| {
+        info!("[{}/{}] {} {}", done, total, if result.outcome.is_ok() { "created" } else { "failed" }, result.url);
+    }).await;
+
+    let mut replacements = Vec::new();
+    for ((line, url), result) in pending.iter().zip(&results) {
+        match &result.outcome {
+            Ok(path) => {
+                state.retry_at.remove(url);
+                replacements.push((line.clone(), mark_done(line, url, path, &root, style)));
+            }
+            Err(report) if is_retriable(report) => {
+                warn!("Will retry {} in {}s: {}", url, config.watch.retry_secs, report);
+                state.retry_at.insert(url.clone(), now + Duration::from_secs(config.watch.retry_secs));
+            }
+            Err(report) => replacements.push((line.clone(), mark_failed(line, report))),
+        }
+    }
+    if !replacements.is_empty() {
+        // Re-read so anything appended during the fetches survives the rewrite
+        let current = std::fs::read_to_string(inbox)?;
+        let inbox_root = if inbox.starts_with(&root) { root.clone() } else { inbox.parent().unwrap_or(inbox).canonicalize()? };
+        vault::write_atomic(&inbox_root, inbox, rewrite_lines(&current, &replacements).as_bytes())?;
+    }
+    Ok(results)
+}
+
+fn modified(inbox: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(inbox).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Polls the inbox rather than relying on filesystem events, since sync tools often replace
+/// the file wholesale. Runs a single pass when `once` is set.
+pub async fn watch(inbox: &Path, config: &Config, once: bool) -> Result<()> {
+    info!("Watching {}", inbox.display());
+    let mut state = WatchState::default();
+    let mut interval = tokio::time::interval(Duration::from_secs(config.watch.interval_secs.max(1)));
+    loop {
+        interval.tick().await;
+        let current = modified(inbox);
+        let retry_due = state.retry_at.values().any(|at| *at <= Instant::now());
+        if current.is_some() && (current != state.modified || retry_due) {
+            // Recorded before reading, so lines appended while URLs are fetched trigger another pass
+            state.modified = current;
+            match process_inbox(inbox, config, &mut state).await {
+                Ok(results) if !results.is_empty() => {
+                    let created = results.iter().filter(|result| result.outcome.is_ok()).count();
+                    info!("Processed {} inbox URLs, {} created", results.len(), created);
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Failed to process inbox {}: {}", inbox.display(), e),
+            }
+        }
+        if once {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{fixture, mock_config};
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_pending_lines_skip_done_and_failed() {
+        let content = "# Inbox\n- [ ] https://a.example/1\n- [x] https://a.example/2\nsee https://a.example/3).\nhttps://a.example/4 (obsidian-link failed: no_matching_rule)\n[[Already Linked]]\n";
+        let urls = pending_lines(content).into_iter().map(|(_, url)| url).collect::<Vec<_>>();
+        assert_eq!(urls, vec!["https://a.example/1", "https://a.example/3"]);
+
+        let content = "see https://en.wikipedia.org/wiki/Rust_(programming_language).\n(https://a.example/x?y=1)\n- [ ] [Talk](https://a.example/talk)\n";
+        let urls = pending_lines(content).into_iter().map(|(_, url)| url).collect::<Vec<_>>();
+        assert_eq!(urls, vec!["https://en.wikipedia.org/wiki/Rust_(programming_language)", "https://a.example/x?y=1", "https://a.example/talk"]);
+    }
+
+    #[test]
+    fn test_mark_done_styles() {
+        let root = Path::new("/vault");
+        let path = Path::new("/vault/youtube/Video.md");
+        let url = "https://youtu.be/x";
+        assert_eq!(mark_done("- [ ] https://youtu.be/x", url, path, root, MarkStyle::Wikilink), "- [x] [[youtube/Video]]");
+        assert_eq!(mark_done("https://youtu.be/x", url, path, root, MarkStyle::Wikilink), "[[youtube/Video]]");
+        assert_eq!(mark_done("  https://youtu.be/x", url, path, root, MarkStyle::Check), "  - [x] https://youtu.be/x");
+        assert_eq!(mark_done("https://youtu.be/x", url, path, root, MarkStyle::Comment), "# https://youtu.be/x -> /vault/youtube/Video.md");
+        assert_eq!(mark_done("- [ ] [A video](https://youtu.be/x) later", url, path, root, MarkStyle::Wikilink), "- [x] [[youtube/Video|A video]] later");
+    }
+
+    #[tokio::test]
+    async fn test_process_inbox_marks_lines_and_keeps_the_rest() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/videos"))
+            .and(query_param("id", "missing0000"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(fixture("youtube_empty.json"), "application/json"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/videos"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(fixture("youtube_video.json"), "application/json"))
+            .mount(&server)
+            .await;
+        let vault = tempfile::tempdir().unwrap();
+        let config = mock_config(&server, vault.path());
+        let inbox = vault.path().canonicalize().unwrap().join("Inbox.md");
+        std::fs::write(&inbox, "# Inbox\n- [ ] https://www.youtube.com/watch?v=dQw4w9WgXcQ\n- [ ] https://www.youtube.com/watch?v=missing0000\nnotes stay\n").unwrap();
+
+        let results = process_inbox(&inbox, &config, &mut WatchState::default()).await.unwrap();
+        assert_eq!(results.len(), 2);
+        let content = std::fs::read_to_string(&inbox).unwrap();
+        let lines = content.lines().collect::<Vec<_>>();
+        assert!(lines[1].starts_with("- [x] [["), "got: {}", content);
+        assert!(lines[2].ends_with("(obsidian-link failed: metadata_missing)"));
+        assert_eq!(lines[3], "notes stay");
+        assert!(process_inbox(&inbox, &config, &mut WatchState::default()).await.unwrap().is_empty());
+    }
+}
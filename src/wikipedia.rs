@@ -0,0 +1,234 @@
+use log::{debug, warn};
+use regex::Regex;
+use serde_json::Value;
+use eyre::{eyre, Result};
+use lazy_static::lazy_static;
+use percent_encoding::percent_decode_str;
+use reqwest::Url;
+
+use crate::error::Error;
+use crate::html::html_to_text;
+use crate::http::HttpClient;
+use crate::{Config, Note};
+
+lazy_static! {
+    static ref WIKIPEDIA_REGEX: Regex = Regex::new(r"^https?://([a-z0-9-]+)(?:\.m)?\.wikipedia\.org/wiki/([^?#]+)").expect("valid regex");
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Section {
+    pub level: usize,
+    pub title: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct WikipediaArticle {
+    pub title: String,
+    pub description: String,
+    pub extract: String,
+    pub thumbnail: String,
+    pub language: String,
+    pub last_modified: String,
+    pub url: String,
+    pub sections: Vec<Section>,
+    pub categories: Vec<String>,
+}
+
+/// The language and the decoded article title of a Wikipedia URL.
+pub fn parse_wikipedia_url(url: &str) -> Result<(String, String)> {
+    debug!("parse_wikipedia_url: url={}", url);
+    let caps = WIKIPEDIA_REGEX.captures(url).ok_or_else(|| eyre!("Not a Wikipedia article URL: {}", url))?;
+    Ok((caps[1].to_string(), percent_decode_str(&caps[2]).decode_utf8_lossy().into_owned()))
+}
+
+fn summary_url(base: &Url, title: &str) -> Url {
+    let mut url = base.clone();
+    // A title such as "AC/DC" is a single path segment
+    url.path_segments_mut().expect("http URLs have a path").extend(["api", "rest_v1", "page", "summary", title]);
+    url
+}
+
+fn api_url(base: &Url, params: &[(&str, &str)]) -> Url {
+    let mut url = base.join("/w/api.php").expect("valid path");
+    url.query_pairs_mut().extend_pairs(params);
+    url
+}
+
+async fn get_json(client: &HttpClient, url: &str) -> Result<Value> {
+    debug!("get_json: url={}", url);
+    let response = client.get_cached("wikipedia", url).await?;
+    if !response.status().is_success() {
+        return Err(Error::http(url, response.status()).into());
+    }
+    client.json::<Value>(response).await
+}
+
+pub fn parse_summary(summary: &Value) -> Result<WikipediaArticle> {
+    if summary["type"].as_str() == Some("disambiguation") {
+        warn!("{} is a disambiguation page", summary["title"]);
+    }
+    let title = summary["title"].as_str().ok_or_else(|| eyre!("Wikipedia summary has no title"))?;
+    Ok(WikipediaArticle {
+        title: html_to_text(summary["displaytitle"].as_str().unwrap_or(title)),
+        description: summary["description"].as_str().unwrap_or_default().to_string(),
+        extract: summary["extract"].as_str().unwrap_or_default().to_string(),
+        thumbnail: summary["thumbnail"]["source"].as_str().unwrap_or_default().to_string(),
+        language: summary["lang"].as_str().unwrap_or_default().to_string(),
+        last_modified: summary["timestamp"].as_str().unwrap_or_default().to_string(),
+        url: summary["content_urls"]["desktop"]["page"].as_str().unwrap_or_default().to_string(),
+        ..WikipediaArticle::default()
+    })
+}
+
+pub fn parse_sections(parse: &Value) -> Vec<Section> {
+    parse["parse"]["sections"].as_array().map_or_else(Vec::new, |sections| {
+        sections.iter().map(|section| Section {
+            level: section["toclevel"].as_u64().unwrap_or(1) as usize,
+            title: html_to_text(section["line"].as_str().unwrap_or_default()),
+        }).collect()
+    })
+}
+
+pub fn parse_categories(query: &Value) -> Vec<String> {
+    let mut categories = Vec::new();
+    if let Some(pages) = query["query"]["pages"].as_object() {
+        for page in pages.values() {
+            for category in page["categories"].as_array().into_iter().flatten() {
+                if let Some(title) = category["title"].as_str() {
+                    let name = title.split_once(':').map(|(_, name)| name).unwrap_or(title);
+                    categories.push(name.to_string());
+                }
+            }
+        }
+    }
+    categories
+}
+
+pub async fn fetch_wikipedia_article(client: &HttpClient, url: &str, outline: bool) -> Result<WikipediaArticle> {
+    debug!("fetch_wikipedia_article: url={} outline={}", url, outline);
+    let (language, title) = parse_wikipedia_url(url)?;
+    let base = Url::parse(&format!("https://{}.wikipedia.org", language))?;
+    let summary = get_json(client, summary_url(&base, &title).as_str()).await?;
+    let mut article = parse_summary(&summary)?;
+    if article.language.is_empty() {
+        article.language = language;
+    }
+    let canonical = summary["titles"]["canonical"].as_str().unwrap_or(&title).to_string();
+
+    if outline {
+        let sections = api_url(&base, &[("action", "parse"), ("page", &canonical), ("prop", "sections"), ("format", "json"), ("redirects", "1")]);
+        match get_json(client, sections.as_str()).await {
+            Ok(parse) => article.sections = parse_sections(&parse),
+            Err(e) => warn!("Failed to fetch section outline for {}: {}", url, e),
+        }
+    }
+    let categories = api_url(&base, &[
+        ("action", "query"), ("prop", "categories"), ("clshow", "!hidden"), ("cllimit", "max"),
+        ("format", "json"), ("redirects", "1"), ("titles", &canonical),
+    ]);
+    match get_json(client, categories.as_str()).await {
+        Ok(query) => article.categories = parse_categories(&query),
+        Err(e) => warn!("Failed to fetch categories for {}: {}", url, e),
+    }
+    Ok(article)
+}
+
+fn render_description(article: &WikipediaArticle) -> String {
+    let mut description = String::new();
+    if !article.description.is_empty() {
+        description += &format!("*{}*\n\n", article.description);
+    }
+    description += &article.extract;
+    description += &format!("\n\n**Language:** {}", article.language);
+    if !article.last_modified.is_empty() {
+        description += &format!("\n**Last modified:** {}", article.last_modified);
+    }
+    if !article.sections.is_empty() {
+        description += "\n\n## Outline";
+        for section in &article.sections {
+            description += &format!("\n\n{} {}", "#".repeat((section.level + 2).min(6)), section.title);
+        }
+    }
+    description
+}
+
+pub async fn handle_wikipedia_url(url: &str, folder: &str, outline: bool, config: &Config) -> Result<Note> {
+    debug!("handle_wikipedia_url: url={} folder={} outline={} config={:?}", url, folder, outline, config);
+    let article = fetch_wikipedia_article(config.http()?, url, outline).await?;
+    let description = render_description(&article);
+    let embed_code = if article.thumbnail.is_empty() {
+        String::new()
+    } else {
+        format!("![{}]({})", article.title, article.thumbnail)
+    };
+    Ok(Note {
+        title: article.title,
+        description,
+        embed_code,
+        url: url.to_string(),
+        folder: folder.to_string(),
+        author: "Wikipedia".to_string(),
+        tags: article.categories,
+        ..Note::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_wikipedia_url() {
+        assert_eq!(
+            parse_wikipedia_url("https://en.wikipedia.org/wiki/Rust_(programming_language)#History").expect("url"),
+            ("en".to_string(), "Rust_(programming_language)".to_string())
+        );
+        assert_eq!(
+            parse_wikipedia_url("https://de.m.wikipedia.org/wiki/Krabbe").expect("url"),
+            ("de".to_string(), "Krabbe".to_string())
+        );
+        let (_, title) = parse_wikipedia_url("https://en.wikipedia.org/wiki/AT%26T").expect("url");
+        assert_eq!(title, "AT&T");
+        let base = Url::parse("https://en.wikipedia.org").unwrap();
+        assert_eq!(summary_url(&base, "AC/DC").as_str(), "https://en.wikipedia.org/api/rest_v1/page/summary/AC%2FDC");
+        assert_eq!(api_url(&base, &[("titles", "AT&T")]).as_str(), "https://en.wikipedia.org/w/api.php?titles=AT%26T");
+        assert!(parse_wikipedia_url("https://wikimedia.org/wiki/Foo").is_err());
+    }
+
+    #[test]
+    fn test_parse_summary() {
+        let summary = json!({
+            "type": "standard",
+            "title": "Rust (programming language)",
+            "displaytitle": "<span class=\"mw-page-title-main\">Rust (programming language)</span>",
+            "description": "General-purpose programming language",
+            "extract": "Rust is a general-purpose programming language.",
+            "thumbnail": {"source": "https://upload.wikimedia.org/rust.png"},
+            "lang": "en",
+            "timestamp": "2024-01-01T00:00:00Z",
+            "content_urls": {"desktop": {"page": "https://en.wikipedia.org/wiki/Rust_(programming_language)"}}
+        });
+        let article = parse_summary(&summary).expect("article");
+        assert_eq!(article.title, "Rust (programming language)");
+        assert_eq!(article.thumbnail, "https://upload.wikimedia.org/rust.png");
+        assert_eq!(article.last_modified, "2024-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn test_parse_sections_and_categories() {
+        let parse = json!({"parse": {"sections": [
+            {"toclevel": 1, "line": "History"},
+            {"toclevel": 2, "line": "<i>Early</i> years"}
+        ]}});
+        assert_eq!(parse_sections(&parse), vec![
+            Section { level: 1, title: "History".into() },
+            Section { level: 2, title: "Early years".into() },
+        ]);
+        let query = json!({"query": {"pages": {"1": {"categories": [
+            {"ns": 14, "title": "Category:Programming languages"},
+            {"ns": 14, "title": "Category:Mozilla"}
+        ]}}}});
+        assert_eq!(parse_categories(&query), vec!["Programming languages".to_string(), "Mozilla".to_string()]);
+    }
+}